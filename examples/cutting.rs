@@ -0,0 +1,94 @@
+//! Shows how to mow and burn grass at runtime and how it grows back
+use bevy::{math::Vec3Swizzles, prelude::*, render::primitives::Aabb};
+use warbler_grass::{
+    cutting::{CutGrass, CutMode, CutShape, GrassRegrowth},
+    prelude::*,
+};
+mod helper;
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(WarblersPlugin)
+        // Just a helper plugin for spawning a camera
+        // As in all examples, you can use the wasd keys for movement and qe for rotation
+        .add_plugin(helper::SimpleCamera)
+        .add_startup_system(setup)
+        .add_system(mow)
+        .add_system(burn)
+        .run();
+}
+#[derive(Component)]
+struct Lawnmower;
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let density_map = asset_server.load("grass_density_map.png");
+    commands.spawn((
+        WarblersBundle {
+            density_map: DensityMap {
+                density_map,
+                density: 2.,
+                ..default()
+            },
+            height: WarblerHeight::Uniform(2.),
+            aabb: Aabb::from_min_max(Vec3::ZERO, Vec3::new(100., 1., 100.)),
+            ..default()
+        },
+        // the cut grass grows back within 20 seconds
+        GrassRegrowth { duration: 20. },
+    ));
+    // the lawnmower cuts the grass it drives over
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(shape::Box::new(2., 1., 2.).into()),
+            material: materials.add(Color::RED.into()),
+            ..default()
+        },
+        Lawnmower,
+    ));
+    commands.spawn(DirectionalLightBundle {
+        transform: Transform::from_xyz(0., 10., 0.).looking_at(Vec3::new(1., 0., 1.), Vec3::Y),
+        ..default()
+    });
+}
+fn mow(
+    time: Res<Time>,
+    mut lawnmowers: Query<&mut Transform, With<Lawnmower>>,
+    mut cuts: EventWriter<CutGrass>,
+) {
+    // drives in lines over the lawn
+    let t = time.elapsed_seconds() * 10.;
+    let row = (t / 100.).floor();
+    let along = t % 100.;
+    let x = if row % 2. == 0. { along } else { 100. - along };
+    let z = (row * 2.) % 100.;
+    for mut transform in &mut lawnmowers {
+        transform.translation = Vec3::new(x, 0.5, z);
+        cuts.send(CutGrass {
+            shape: CutShape::Box {
+                min: transform.translation.xz() - 1.,
+                max: transform.translation.xz() + 1.,
+            },
+            mode: CutMode::Trim(0.2),
+        });
+    }
+}
+fn burn(input: Res<Input<KeyCode>>, time: Res<Time>, mut cuts: EventWriter<CutGrass>) {
+    if !input.just_pressed(KeyCode::Space) {
+        return;
+    }
+    // burns the grass in a circle somewhere on the lawn
+    let angle = time.elapsed_seconds() * 2.3;
+    cuts.send(CutGrass {
+        shape: CutShape::Circle {
+            center: Vec2::new(50. + angle.cos() * 30., 50. + angle.sin() * 30.),
+            radius: 8.,
+        },
+        mode: CutMode::Remove,
+    });
+}
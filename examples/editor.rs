@@ -10,6 +10,7 @@ fn main() {
         .add_plugins(DefaultPlugins)
         .add_plugin(WarblersPlugin)
         .add_startup_system(setup_camera)
+        .add_startup_system(helper::setup_light)
         // As in all examples, you can use the wasd keys for movement and qe for rotation
         .add_system(helper::camera_movement)
         // enable the editor by adding the plugin
@@ -31,13 +32,14 @@ fn setup_grass(
 ) {
     let height_map = asset_server.load("grass_height_map.png");
 
-    let height_map = HeightMap { height_map };
+    let height_map = HeightMap::from(height_map);
     let density_map_texture = asset_server.load("grass_density_map.png");
     let heights_map_texture = asset_server.load("grass_heights_map.png");
 
     let density_map = DensityMap {
         density_map: density_map_texture.clone(),
         density: 2.,
+        ..default()
     };
     let quad_handle = meshes.add(Mesh::from(shape::Quad::new(Vec2::new(10., 10.))));
     let material_handle = materials.add(StandardMaterial {
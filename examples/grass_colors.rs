@@ -15,12 +15,13 @@ fn main() {
 fn setup_grass(mut commands: Commands, asset_server: Res<AssetServer>) {
     let height_map = asset_server.load("grass_height_map.png");
 
-    let height_map = HeightMap { height_map };
+    let height_map = HeightMap::from(height_map);
     let density_map = asset_server.load("grass_density_map.png");
 
     let density_map = DensityMap {
         density_map,
         density: 2.,
+        ..default()
     };
     commands.spawn((WarblersBundle {
         density_map,
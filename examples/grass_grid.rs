@@ -0,0 +1,54 @@
+//! Demonstrates how to spawn a large area of grass which is split into chunks automatically
+use bevy::{diagnostic::LogDiagnosticsPlugin, prelude::*};
+use warbler_grass::{diagnostic::WarblerDiagnosticsPlugin, prelude::*};
+mod helper;
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(WarblersPlugin)
+        // Just a helper plugin for spawning a camera
+        // As in all examples, you can use the wasd keys for movement and qe for rotation
+        .add_plugin(helper::SimpleCamera)
+        // Let's log the amount of blades rendered, to see the effect of the culling
+        .add_plugin(WarblerDiagnosticsPlugin)
+        .add_plugin(LogDiagnosticsPlugin::default())
+        .add_startup_system(setup_grass_grid)
+        .add_system(cast_shadows)
+        .run();
+}
+fn setup_grass_grid(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn(WarblersGridBundle {
+        grid: GrassGrid {
+            density_map: DensityMap {
+                density_map: asset_server.load("grass_density_map.png"),
+                density: 2.,
+                ..default()
+            },
+            height_map: asset_server.load::<Image, _>("grass_height_map.png").into(),
+            height: WarblerHeight::Uniform(2.),
+            // the whole area is 500x500 and the height map reaches up to 10
+            size: Vec3::new(500., 10., 500.),
+            // which is split into 8x8 chunks
+            chunks: UVec2::new(8, 8),
+        },
+        spatial: SpatialBundle {
+            transform: Transform::from_xyz(-250., -10., -250.),
+            ..default()
+        },
+        ..default()
+    });
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            shadows_enabled: true,
+            ..default()
+        },
+        transform: Transform::from_rotation(Quat::from_rotation_x(-1.)),
+        ..default()
+    });
+}
+/// The chunks are spawned once the maps are loaded, so we add other components to them afterwards
+fn cast_shadows(mut commands: Commands, chunks: Query<Entity, Added<GrassGridChunk>>) {
+    for chunk in &chunks {
+        commands.entity(chunk).insert(GrassCastShadows);
+    }
+}
@@ -42,12 +42,13 @@ fn setup_grass(
 
     let height_map = asset_server.load("grass_height_map.png");
 
-    let height_map = HeightMap { height_map };
+    let height_map = HeightMap::from(height_map);
     let density_map = asset_server.load("grass_density_map.png");
 
     let density_map = DensityMap {
         density_map,
         density: 1.,
+        ..default()
     };
     // simple add the grass mesh in the bundle, instead of using the default
     commands.spawn(WarblersBundle {
@@ -5,6 +5,7 @@ pub struct SimpleCamera;
 impl Plugin for SimpleCamera {
     fn build(&self, app: &mut App) {
         app.add_startup_system(setup_camera)
+            .add_startup_system(setup_light)
             .add_system(camera_movement);
     }
 }
@@ -15,6 +16,13 @@ fn setup_camera(mut commands: Commands) {
         ..default()
     },));
 }
+/// Grass is lit by the lights of the scene, so we spawn a sun
+pub fn setup_light(mut commands: Commands) {
+    commands.spawn(DirectionalLightBundle {
+        transform: Transform::from_rotation(Quat::from_euler(EulerRot::XYZ, -1.0, 0.4, 0.)),
+        ..default()
+    });
+}
 pub fn camera_movement(input: Res<Input<KeyCode>>, mut query: Query<&mut Transform, With<Camera>>) {
     for mut transform in &mut query {
         let move_speed = 0.6;
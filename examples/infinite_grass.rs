@@ -0,0 +1,37 @@
+//! Demonstrates how to spawn procedurally generated grass around the camera.
+//! Move around with the wasd keys, the grass never ends
+use bevy::prelude::*;
+use warbler_grass::{
+    infinite::{GrassGenerator, InfiniteGrass, InfiniteGrassPlugin, InfiniteGrassTarget},
+    prelude::*,
+};
+mod helper;
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(WarblersPlugin)
+        // keeps chunks of grass around the entity with the InfiniteGrassTarget component
+        .add_plugin(InfiniteGrassPlugin)
+        .insert_resource(InfiniteGrass {
+            // the density and height of the grass are generated from noise
+            generator: GrassGenerator::Noise {
+                seed: 42,
+                scale: 80.,
+            },
+            chunk_size: 25.,
+            radius: 6,
+            max_height: 8.,
+            ..default()
+        })
+        // Just a helper plugin for spawning a camera
+        // As in all examples, you can use the wasd keys for movement and qe for rotation
+        .add_plugin(helper::SimpleCamera)
+        .add_system(track_camera)
+        .run();
+}
+/// The helper spawns the camera, so we let the grass follow it once it's there
+fn track_camera(mut commands: Commands, cameras: Query<Entity, Added<Camera>>) {
+    for camera in &cameras {
+        commands.entity(camera).insert(InfiniteGrassTarget);
+    }
+}
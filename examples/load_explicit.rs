@@ -20,7 +20,6 @@ fn setup_grass(mut commands: Commands) {
     let radius = 6.;
     let speed = 3.;
     let positions = (0..count)
-        .into_iter()
         // the part that makes our spiral
         .map(|i| {
             let f = i as f32 / count as f32 * speed;
@@ -34,15 +33,32 @@ fn setup_grass(mut commands: Commands) {
         // collect the positions to a vector
         .collect();
 
+    // Each blade can also have its own height, width, rotation and color
+    // Here the blades grow and get more yellow towards the end of the spiral
+    let blades = (0..count)
+        .map(|i| {
+            let f = i as f32 / count as f32;
+            BladeAttributes {
+                height: 1. + f * 2.,
+                width: 1. + f,
+                color: Color::rgb(1. + f * 2., 1., 1.),
+                ..default()
+            }
+        })
+        .collect();
+
     // If we want to use explicit positions, we need to use
     // the WarblersExplicitBundle instead of the normal WarblersBundle
-    commands.spawn(WarblersExplicitBundle {
-        grass: Grass {
-            // the positions of the grass blades
-            positions,
-            // the height of the blades
-            height: 2.,
+    commands.spawn((
+        WarblersExplicitBundle {
+            grass: Grass {
+                // the positions of the grass blades
+                positions,
+                // the height of the blades without their own attributes
+                height: 2.,
+            },
+            ..default()
         },
-        ..default()
-    });
+        GrassInstanceData { blades },
+    ));
 }
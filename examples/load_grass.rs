@@ -18,7 +18,12 @@ fn setup_grass(mut commands: Commands, asset_server: Res<AssetServer>) {
     // Loading the height map from an image
     let height_map = asset_server.load("grass_height_map.png");
     // Constructing the height map struct
-    let height_map = HeightMap { height_map };
+    let height_map = HeightMap {
+        height_map,
+        // Interpolates between the pixels of the height map, which avoids steps in the terrain
+        filter: HeightMapFilter::Bilinear,
+        ..default()
+    };
 
     // Loading the density map from an image
     let density_map = asset_server.load("grass_density_map.png");
@@ -28,6 +33,9 @@ fn setup_grass(mut commands: Commands, asset_server: Res<AssetServer>) {
         // The density corresponds to how dense a dense area is supposed to be
         // Be careful with this parameter since the blade count grows fast
         density: 2.,
+        // Scatters the blades randomly instead of on a grid, which looks more organic
+        // The seed makes sure the blades are always placed the same way
+        scattering: Scattering::PoissonDisk { seed: 42 },
     };
     // spawns the "chunk" entity
     commands.spawn(WarblersBundle {
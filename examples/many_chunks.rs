@@ -11,6 +11,14 @@ fn main() {
         // Just a helper plugin for spawning a camera
         // As in all examples, you can use the wasd keys for movement and qe for rotation
         .add_plugin(helper::SimpleCamera)
+        // Distant chunks get thinned out and aren't drawn at all after some distance
+        .insert_resource(GrassConfiguration {
+            lod: Some(GrassLod {
+                near: 100.,
+                far: 300.,
+            }),
+            ..default()
+        })
         // Let's also log the amount of blades rendered
         .add_plugin(WarblerDiagnosticsPlugin)
         .add_plugin(LogDiagnosticsPlugin::default())
@@ -23,10 +31,11 @@ fn setup_grass_chunks(mut commands: Commands, asset_server: Res<AssetServer>) {
     let density_map = DensityMap {
         density_map: density_map_handle.clone(),
         density: 2.,
+        ..default()
     };
     let height_map = asset_server.load("grass_height_map.png");
 
-    let height_map = HeightMap { height_map };
+    let height_map = HeightMap::from(height_map);
     // each chunk is 50x50
     let (chunk_width, chunk_height) = (50., 50.);
     // spawns a 20x20 grid of chunks
@@ -42,24 +51,35 @@ fn setup_grass_chunks(mut commands: Commands, asset_server: Res<AssetServer>) {
             ((chunk % chunk_width as i32) as f32 / chunk_width) + 0.5,
             0.,
         );
-        commands.spawn(WarblersBundle {
-            // we could use seperate density maps for each one
-            density_map: density_map.clone(),
-            // or seperate height maps if we wanted to
-            height_map: height_map.clone(),
-            height: WarblerHeight::Texture(density_map_handle.clone()),
-            // the aabb defined the dimensions of the box the chunk lives in
-            aabb: Aabb::from_min_max(Vec3::ZERO, Vec3::new(chunk_width, 2., chunk_height)),
-            grass_color: GrassColor {
-                main_color: color.clone(),
-                bottom_color: color * 0.4,
-            },
+        let mut entity = commands.spawn((
+            WarblersBundle {
+                // we could use seperate density maps for each one
+                density_map: density_map.clone(),
+                // or seperate height maps if we wanted to
+                height_map: height_map.clone(),
+                height: WarblerHeight::Texture(density_map_handle.clone()),
+                // the aabb defined the dimensions of the box the chunk lives in
+                aabb: Aabb::from_min_max(Vec3::ZERO, Vec3::new(chunk_width, 2., chunk_height)),
+                grass_color: GrassColor {
+                    main_color: color,
+                    bottom_color: color * 0.4,
+                },
 
-            spatial: SpatialBundle {
-                transform: Transform::from_translation(offset),
+                spatial: SpatialBundle {
+                    transform: Transform::from_translation(offset),
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        });
+            // all chunks share the same mesh and maps, so they can be drawn together
+            BatchedGrass,
+        ));
+        // the first row of chunks can be seen from further away than the others
+        if offset.x == 0. {
+            entity.insert(GrassLod {
+                near: 300.,
+                far: 900.,
+            });
+        }
     }
 }
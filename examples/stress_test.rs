@@ -15,14 +15,19 @@ fn main() {
         .add_plugin(helper::SimpleCamera)
         // creates our grass
         .add_startup_system(setup_grass)
-        // more wind
+        // more wind, which comes in gusts
         .insert_resource(GrassConfiguration {
             wind: Vec2::new(2., 2.),
+            wind_model: WindModel {
+                gust_strength: 0.8,
+                ..default()
+            },
             ..default()
         })
         // Let's also log the amount of blades rendered
         // Since we spawn all grass in one huge chunk all blades get rendered
-        // as long as one is on the screen (normally you'd devide the area into chunks)
+        // as long as one is on the screen (normally you'd devide the area into chunks).
+        // Blades culled on the gpu are logged separately
         .add_plugin(WarblerDiagnosticsPlugin)
         .add_plugin(LogDiagnosticsPlugin::default())
         .run();
@@ -31,9 +36,7 @@ fn main() {
 fn setup_grass(mut commands: Commands, asset_server: Res<AssetServer>) {
     // load the image used for the height map
     let height_map_image = asset_server.load("grass_height_map.png");
-    let height_map = HeightMap {
-        height_map: height_map_image,
-    };
+    let height_map = HeightMap::from(height_map_image);
 
     // load the image used for the density map
     let density_map_image = asset_server.load("grass_density_map.png");
@@ -41,20 +44,27 @@ fn setup_grass(mut commands: Commands, asset_server: Res<AssetServer>) {
         density_map: density_map_image,
         // The density defines how many blades in a dense area spawns.
         density: 4.,
+        ..default()
     };
     // spawn the entity rendering out large grass chunk
-    commands.spawn(WarblersBundle {
-        density_map,
-        height_map,
-        height: WarblerHeight::Uniform(5.),
-        // Let's make a large chunk
-        // With our density map we spawn around 10 million blades on this area
-        aabb: Aabb::from_min_max(Vec3::ZERO, Vec3::new(1000., 0., 1000.)),
-        spatial: SpatialBundle {
-            // translate the chunk so we are in a nice middle place
-            transform: Transform::from_xyz(-480., -5., -480.),
+    commands.spawn((
+        WarblersBundle {
+            density_map,
+            height_map,
+            height: WarblerHeight::Uniform(5.),
+            // Let's make a large chunk
+            // With our density map we spawn around 10 million blades on this area
+            aabb: Aabb::from_min_max(Vec3::ZERO, Vec3::new(1000., 0., 1000.)),
+            spatial: SpatialBundle {
+                // translate the chunk so we are in a nice middle place
+                transform: Transform::from_xyz(-480., -5., -480.),
+                ..default()
+            },
             ..default()
         },
-        ..default()
-    });
+        // only the blades in front of the camera are drawn
+        GpuCulling {
+            max_distance: Some(500.),
+        },
+    ));
 }
@@ -0,0 +1,73 @@
+//! Shows how objects bend the grass around them and leave a trail behind
+use bevy::{prelude::*, render::primitives::Aabb};
+use warbler_grass::{
+    prelude::*,
+    trample::{GrassInteractor, TrampleMask},
+};
+mod helper;
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(WarblersPlugin)
+        // Just a helper plugin for spawning a camera
+        // As in all examples, you can use the wasd keys for movement and qe for rotation
+        .add_plugin(helper::SimpleCamera)
+        .add_startup_system(setup)
+        .add_system(roll_ball)
+        .run();
+}
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let density_map = asset_server.load("grass_density_map.png");
+    // the trample mask remembers where the ball rolled
+    let mut trample_mask = TrampleMask::new(&mut images, UVec2::splat(128));
+    // the trail needs a few seconds to vanish
+    trample_mask.recovery_time = 5.;
+    commands.spawn((
+        WarblersBundle {
+            density_map: DensityMap {
+                density_map,
+                density: 2.,
+                ..default()
+            },
+            height: WarblerHeight::Uniform(2.),
+            aabb: Aabb::from_min_max(Vec3::ZERO, Vec3::new(100., 1., 100.)),
+            ..default()
+        },
+        trample_mask,
+    ));
+    // the ball bends the grass around it
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(
+                shape::UVSphere {
+                    radius: 1.5,
+                    ..default()
+                }
+                .into(),
+            ),
+            material: materials.add(Color::ORANGE_RED.into()),
+            ..default()
+        },
+        GrassInteractor {
+            radius: 3.,
+            strength: 2.,
+        },
+    ));
+    commands.spawn(DirectionalLightBundle {
+        transform: Transform::from_xyz(0., 10., 0.).looking_at(Vec3::new(1., 0., 1.), Vec3::Y),
+        ..default()
+    });
+}
+fn roll_ball(time: Res<Time>, mut balls: Query<&mut Transform, With<GrassInteractor>>) {
+    let angle = time.elapsed_seconds() * 0.4;
+    for mut transform in &mut balls {
+        transform.translation = Vec3::new(50. + angle.cos() * 30., 1.5, 50. + angle.sin() * 20.);
+    }
+}
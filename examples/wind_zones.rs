@@ -0,0 +1,73 @@
+//! Shows how local wind sources push the grass around them.
+//! Press space to send an impulse through the grass, like an explosion
+use bevy::{prelude::*, render::primitives::Aabb};
+use warbler_grass::{
+    prelude::*,
+    wind::{WindImpulse, WindZone},
+};
+mod helper;
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(WarblersPlugin)
+        // Just a helper plugin for spawning a camera
+        // As in all examples, you can use the wasd keys for movement and qe for rotation
+        .add_plugin(helper::SimpleCamera)
+        // only a light breeze, so the local wind is easy to see
+        .insert_resource(GrassConfiguration {
+            wind: Vec2::new(0.3, 0.3),
+            ..default()
+        })
+        .add_startup_system(setup_grass)
+        .add_system(move_vortex)
+        .add_system(send_impulse)
+        .run();
+}
+fn setup_grass(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let density_map = asset_server.load("grass_density_map.png");
+    commands.spawn(WarblersBundle {
+        density_map: DensityMap {
+            density_map,
+            density: 2.,
+            ..default()
+        },
+        height: WarblerHeight::Uniform(2.),
+        aabb: Aabb::from_min_max(Vec3::ZERO, Vec3::new(100., 1., 100.)),
+        ..default()
+    });
+    // a fan blowing the grass in one direction
+    commands.spawn((
+        WindZone::Directional {
+            half_extents: Vec3::new(15., 5., 15.),
+            wind: Vec2::new(3., 0.),
+        },
+        SpatialBundle::from_transform(Transform::from_xyz(25., 0., 25.)),
+    ));
+    // a vortex moving over the grass
+    commands.spawn((
+        WindZone::Vortex {
+            radius: 12.,
+            strength: 2.5,
+        },
+        SpatialBundle::default(),
+    ));
+}
+fn move_vortex(time: Res<Time>, mut zones: Query<(&WindZone, &mut Transform)>) {
+    let angle = time.elapsed_seconds() * 0.5;
+    for (zone, mut transform) in &mut zones {
+        if let WindZone::Vortex { .. } = zone {
+            transform.translation = Vec3::new(65. + angle.cos() * 15., 0., 65. + angle.sin() * 15.);
+        }
+    }
+}
+fn send_impulse(keys: Res<Input<KeyCode>>, mut impulses: EventWriter<WindImpulse>) {
+    if keys.just_pressed(KeyCode::Space) {
+        impulses.send(WindImpulse {
+            position: Vec3::new(50., 0., 50.),
+            radius: 30.,
+            strength: 5.,
+            duration: 1.5,
+        });
+    }
+}
@@ -1,7 +1,7 @@
 use bevy::{
     asset::Handle,
     ecs::{bundle::Bundle, component::Component, query::QueryItem},
-    math::Vec3,
+    math::{UVec2, Vec3},
     prelude::Color,
     render::{
         extract_component::ExtractComponent, mesh::Mesh, prelude::SpatialBundle, primitives::Aabb,
@@ -54,6 +54,76 @@ impl Default for WarblersBundle {
         }
     }
 }
+/// This [`Bundle`] spawns a large area of grass, which is split into a grid of chunks.
+///
+/// A single chunk is always drawn completely, as long as a part of it is visible.
+/// For large areas it's better to use many smaller chunks, which this bundle does for you:
+/// Once the maps of the [`GrassGrid`] are loaded, a [`WarblersBundle`] is spawned as child for each cell of the grid.
+/// Each child gets the part of the maps lying in its cell, so the chunks fit together seamlessly.
+///
+/// The chunks are marked with the [`GrassGridChunk`] component,
+/// which can be used to add other components (like [`GrassCastShadows`]) to them.
+/// They are spawned again if the [`GrassGrid`] or one of its maps changes.
+#[derive(Bundle)]
+pub struct WarblersGridBundle {
+    /// The [`Mesh`] of the grass blades used by all chunks
+    pub grass_mesh: Handle<Mesh>,
+    /// An [`GrassGrid`] component
+    pub grid: GrassGrid,
+    /// An [`GrassColor`] component used by all chunks
+    pub grass_color: GrassColor,
+    #[bundle]
+    pub spatial: SpatialBundle,
+}
+impl Default for WarblersGridBundle {
+    fn default() -> Self {
+        Self {
+            grass_mesh: GRASS_MESH_HANDLE.typed(),
+            grid: GrassGrid::default(),
+            grass_color: GrassColor::default(),
+            spatial: Default::default(),
+        }
+    }
+}
+/// Defines the area of grass which is split into chunks by the [`WarblersGridBundle`]
+///
+/// The chunks are bounded by the pixels of the height map, so they fit together seamlessly for any resolution.
+/// Maps with another resolution than the height map are split at the pixels closest to these bounds.
+/// Only uncompressed images can be split.
+#[derive(Component, Clone)]
+pub struct GrassGrid {
+    /// The [`DensityMap`] covering the whole area
+    pub density_map: DensityMap,
+    /// The [`HeightMap`] covering the whole area
+    pub height_map: HeightMap,
+    /// The [`WarblerHeight`] of the blades.
+    ///
+    /// A height texture is split like the maps
+    pub height: WarblerHeight,
+    /// The size of the whole area.
+    ///
+    /// The y value is the maximal height of the [`HeightMap`], like the height of the [`Aabb`] of a single chunk
+    pub size: Vec3,
+    /// The amount of chunks in the x and z direction
+    pub chunks: UVec2,
+}
+impl Default for GrassGrid {
+    fn default() -> Self {
+        Self {
+            density_map: DensityMap::default(),
+            height_map: DEFAULT_IMAGE_HANDLE.typed().into(),
+            height: WarblerHeight::Uniform(1.),
+            size: Vec3::ONE,
+            chunks: UVec2::ONE,
+        }
+    }
+}
+/// Marks a chunk spawned by a [`WarblersGridBundle`]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GrassGridChunk {
+    /// The cell of the chunk in the grid
+    pub cell: UVec2,
+}
 /// The height of the grass blades
 ///
 /// Can be used in Combination with the [`WarblersBundle`] to spawn grass chunks
@@ -63,7 +133,9 @@ pub enum WarblerHeight {
     Uniform(f32),
     /// Samples the height from an [`Image`]
     ///
-    /// The [`Image`] will be scaled over the plane defined by the [`Aabb`]
+    /// The [`Image`] will be scaled over the plane defined by the [`Aabb`].
+    /// Supports the same formats as the [`HeightMap`] and is sampled using its
+    /// [`filter`](HeightMap::filter) and [`border`](HeightMap::border)
     Texture(Handle<Image>),
 }
 /// Defines the color of the grass blades
@@ -84,6 +156,72 @@ impl Default for GrassColor {
         }
     }
 }
+/// Disables the random rotation of the grass blades for a single chunk
+///
+/// Can be inserted to entities spawned with the [`WarblersBundle`] or [`WarblersExplicitBundle`].
+/// The maximal rotation of all other chunks is defined in the [`GrassConfiguration`](crate::GrassConfiguration)
+#[derive(Component, Clone, Copy, Default, Debug, ExtractComponent)]
+pub struct NoBladeRotation;
+
+/// Disables lighting for a single chunk
+///
+/// Per default, grass takes part in the lighting of the scene,
+/// so directional, point and ambient lights are applied to the blades.
+/// A chunk with this component renders the [`GrassColor`] as is instead.
+#[derive(Component, Clone, Copy, Default, Debug, ExtractComponent)]
+pub struct UnlitGrass;
+
+/// Lets a single chunk receive the shadows of directional, point and spot lights
+///
+/// Only lights with shadows enabled darken the grass.
+/// Note that shadows are only applied to lit grass, so this component has no effect
+/// on chunks with the [`UnlitGrass`] component.
+#[derive(Component, Clone, Copy, Default, Debug, ExtractComponent)]
+pub struct GrassReceiveShadows;
+
+/// Lets a single chunk cast shadows
+///
+/// The grass is drawn into the shadow maps of all lights with shadows enabled.
+/// Use the `shadow_blade_fraction` of the [`GrassConfiguration`](crate::GrassConfiguration)
+/// to only let a part of the blades cast shadows.
+#[derive(Component, Clone, Copy, Default, Debug, ExtractComponent)]
+pub struct GrassCastShadows;
+
+/// Lets a chunk be drawn together with other chunks in a single draw call
+///
+/// Chunks with this component are merged into one batch if they use the same mesh,
+/// [`HeightMap`] and height texture and have the same rendering components
+/// (like [`UnlitGrass`] or [`GrassCastShadows`]).
+/// Their colors, transforms and [`Aabb`]s may differ.
+/// This can greatly reduce the amount of draw calls in worlds with many chunks.
+///
+/// Only chunks spawned with a [`DensityMap`], which are not dithered on the gpu
+/// and have neither a [`TrampleMask`](crate::trample::TrampleMask) nor a [`GrassCutMask`](crate::cutting::GrassCutMask), can be batched.
+/// Note that a batch is drawn completely as soon as one of its chunks is visible in a view or casts a shadow into a light,
+/// unless the whole batch is beyond the far distance of the [`GrassLod`](crate::GrassLod).
+/// Hiding or showing a chunk of a batch creates the batch again, so it should be done rarely.
+/// If the device doesn't support storage buffers (e.g. WebGL2), the chunks are drawn one by one.
+#[derive(Component, Clone, Copy, Default, Debug, ExtractComponent)]
+pub struct BatchedGrass;
+
+/// Culls the single blades of a chunk on the gpu before drawing them
+///
+/// Normally, a chunk is either drawn completely or not at all, depending on whether its [`Aabb`] is visible.
+/// With this component, a compute shader additionally removes all blades outside of the view frustum
+/// and further away from the camera than `max_distance`, which helps with big chunks that are only partly visible.
+/// The amount of culled blades is reported by the [`WarblerDiagnosticsPlugin`](crate::diagnostic::WarblerDiagnosticsPlugin).
+///
+/// Only chunks spawned with a [`DensityMap`] which aren't batched can be culled.
+/// The culling only affects cameras, all blades are still drawn into shadow maps.
+/// If the device doesn't support compute shaders (e.g. WebGL2), all blades of the chunk are drawn.
+#[derive(Component, Clone, Copy, Default, Debug, ExtractComponent)]
+pub struct GpuCulling {
+    /// Blades further away from the camera than this distance are culled.
+    ///
+    /// If `None`, blades are only culled against the view frustum
+    pub max_distance: Option<f32>,
+}
+
 impl ExtractComponent for WarblerHeight {
     type Query = &'static Self;
 
@@ -163,6 +301,55 @@ impl From<&[Vec3]> for Grass {
         }
     }
 }
+/// Per blade attributes of an explicit [`Grass`] chunk
+///
+/// Can be added next to the [`Grass`] component to vary the blades of a single chunk.
+/// The n-th entry of `blades` is used for the n-th position of the [`Grass`] component.
+/// Blades without an entry use the default [`BladeAttributes`] with the height of the [`Grass`] component.
+///
+/// # Example
+/// ```rust
+/// use warbler_grass::prelude::{BladeAttributes, GrassInstanceData};
+/// use bevy::prelude::Color;
+///
+/// let blades = (0..10)
+///     .map(|i| BladeAttributes {
+///         height: 1. + i as f32 * 0.1,
+///         color: Color::rgb(1., 0.8, 0.8),
+///         ..Default::default()
+///     })
+///     .collect();
+/// let instance_data = GrassInstanceData { blades };
+/// ```
+#[derive(Component, Clone, PartialEq, Debug, Default)]
+pub struct GrassInstanceData {
+    /// The attributes of each blade, in the same order as the positions of the [`Grass`] component
+    pub blades: Vec<BladeAttributes>,
+}
+/// The attributes of a single blade of an explicit grass chunk
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BladeAttributes {
+    /// The height of the blade, replaces the height of the [`Grass`] component
+    pub height: f32,
+    /// Scales the width of the blade
+    pub width: f32,
+    /// The rotation around the y axis in radians
+    ///
+    /// Added on top of the random rotation, which can be disabled using the [`NoBladeRotation`] component
+    pub rotation: f32,
+    /// Tints the [`GrassColor`] of the chunk
+    pub color: Color,
+}
+impl Default for BladeAttributes {
+    fn default() -> Self {
+        Self {
+            height: 1.,
+            width: 1.,
+            rotation: 0.,
+            color: Color::WHITE,
+        }
+    }
+}
 /// A bundle spawning a grass chunk in the world
 ///
 /// It uses explicit positions of all grass blades to generate the them
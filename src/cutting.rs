@@ -0,0 +1,599 @@
+//! Lets gameplay cut, shorten and remove the grass in a region at runtime
+//!
+//! Sending a [`CutGrass`] event cuts the grass of all chunks inside its [`CutShape`],
+//! like a lawnmower, a fire or an explosion would.
+//! The cuts are stored in a [`GrassCutMask`] of each chunk, which is sampled by the grass shader.
+//! Only the mask is uploaded again, so neither the density map needs to be dithered again
+//! nor the positions of explicit chunks need to be changed.
+//!
+//! Chunks with the [`GrassRegrowth`] component grow back after being cut.
+//!
+//! # Example
+//! ```rust
+//! use bevy::prelude::*;
+//! use warbler_grass::cutting::{CutGrass, CutMode, CutShape, GrassRegrowth};
+//! use warbler_grass::prelude::*;
+//!
+//! fn setup(mut commands: Commands) {
+//!     // the lawn grows back within a minute
+//!     commands.spawn((WarblersBundle::default(), GrassRegrowth { duration: 60. }));
+//! }
+//! fn mow_lawn(mut cuts: EventWriter<CutGrass>) {
+//!     // cuts the blades down to a third of their height
+//!     cuts.send(CutGrass {
+//!         shape: CutShape::Circle {
+//!             center: Vec2::new(10., 10.),
+//!             radius: 2.,
+//!         },
+//!         mode: CutMode::Trim(0.3),
+//!     });
+//! }
+//! fn burn(mut cuts: EventWriter<CutGrass>) {
+//!     cuts.send(CutGrass {
+//!         shape: CutShape::Polygon(vec![
+//!             Vec2::new(0., 0.),
+//!             Vec2::new(5., 1.),
+//!             Vec2::new(3., 6.),
+//!         ]),
+//!         mode: CutMode::Remove,
+//!     });
+//! }
+//! ```
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    render::{
+        extract_component::ExtractComponent,
+        primitives::Aabb,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+    utils::HashMap,
+};
+
+use crate::bundle::{Grass, WarblerHeight};
+
+/// The amount of texels per unit of a [`GrassCutMask`] which is added to a chunk by its first cut
+pub const CUT_MASK_TEXELS_PER_UNIT: f32 = 4.;
+/// The maximal width and height of a [`GrassCutMask`] which is added to a chunk by its first cut
+pub const MAX_CUT_MASK_RESOLUTION: u32 = 1024;
+
+/// An event cutting the grass of all chunks inside the [`CutShape`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CutGrass {
+    pub shape: CutShape,
+    pub mode: CutMode,
+}
+
+/// A region on the x,z plane in world space.
+///
+/// The region reaches infinitely far up and down, so the height of the grass doesn't matter
+#[derive(Clone, Debug, PartialEq)]
+pub enum CutShape {
+    Circle {
+        center: Vec2,
+        radius: f32,
+    },
+    /// An axis aligned box between the corners
+    Box {
+        min: Vec2,
+        max: Vec2,
+    },
+    /// A polygon with the given corners, which may be concave
+    Polygon(Vec<Vec2>),
+}
+impl CutShape {
+    /// Whether the position lies inside of the shape
+    pub fn contains(&self, position: Vec2) -> bool {
+        match self {
+            CutShape::Circle { center, radius } => {
+                center.distance_squared(position) <= radius * radius
+            }
+            CutShape::Box { min, max } => position.cmpge(*min).all() && position.cmple(*max).all(),
+            CutShape::Polygon(corners) => {
+                // counts the edges crossing a ray from the position in x direction
+                let mut inside = false;
+                let mut previous = match corners.last() {
+                    Some(corner) => *corner,
+                    None => return false,
+                };
+                for corner in corners {
+                    if (corner.y > position.y) != (previous.y > position.y) {
+                        let crossing = corner.x
+                            + (position.y - corner.y) / (previous.y - corner.y)
+                                * (previous.x - corner.x);
+                        if position.x < crossing {
+                            inside = !inside;
+                        }
+                    }
+                    previous = *corner;
+                }
+                inside
+            }
+        }
+    }
+    /// The corners of the axis aligned rectangle around the shape
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        match self {
+            CutShape::Circle { center, radius } => (*center - *radius, *center + *radius),
+            CutShape::Box { min, max } => (*min, *max),
+            CutShape::Polygon(corners) => corners.iter().fold(
+                (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+                |(min, max), corner| (min.min(*corner), max.max(*corner)),
+            ),
+        }
+    }
+}
+
+/// How the blades inside a [`CutShape`] are cut
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CutMode {
+    /// Removes the blades completely, like a fire
+    Remove,
+    /// Cuts the blades down to the given fraction of their height, like a lawnmower.
+    ///
+    /// Blades which are already shorter stay untouched
+    Trim(f32),
+    /// Multiplies the height of the blades with the given factor,
+    /// so cutting the same region repeatedly shortens the grass further
+    Shorten(f32),
+}
+impl CutMode {
+    /// The remaining height and density of the grass after the cut
+    fn apply(&self, growth: Vec2) -> Vec2 {
+        let height = match self {
+            CutMode::Remove => return Vec2::ZERO,
+            CutMode::Trim(fraction) => growth.x.min(*fraction),
+            CutMode::Shorten(factor) => growth.x * factor,
+        };
+        Vec2::new(height.clamp(0., 1.), growth.y)
+    }
+}
+
+/// Stores how much of the grass of a chunk was cut.
+///
+/// The mask is stored in an `Rg32Float` texture stretched over the chunk.
+/// The red channel scales the height of the blades and the green channel is the fraction of blades which are present,
+/// so `1.` in both channels means untouched grass.
+/// Removed blades have a density of `0.`, while trimmed blades are only shortened.
+///
+/// A mask is added automatically to chunks hit by a [`CutGrass`] event.
+/// It can also be inserted beforehand, to use another resolution.
+///
+/// Chunks with a [`GrassCutMask`] are never merged into batches, see [`BatchedGrass`](crate::bundle::BatchedGrass).
+#[derive(Component, Clone, Debug, Reflect)]
+pub struct GrassCutMask {
+    pub mask: Handle<Image>,
+}
+impl GrassCutMask {
+    /// Creates a mask of untouched grass with the given resolution
+    pub fn new(images: &mut Assets<Image>, resolution: UVec2) -> Self {
+        GrassCutMask {
+            mask: images.add(mask_image(resolution)),
+        }
+    }
+}
+/// An image of untouched grass with the given resolution
+fn mask_image(resolution: UVec2) -> Image {
+    let mut pixel = [0; 8];
+    write_growth(&mut pixel, Vec2::ONE);
+    Image::new_fill(
+        Extent3d {
+            width: resolution.x.max(1),
+            height: resolution.y.max(1),
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &pixel,
+        TextureFormat::Rg32Float,
+    )
+}
+impl ExtractComponent for GrassCutMask {
+    type Query = &'static Self;
+    type Filter = ();
+    type Out = Self;
+
+    fn extract_component(item: bevy::ecs::query::QueryItem<'_, Self::Query>) -> Option<Self::Out> {
+        Some(GrassCutMask {
+            mask: item.mask.clone_weak(),
+        })
+    }
+}
+
+/// Applies the [`CutGrass`] events to the [`GrassCutMask`]s of the chunks they hit.
+///
+/// Chunks without a mask get a new one
+#[allow(clippy::type_complexity)]
+pub(crate) fn cut_grass(
+    mut commands: Commands,
+    mut events: EventReader<CutGrass>,
+    chunks: Query<
+        (
+            Entity,
+            &Aabb,
+            &GlobalTransform,
+            Option<&GrassCutMask>,
+            Option<&Grass>,
+        ),
+        With<WarblerHeight>,
+    >,
+    mut images: ResMut<Assets<Image>>,
+) {
+    // masks added this frame, which aren't inserted yet
+    let mut added_masks: HashMap<Entity, Handle<Image>> = HashMap::new();
+    for cut in events.iter() {
+        let (cut_min, cut_max) = cut.shape.bounds();
+        for (entity, aabb, transform, mask, explicit) in chunks.iter() {
+            let size = Vec3::from(aabb.half_extents) * 2.;
+            if !intersects_chunk(aabb, transform, cut_min, cut_max) {
+                continue;
+            }
+            let handle = match (mask, added_masks.get(&entity)) {
+                (Some(mask), _) => mask.mask.clone(),
+                (None, Some(handle)) => handle.clone(),
+                (None, None) => {
+                    let resolution = (size.xz() * CUT_MASK_TEXELS_PER_UNIT)
+                        .ceil()
+                        .as_uvec2()
+                        .clamp(UVec2::ONE, UVec2::splat(MAX_CUT_MASK_RESOLUTION));
+                    let mask = GrassCutMask::new(&mut images, resolution);
+                    added_masks.insert(entity, mask.mask.clone());
+                    commands.entity(entity).insert(mask.clone());
+                    mask.mask
+                }
+            };
+            let Some(image) = images.get_mut(&handle) else {
+                continue;
+            };
+            if image.texture_descriptor.format != TextureFormat::Rg32Float {
+                warn!("grass cut masks need to be `Rg32Float` textures");
+                continue;
+            }
+            // the positions of explicit grass aren't relative to the corner of the aabb
+            let origin = if explicit.is_some() {
+                Vec3::from(aabb.min()).xz()
+            } else {
+                Vec2::ZERO
+            };
+            cut_mask(image, cut, aabb, transform, origin);
+        }
+    }
+}
+
+/// Applies the cut to the texels of the mask whose centers lie inside the [`CutShape`].
+///
+/// `origin` is the local x,z position the mask starts at
+fn cut_mask(
+    image: &mut Image,
+    cut: &CutGrass,
+    aabb: &Aabb,
+    transform: &GlobalTransform,
+    origin: Vec2,
+) {
+    let size = Vec3::from(aabb.half_extents).xz() * 2.;
+    let width = image.texture_descriptor.size.width as usize;
+    let dim = image.size();
+    let affine = transform.affine();
+    for (index, texel) in image.data.chunks_exact_mut(8).enumerate() {
+        let pixel = Vec2::new((index % width) as f32, (index / width) as f32);
+        // the center of the texel in the local space of the chunk, like the shader maps it
+        let local = origin + (pixel + 0.5) / dim * size;
+        let world = affine
+            .transform_point3(Vec3::new(local.x, aabb.center.y, local.y))
+            .xz();
+        if cut.shape.contains(world) {
+            write_growth(texel, cut.mode.apply(read_growth(texel)));
+        }
+    }
+}
+
+/// Lets the cut grass of a chunk with a [`GrassCutMask`] grow back.
+///
+/// Removed blades sprout again one after another while they grow,
+/// so both the height and the density of the grass recover.
+/// The mask is only uploaded again each time the grass grew by another [`REGROWTH_STEP`]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Reflect, FromReflect)]
+pub struct GrassRegrowth {
+    /// The time in seconds removed grass needs to grow back completely
+    pub duration: f32,
+}
+impl Default for GrassRegrowth {
+    fn default() -> Self {
+        GrassRegrowth { duration: 30. }
+    }
+}
+/// How much the grass grows before its [`GrassCutMask`] is updated, as fraction of the full height.
+///
+/// Updating the mask uploads the whole texture again, so it isn't done every frame
+pub const REGROWTH_STEP: f32 = 0.01;
+
+/// Lets the grass in the [`GrassCutMask`]s of chunks with [`GrassRegrowth`] grow back
+pub(crate) fn regrow_grass(
+    time: Res<Time>,
+    chunks: Query<(Entity, &GrassCutMask, &GrassRegrowth)>,
+    mut images: ResMut<Assets<Image>>,
+    // the growth of each chunk since its mask was updated the last time
+    mut pending: Local<HashMap<Entity, f32>>,
+) {
+    pending.retain(|entity, _| chunks.contains(*entity));
+    for (entity, mask, regrowth) in chunks.iter() {
+        let growth = pending.entry(entity).or_default();
+        *growth += time.delta_seconds() / regrowth.duration.max(f32::EPSILON);
+        if *growth < REGROWTH_STEP {
+            continue;
+        }
+        let Some(image) = images.get(&mask.mask) else {
+            continue;
+        };
+        if image.texture_descriptor.format != TextureFormat::Rg32Float {
+            warn!("grass cut masks need to be `Rg32Float` textures");
+            continue;
+        }
+        // grown masks don't need to be uploaded again
+        if !is_growing(image) {
+            *growth = 0.;
+            continue;
+        }
+        let Some(image) = images.get_mut(&mask.mask) else {
+            continue;
+        };
+        regrow_mask(image, *growth);
+        *growth = 0.;
+    }
+}
+/// Whether any of the grass in the mask isn't fully grown
+fn is_growing(image: &Image) -> bool {
+    image
+        .data
+        .chunks_exact(8)
+        .any(|texel| read_growth(texel).cmplt(Vec2::ONE).any())
+}
+/// Raises the height and density of all texels of the mask by the growth
+fn regrow_mask(image: &mut Image, growth: f32) {
+    for texel in image.data.chunks_exact_mut(8) {
+        write_growth(texel, (read_growth(texel) + growth).min(Vec2::ONE));
+    }
+}
+/// Lets all grass of the mask stand untouched again
+pub(crate) fn clear_cut_mask(image: &mut Image) {
+    regrow_mask(image, 1.);
+}
+
+/// Reads the height and density of a texel of a [`GrassCutMask`]
+fn read_growth(texel: &[u8]) -> Vec2 {
+    Vec2::new(
+        f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]),
+        f32::from_le_bytes([texel[4], texel[5], texel[6], texel[7]]),
+    )
+}
+/// Writes the height and density to a texel of a [`GrassCutMask`]
+fn write_growth(texel: &mut [u8], growth: Vec2) {
+    texel[..4].copy_from_slice(&growth.x.to_le_bytes());
+    texel[4..8].copy_from_slice(&growth.y.to_le_bytes());
+}
+
+/// Whether the chunk overlaps the rectangle on the x,z plane in world space
+fn intersects_chunk(aabb: &Aabb, transform: &GlobalTransform, min: Vec2, max: Vec2) -> bool {
+    let affine = transform.affine();
+    let (chunk_min, chunk_max) = (Vec3::from(aabb.min()), Vec3::from(aabb.max()));
+    let (mut world_min, mut world_max) = (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN));
+    for corner in 0..8 {
+        let local = Vec3::select(
+            BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+            chunk_max,
+            chunk_min,
+        );
+        let world = affine.transform_point3(local).xz();
+        world_min = world_min.min(world);
+        world_max = world_max.max(world);
+    }
+    world_min.cmple(max).all() && world_max.cmpge(min).all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CutGrass, CutMode, CutShape, GrassCutMask, GrassRegrowth};
+    use crate::bundle::WarblerHeight;
+    use bevy::prelude::*;
+    use bevy::render::primitives::Aabb;
+    use std::time::Duration;
+
+    fn growth(image: &Image, x: usize, y: usize) -> Vec2 {
+        let width = image.texture_descriptor.size.width as usize;
+        let start = (y * width + x) * 8;
+        super::read_growth(&image.data[start..start + 8])
+    }
+    #[test]
+    fn circle_and_box() {
+        let circle = CutShape::Circle {
+            center: Vec2::new(1., 2.),
+            radius: 2.,
+        };
+        assert!(circle.contains(Vec2::new(1., 2.)));
+        assert!(circle.contains(Vec2::new(3., 2.)));
+        assert!(!circle.contains(Vec2::new(2.5, 3.5)));
+        assert_eq!(circle.bounds(), (Vec2::new(-1., 0.), Vec2::new(3., 4.)));
+
+        let rect = CutShape::Box {
+            min: Vec2::new(0., 0.),
+            max: Vec2::new(2., 1.),
+        };
+        // the edges belong to the box
+        assert!(rect.contains(Vec2::new(0., 0.)));
+        assert!(rect.contains(Vec2::new(2., 1.)));
+        assert!(rect.contains(Vec2::new(1., 0.5)));
+        assert!(!rect.contains(Vec2::new(2.01, 0.5)));
+        assert!(!rect.contains(Vec2::new(1., -0.01)));
+    }
+    #[test]
+    fn concave_polygon() {
+        // a U shape opening upwards
+        let polygon = CutShape::Polygon(vec![
+            Vec2::new(0., 0.),
+            Vec2::new(3., 0.),
+            Vec2::new(3., 3.),
+            Vec2::new(2., 3.),
+            Vec2::new(2., 1.),
+            Vec2::new(1., 1.),
+            Vec2::new(1., 3.),
+            Vec2::new(0., 3.),
+        ]);
+        assert!(polygon.contains(Vec2::new(0.5, 2.5)));
+        assert!(polygon.contains(Vec2::new(2.5, 2.5)));
+        assert!(polygon.contains(Vec2::new(1.5, 0.5)));
+        // the notch of the U
+        assert!(!polygon.contains(Vec2::new(1.5, 2.)));
+        assert!(!polygon.contains(Vec2::new(4., 0.5)));
+        assert!(!polygon.contains(Vec2::new(-1., 2.)));
+        assert_eq!(polygon.bounds(), (Vec2::ZERO, Vec2::splat(3.)));
+        // the winding order doesn't matter
+        let CutShape::Polygon(mut corners) = polygon else {
+            unreachable!()
+        };
+        corners.reverse();
+        let reversed = CutShape::Polygon(corners);
+        assert!(reversed.contains(Vec2::new(0.5, 2.5)));
+        assert!(!reversed.contains(Vec2::new(1.5, 2.)));
+        // degenerate polygons contain nothing
+        assert!(!CutShape::Polygon(Vec::new()).contains(Vec2::ZERO));
+        assert!(!CutShape::Polygon(vec![Vec2::ZERO, Vec2::X]).contains(Vec2::new(0.5, 0.)));
+    }
+    #[test]
+    fn cut_modes() {
+        let grown = Vec2::ONE;
+        assert_eq!(CutMode::Remove.apply(grown), Vec2::ZERO);
+        assert_eq!(CutMode::Trim(0.3).apply(grown), Vec2::new(0.3, 1.));
+        assert_eq!(CutMode::Shorten(0.5).apply(grown), Vec2::new(0.5, 1.));
+        // trimming never lets shorter grass grow
+        let short = Vec2::new(0.2, 0.5);
+        assert_eq!(CutMode::Trim(0.3).apply(short), short);
+        // shortening repeatedly shortens further
+        let twice = CutMode::Shorten(0.5).apply(CutMode::Shorten(0.5).apply(grown));
+        assert_eq!(twice, Vec2::new(0.25, 1.));
+        // the height stays between 0 and 1
+        assert_eq!(CutMode::Shorten(2.).apply(grown), grown);
+        assert_eq!(CutMode::Trim(-1.).apply(grown), Vec2::new(0., 1.));
+    }
+    #[test]
+    fn cut_mask_texels() {
+        let image = &mut super::mask_image(UVec2::new(4, 2));
+        let aabb = Aabb::from_min_max(Vec3::ZERO, Vec3::new(4., 1., 2.));
+        // the chunk is moved by 10 units in x direction
+        let transform = GlobalTransform::from_translation(Vec3::new(10., 0., 0.));
+        let cut = CutGrass {
+            shape: CutShape::Box {
+                min: Vec2::new(10., 0.),
+                max: Vec2::new(12., 1.),
+            },
+            mode: CutMode::Remove,
+        };
+        super::cut_mask(image, &cut, &aabb, &transform, Vec2::ZERO);
+        for y in 0..2 {
+            for x in 0..4 {
+                // the texel centers are at 0.5 + x and 0.5 + y
+                let expected = if x < 2 && y == 0 {
+                    Vec2::ZERO
+                } else {
+                    Vec2::ONE
+                };
+                assert_eq!(growth(image, x, y), expected, "texel {x} {y}");
+            }
+        }
+    }
+    #[test]
+    fn regrow_masks() {
+        let image = &mut super::mask_image(UVec2::new(2, 1));
+        assert!(!super::is_growing(image));
+        let cut = CutGrass {
+            shape: CutShape::Box {
+                min: Vec2::ZERO,
+                max: Vec2::new(1., 1.),
+            },
+            mode: CutMode::Remove,
+        };
+        let aabb = Aabb::from_min_max(Vec3::ZERO, Vec3::new(2., 1., 1.));
+        super::cut_mask(image, &cut, &aabb, &GlobalTransform::default(), Vec2::ZERO);
+        assert!(super::is_growing(image));
+        super::regrow_mask(image, 0.25);
+        assert_eq!(growth(image, 0, 0), Vec2::splat(0.25));
+        assert_eq!(growth(image, 1, 0), Vec2::ONE);
+        // the grass never grows higher than its full height
+        super::regrow_mask(image, 0.8);
+        assert_eq!(growth(image, 0, 0), Vec2::ONE);
+        assert!(!super::is_growing(image));
+    }
+    #[test]
+    fn regrowth_is_throttled() {
+        let mut app = App::new();
+        app.add_plugin(TaskPoolPlugin::default())
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<Image>()
+            .init_resource::<Time>()
+            .add_system(super::regrow_grass);
+        let mut removed = super::mask_image(UVec2::ONE);
+        super::write_growth(&mut removed.data, Vec2::ZERO);
+        let mask = app.world.resource_mut::<Assets<Image>>().add(removed);
+        app.world.spawn((
+            GrassCutMask { mask: mask.clone() },
+            GrassRegrowth { duration: 10. },
+        ));
+        let start = std::time::Instant::now();
+        let advance = |app: &mut App, seconds: f32| {
+            let mut time = app.world.resource_mut::<Time>();
+            let now = time.last_update().unwrap_or(start) + Duration::from_secs_f32(seconds);
+            time.update_with_instant(now);
+            app.update();
+            let images = app.world.resource::<Assets<Image>>();
+            growth(images.get(&mask).unwrap(), 0, 0)
+        };
+        advance(&mut app, 0.);
+        // the grass grew less than a step, so the mask isn't touched yet
+        assert_eq!(advance(&mut app, 0.05), Vec2::ZERO);
+        let grown = advance(&mut app, 0.1);
+        assert!((grown - Vec2::splat(0.015)).abs().max_element() < 1e-4);
+    }
+    #[test]
+    fn cut_grass_adds_masks() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<Image>()
+            .add_event::<CutGrass>()
+            .add_system(super::cut_grass);
+        let hit = app
+            .world
+            .spawn((
+                WarblerHeight::Uniform(1.),
+                Aabb::from_min_max(Vec3::ZERO, Vec3::new(10., 1., 10.)),
+                GlobalTransform::default(),
+            ))
+            .id();
+        let missed = app
+            .world
+            .spawn((
+                WarblerHeight::Uniform(1.),
+                Aabb::from_min_max(Vec3::ZERO, Vec3::new(10., 1., 10.)),
+                GlobalTransform::from_translation(Vec3::new(100., 0., 0.)),
+            ))
+            .id();
+        app.world.send_event(CutGrass {
+            shape: CutShape::Circle {
+                center: Vec2::new(5., 5.),
+                radius: 1.,
+            },
+            mode: CutMode::Trim(0.5),
+        });
+        app.update();
+        let world = &app.world;
+
+        assert!(world.get::<GrassCutMask>(missed).is_none());
+        let mask = world.get::<GrassCutMask>(hit).unwrap().mask.clone();
+        let images = world.resource::<Assets<Image>>();
+        let image = images.get(&mask).unwrap();
+        let resolution = (10. * super::CUT_MASK_TEXELS_PER_UNIT) as u32;
+        assert_eq!(image.size(), Vec2::splat(resolution as f32));
+        // the center is trimmed while the corners stay untouched
+        let center = resolution as usize / 2;
+        assert_eq!(growth(image, center, center), Vec2::new(0.5, 1.));
+        assert_eq!(growth(image, 0, 0), Vec2::ONE);
+    }
+}
@@ -1,5 +1,10 @@
 //! Contains the implementation of the [`DensityMap`] component
-use bevy::{asset::Handle, ecs::component::Component, reflect::Reflect, render::texture::Image};
+use bevy::{
+    asset::Handle,
+    ecs::component::Component,
+    reflect::{FromReflect, Reflect},
+    render::texture::{Image, DEFAULT_IMAGE_HANDLE},
+};
 /// The density map defining the density of grass at specific positions.
 /// White pixels corresponds to dense areas.
 ///
@@ -21,6 +26,13 @@ pub struct DensityMap {
     ///
     /// If the density is high, more grass is spawned in a dense area
     pub density: f32,
+    /// How the grass blades are scattered over the chunk
+    pub scattering: Scattering,
+}
+impl Default for DensityMap {
+    fn default() -> Self {
+        DEFAULT_IMAGE_HANDLE.typed().into()
+    }
 }
 /// A density map can be created from the image alone
 ///
@@ -30,6 +42,46 @@ impl From<Handle<Image>> for DensityMap {
         DensityMap {
             density_map: value,
             density: 1.,
+            scattering: Scattering::default(),
         }
     }
 }
+
+/// The algorithm used to scatter the grass blades of a [`DensityMap`]
+///
+/// All algorithms are deterministic, so the same seed always results in the same blades
+#[derive(Reflect, FromReflect, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scattering {
+    /// Places the blades on a regular grid using an ordered 8x8 bayer matrix.
+    ///
+    /// This is the fastest algorithm, but grid patterns might be visible at low densities
+    #[default]
+    Bayer,
+    /// Scatters the blades randomly, while keeping a minimum distance between them.
+    ///
+    /// The blades look the most organic, but the generation is the slowest
+    PoissonDisk {
+        /// The seed used for the random placement
+        seed: u64,
+    },
+    /// Places the blades on a regular grid using a blue noise threshold matrix.
+    ///
+    /// Avoids the crosshatch patterns of [`Scattering::Bayer`] in sparse areas
+    BlueNoise {
+        /// The seed used to generate the blue noise
+        seed: u64,
+    },
+}
+
+/// Generates the grass blades of a chunk on the gpu instead of the cpu
+///
+/// Normally, the [`DensityMap`] is dithered on the cpu every time it or the [`Aabb`](bevy::render::primitives::Aabb) changes,
+/// which can take a while for big chunks.
+/// With this component, the blade positions are generated by a compute shader instead.
+///
+/// If the device doesn't support compute shaders (e.g. WebGL2), the cpu is used as fallback.
+/// Only [`Scattering::Bayer`] is supported on the gpu, other algorithms always use the cpu.
+/// Note that the blades of gpu dithered chunks are not counted by the
+/// [`WarblerDiagnosticsPlugin`](crate::diagnostic::WarblerDiagnosticsPlugin)
+#[derive(Reflect, Clone, Copy, Default, Debug, Component)]
+pub struct GpuDithering;
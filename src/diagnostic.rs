@@ -1,12 +1,21 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
 use bevy::{
     diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
-    prelude::{Assets, ComputedVisibility, Handle, Plugin, Query, Res, ResMut},
+    prelude::{Assets, ComputedVisibility, Handle, Plugin, Query, Res, ResMut, Resource},
+    render::RenderApp,
 };
 
 use crate::{dithering::DitheredBuffer, prelude::Grass};
 
 /// A [`Plugin`] that logs the blades drawn in each frame.
 ///
+/// Additionally, the blades culled on the gpu of chunks with the [`GpuCulling`](crate::bundle::GpuCulling)
+/// component or a [`GrassLod`](crate::GrassLod) are logged. Since they have to be read back from the gpu, the value lags a few frames behind.
+///
 /// If you want to simply log the values in the terminal,
 /// you can also add the [`LogDiagnosticsPlugin`](bevy::diagnostic::LogDiagnosticsPlugin) to your app
 ///
@@ -25,7 +34,13 @@ use crate::{dithering::DitheredBuffer, prelude::Grass};
 pub struct WarblerDiagnosticsPlugin;
 impl Plugin for WarblerDiagnosticsPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_startup_system(Self::setup_blade_count)
+        let culling_stats = GpuCullingStats::default();
+        // the render world writes the culled blade count
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.insert_resource(culling_stats.clone());
+        }
+        app.insert_resource(culling_stats)
+            .add_startup_system(Self::setup_blade_count)
             .add_system(Self::measure_blades);
     }
 }
@@ -33,6 +48,9 @@ impl WarblerDiagnosticsPlugin {
     /// An id for the [`Diagnostic`] of the blade count
     pub const GRASS_BLADE_COUNT: DiagnosticId =
         DiagnosticId::from_u128(11920430925311532474622109399490581929);
+    /// An id for the [`Diagnostic`] of the blades culled on the gpu
+    pub const GRASS_CULLED_BLADE_COUNT: DiagnosticId =
+        DiagnosticId::from_u128(20779637014452711069870349624262305126);
 
     /// Adds the [`Diagnostic`] responsable for logging the blade count to the [`Diagnostics`]
     fn setup_blade_count(mut diagnostics: ResMut<Diagnostics>) {
@@ -40,6 +58,14 @@ impl WarblerDiagnosticsPlugin {
             Diagnostic::new(Self::GRASS_BLADE_COUNT, "grass blade count", 20)
                 .with_suffix(" blades"),
         );
+        diagnostics.add(
+            Diagnostic::new(
+                Self::GRASS_CULLED_BLADE_COUNT,
+                "grass culled blade count",
+                20,
+            )
+            .with_suffix(" blades"),
+        );
     }
 
     /// Calculates the amount of blades that are drawn this frame and logs them
@@ -47,6 +73,7 @@ impl WarblerDiagnosticsPlugin {
         blades: Query<(&Handle<DitheredBuffer>, &ComputedVisibility)>,
         explicit_blades: Query<(&Grass, &ComputedVisibility)>,
         dither: Res<Assets<DitheredBuffer>>,
+        culling_stats: Res<GpuCullingStats>,
         mut diagnostics: ResMut<Diagnostics>,
     ) {
         // entities spawned with the WarblersBundle
@@ -68,5 +95,20 @@ impl WarblerDiagnosticsPlugin {
         diagnostics.add_measurement(Self::GRASS_BLADE_COUNT, || {
             count as f64 + count_explicit as f64
         });
+        diagnostics.add_measurement(Self::GRASS_CULLED_BLADE_COUNT, || {
+            culling_stats.culled_blades() as f64
+        });
+    }
+}
+
+/// The amount of blades culled on the gpu, shared between the main and the render world
+#[derive(Resource, Clone, Default)]
+pub(crate) struct GpuCullingStats(Arc<AtomicU64>);
+impl GpuCullingStats {
+    pub fn culled_blades(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+    pub fn set_culled_blades(&self, count: u64) {
+        self.0.store(count, Ordering::Relaxed);
     }
 }
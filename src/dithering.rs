@@ -1,5 +1,5 @@
 use bevy::{
-    asset::Assets,
+    asset::{AssetEvent, Assets, Handle},
     ecs::{
         prelude::*,
         system::{lifetimeless::SRes, SystemParamItem},
@@ -15,9 +15,13 @@ use bevy::{
         renderer::RenderDevice,
         texture::Image,
     },
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
 };
+use futures_lite::future;
+use std::sync::{Arc, Mutex, OnceLock};
 
-use crate::density_map::DensityMap;
+use crate::density_map::{DensityMap, GpuDithering, Scattering};
 
 // see https://surma.dev/things/ditherpunk/ for a good resource regarding ordered dithering
 const BAYER_DITHER: [[u8; 8]; 8] = [
@@ -42,7 +46,7 @@ pub(crate) fn dither_density_map(
     if field_size.length() < 0.0001 {
         return None;
     }
-    let Ok(dynamic_image)  = image.clone().try_into_dynamic() else {
+    let Ok(dynamic_image) = image.clone().try_into_dynamic() else {
         return None;
     };
     // Capacity is not precise but should be a good estimate
@@ -71,6 +75,340 @@ pub(crate) fn dither_density_map(
         positions: dither_buffer,
     })
 }
+/// Scatters the grass blades of a density map using the given [`Scattering`] algorithm
+pub(crate) fn scatter_density_map(
+    image: &Image,
+    density: f32,
+    field_size: Vec2,
+    scattering: Scattering,
+) -> Option<DitheredBuffer> {
+    match scattering {
+        Scattering::Bayer => dither_density_map(image, density, field_size),
+        Scattering::PoissonDisk { seed } => {
+            poisson_disk_density_map(image, density, field_size, seed)
+        }
+        Scattering::BlueNoise { seed } => blue_noise_density_map(image, density, field_size, seed),
+    }
+}
+
+/// The amount of blades along one side of a poisson disk tile
+const POISSON_TILE_BLADES: f32 = 64.;
+/// The minimal distance between two blades relative to the distance of the blades in a dense area of a bayer dithered chunk.
+///
+/// Chosen so both algorithms spawn roughly the same amount of blades
+const POISSON_RADIUS: f32 = 0.8;
+/// The amount of candidates tried around each blade before it is considered done
+const POISSON_CANDIDATES: usize = 30;
+
+/// Scatters the blades using a tileable poisson disk pattern.
+///
+/// The pattern is thinned out according to the density map using seeded random thresholds
+fn poisson_disk_density_map(
+    image: &Image,
+    density: f32,
+    field_size: Vec2,
+    seed: u64,
+) -> Option<DitheredBuffer> {
+    if density < 0. {
+        warn!("tried to dither a image with density < 0");
+        return None;
+    }
+    if field_size.length() < 0.0001 {
+        return None;
+    }
+    let Ok(dynamic_image) = image.clone().try_into_dynamic() else {
+        return None;
+    };
+    let buffer = dynamic_image.into_luma8();
+    let size = field_size.abs();
+    let mut positions = Vec::new();
+    if density == 0. || size.x == 0. || size.y == 0. {
+        return Some(DitheredBuffer { positions });
+    }
+    let tile_size = POISSON_TILE_BLADES / density;
+    let tile = cached_poisson_disk_tile(tile_size, POISSON_RADIUS / density, seed);
+    let tiles_x = (size.x / tile_size).ceil() as u64;
+    let tiles_y = (size.y / tile_size).ceil() as u64;
+    for tile_x in 0..tiles_x {
+        for tile_y in 0..tiles_y {
+            let tile_offset = Vec2::new(tile_x as f32, tile_y as f32) * tile_size;
+            // each repetition of the tile gets thinned out differently, which hides the repetition
+            let mut rng = SeededRng::new(seed ^ (tile_x << 32 | tile_y).wrapping_mul(0x9e37_79b9));
+            for point in tile.iter() {
+                let threshold = rng.next_f32() * 255.;
+                let position = tile_offset + *point;
+                if position.x >= size.x || position.y >= size.y {
+                    continue;
+                }
+                let normalized = position / size;
+                let x = normalized.x * buffer.dimensions().0 as f32;
+                let y = normalized.y * buffer.dimensions().1 as f32;
+                let pixel = buffer.get_pixel(x as u32, y as u32).0[0];
+                if pixel as f32 > threshold {
+                    positions.push(normalized * field_size);
+                }
+            }
+        }
+    }
+    Some(DitheredBuffer { positions })
+}
+
+/// Returns the poisson disk tile of the seed and radius, which is only generated once per seed and radius.
+///
+/// Like the blue noise matrix, generating a tile is expensive while chunks are dithered again quite often
+fn cached_poisson_disk_tile(tile_size: f32, radius: f32, seed: u64) -> Arc<Vec<Vec2>> {
+    type TileKey = (u64, u32, u32);
+    static CACHE: OnceLock<Mutex<HashMap<TileKey, Arc<Vec<Vec2>>>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
+    let key = (seed, tile_size.to_bits(), radius.to_bits());
+    if let Some(tile) = cache.lock().unwrap().get(&key) {
+        return tile.clone();
+    }
+    // generated without holding the lock, so other tiles can still be generated meanwhile
+    let tile = Arc::new(poisson_disk_tile(tile_size, radius, seed));
+    cache.lock().unwrap().entry(key).or_insert(tile).clone()
+}
+
+/// Generates a poisson disk pattern on a square, which can be repeated without seams.
+///
+/// Uses the algorithm of Bridson, see <https://www.cs.ubc.ca/~rbridson/docs/bridson-siggraph07-poissondisk.pdf>
+fn poisson_disk_tile(tile_size: f32, radius: f32, seed: u64) -> Vec<Vec2> {
+    // each cell of the grid contains at most one point
+    let cells = (tile_size / radius * std::f32::consts::SQRT_2)
+        .ceil()
+        .max(1.) as usize;
+    let cell_size = tile_size / cells as f32;
+    let search = (radius / cell_size).ceil() as i64;
+    let cell_of = |point: Vec2| {
+        let x = ((point.x / cell_size) as usize).min(cells - 1);
+        let y = ((point.y / cell_size) as usize).min(cells - 1);
+        (x, y)
+    };
+    // the distance on the tile, which wraps around at the borders
+    let wrapped_distance = |a: Vec2, b: Vec2| {
+        let d = (a - b).abs();
+        d.min(Vec2::splat(tile_size) - d).length()
+    };
+
+    let mut rng = SeededRng::new(seed);
+    let mut grid = vec![usize::MAX; cells * cells];
+    let first = Vec2::new(rng.next_f32(), rng.next_f32()) * tile_size;
+    let (x, y) = cell_of(first);
+    grid[y * cells + x] = 0;
+    let mut points = vec![first];
+    let mut active = vec![0];
+
+    while !active.is_empty() {
+        let active_index = (rng.next_u64() % active.len() as u64) as usize;
+        let center = points[active[active_index]];
+        let mut found = false;
+        for _ in 0..POISSON_CANDIDATES {
+            let angle = rng.next_f32() * std::f32::consts::TAU;
+            let distance = radius * (1. + rng.next_f32());
+            let candidate = center + Vec2::new(angle.cos(), angle.sin()) * distance;
+            let candidate = Vec2::new(
+                candidate.x.rem_euclid(tile_size),
+                candidate.y.rem_euclid(tile_size),
+            );
+            let (x, y) = cell_of(candidate);
+            let mut free = true;
+            'search: for dy in -search..=search {
+                for dx in -search..=search {
+                    let nx = (x as i64 + dx).rem_euclid(cells as i64) as usize;
+                    let ny = (y as i64 + dy).rem_euclid(cells as i64) as usize;
+                    let neighbour = grid[ny * cells + nx];
+                    if neighbour != usize::MAX
+                        && wrapped_distance(points[neighbour], candidate) < radius
+                    {
+                        free = false;
+                        break 'search;
+                    }
+                }
+            }
+            if free {
+                grid[y * cells + x] = points.len();
+                active.push(points.len());
+                points.push(candidate);
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            active.swap_remove(active_index);
+        }
+    }
+    points
+}
+
+/// The width and height of the blue noise threshold matrix
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// Dithers the density map like [`dither_density_map`], but uses a blue noise threshold matrix instead of the bayer matrix
+fn blue_noise_density_map(
+    image: &Image,
+    density: f32,
+    field_size: Vec2,
+    seed: u64,
+) -> Option<DitheredBuffer> {
+    if density < 0. {
+        warn!("tried to dither a image with density < 0");
+        return None;
+    }
+    if field_size.length() < 0.0001 {
+        return None;
+    }
+    let Ok(dynamic_image) = image.clone().try_into_dynamic() else {
+        return None;
+    };
+    let thresholds = cached_blue_noise_matrix(seed);
+    let mut dither_buffer = Vec::with_capacity(image.size().length() as usize);
+    let buffer = dynamic_image.into_luma8();
+    let i_count = (density * field_size.x).abs() as usize;
+    let j_count = (density * field_size.y).abs() as usize;
+    for i in 0..i_count {
+        for j in 0..j_count {
+            let rank = thresholds[(i % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + j % BLUE_NOISE_SIZE];
+            let threshold = rank * 255 / (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as u32;
+
+            //normalize i,j between 0,1
+            let i = i as f32 / i_count as f32;
+            let j = j as f32 / j_count as f32;
+
+            let x = i * buffer.dimensions().0 as f32;
+            let y = j * buffer.dimensions().1 as f32;
+
+            let pixel = buffer.get_pixel(x as u32, y as u32).0[0];
+            if pixel as u32 > threshold {
+                dither_buffer.push(Vec2::new(i * field_size.x, j * field_size.y));
+            }
+        }
+    }
+    Some(DitheredBuffer {
+        positions: dither_buffer,
+    })
+}
+
+/// Returns the blue noise threshold matrix of the seed, which is only generated once per seed.
+///
+/// Generating a matrix is expensive, while chunks are dithered again quite often
+fn cached_blue_noise_matrix(seed: u64) -> Arc<Vec<u32>> {
+    static CACHE: OnceLock<Mutex<HashMap<u64, Arc<Vec<u32>>>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(matrix) = cache.lock().unwrap().get(&seed) {
+        return matrix.clone();
+    }
+    // generated without holding the lock, so other seeds can still be dithered meanwhile
+    let matrix = Arc::new(blue_noise_matrix(seed));
+    cache.lock().unwrap().entry(seed).or_insert(matrix).clone()
+}
+
+/// Generates a tileable blue noise threshold matrix containing each rank exactly once.
+///
+/// Uses the void and cluster algorithm of Ulichney
+fn blue_noise_matrix(seed: u64) -> Vec<u32> {
+    const N: usize = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+    const SIGMA: f32 = 1.5;
+    // the gaussian energy between two pixels, depending on their wrapped distance
+    let mut kernel = vec![0.; N];
+    for y in 0..BLUE_NOISE_SIZE {
+        for x in 0..BLUE_NOISE_SIZE {
+            let dx = x.min(BLUE_NOISE_SIZE - x) as f32;
+            let dy = y.min(BLUE_NOISE_SIZE - y) as f32;
+            kernel[y * BLUE_NOISE_SIZE + x] = (-(dx * dx + dy * dy) / (2. * SIGMA * SIGMA)).exp();
+        }
+    }
+    let toggle = |energy: &mut [f32], pattern: &mut [bool], pixel: usize| {
+        pattern[pixel] = !pattern[pixel];
+        let sign = if pattern[pixel] { 1. } else { -1. };
+        let (px, py) = (pixel % BLUE_NOISE_SIZE, pixel / BLUE_NOISE_SIZE);
+        for (other, energy) in energy.iter_mut().enumerate() {
+            let dx = (other % BLUE_NOISE_SIZE + BLUE_NOISE_SIZE - px) % BLUE_NOISE_SIZE;
+            let dy = (other / BLUE_NOISE_SIZE + BLUE_NOISE_SIZE - py) % BLUE_NOISE_SIZE;
+            *energy += sign * kernel[dy * BLUE_NOISE_SIZE + dx];
+        }
+    };
+    // the tightest cluster is the set pixel with the most energy,
+    // the largest void the unset pixel with the least energy
+    let tightest_cluster = |energy: &[f32], pattern: &[bool]| {
+        (0..N)
+            .filter(|pixel| pattern[*pixel])
+            .max_by(|a, b| energy[*a].total_cmp(&energy[*b]))
+    };
+    let largest_void = |energy: &[f32], pattern: &[bool]| {
+        (0..N)
+            .filter(|pixel| !pattern[*pixel])
+            .min_by(|a, b| energy[*a].total_cmp(&energy[*b]))
+    };
+
+    // random initial pattern
+    let mut rng = SeededRng::new(seed);
+    let mut pattern = vec![false; N];
+    let mut energy = vec![0.; N];
+    let initial_count = N / 10;
+    let mut count = 0;
+    while count < initial_count {
+        let pixel = (rng.next_u64() % N as u64) as usize;
+        if !pattern[pixel] {
+            toggle(&mut energy, &mut pattern, pixel);
+            count += 1;
+        }
+    }
+    // distribute the initial pattern evenly
+    for _ in 0..N {
+        let Some(cluster) = tightest_cluster(&energy, &pattern) else {
+            break;
+        };
+        toggle(&mut energy, &mut pattern, cluster);
+        let Some(void) = largest_void(&energy, &pattern) else {
+            break;
+        };
+        toggle(&mut energy, &mut pattern, void);
+        if void == cluster {
+            break;
+        }
+    }
+    let initial_pattern = pattern.clone();
+    let initial_energy = energy.clone();
+
+    let mut ranks = vec![0; N];
+    // rank the initial pattern by removing the tightest clusters
+    let mut rank = initial_count;
+    while let Some(cluster) = tightest_cluster(&energy, &pattern) {
+        toggle(&mut energy, &mut pattern, cluster);
+        rank -= 1;
+        ranks[cluster] = rank as u32;
+    }
+    // rank the remaining pixels by filling the largest voids
+    pattern = initial_pattern;
+    energy = initial_energy;
+    let mut rank = initial_count;
+    while let Some(void) = largest_void(&energy, &pattern) {
+        toggle(&mut energy, &mut pattern, void);
+        ranks[void] = rank as u32;
+        rank += 1;
+    }
+    ranks
+}
+
+/// A small deterministic random number generator (splitmix64)
+struct SeededRng(u64);
+impl SeededRng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    /// Returns a value between 0 and 1
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
 /// A buffer containing the dithered density map
 ///
 /// This struct shouldn't be modified by the user
@@ -100,10 +438,16 @@ impl RenderAsset for DitheredBuffer {
         param: &mut SystemParamItem<Self::Param>,
     ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
         let render_device = param;
+        // the buffer is copied if the chunk is batched
+        let mut usage = BufferUsages::VERTEX | BufferUsages::COPY_DST | BufferUsages::COPY_SRC;
+        // and read by a compute shader if the chunk is culled on the gpu
+        if render_device.limits().max_storage_buffers_per_shader_stage > 0 {
+            usage |= BufferUsages::STORAGE;
+        }
         let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
             label: "dither buffer".into(),
             contents: bytemuck::cast_slice(extracted_asset.positions.as_slice()),
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            usage,
         });
         Ok(GpuDitheredBuffer {
             buffer,
@@ -112,30 +456,153 @@ impl RenderAsset for DitheredBuffer {
     }
 }
 
-/// Updates the [`DitheredBuffer`] of an entity
-#[allow(clippy::type_complexity)]
+/// Checks whether the device is able to dither density maps using compute shaders
+pub(crate) fn gpu_dithering_supported(render_device: &RenderDevice) -> bool {
+    let limits = render_device.limits();
+    // WebGL2 doesn't support compute shaders, which is reflected in the limits
+    limits.max_compute_workgroups_per_dimension > 0
+        && limits.max_storage_buffers_per_shader_stage >= 2
+}
+
+/// An event which is sent once the blades of a chunk are generated and the chunk is ready to be rendered
+///
+/// This happens each time the [`DensityMap`] or the [`Aabb`] of the chunk changes.
+/// Chunks with the [`GpuDithering`] component are ready as soon as they are spawned,
+/// while explicit chunks never send this event since they don't need to be dithered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GrassChunkReady {
+    /// The chunk entity
+    pub entity: Entity,
+    /// The generation of the dithering which finished.
+    ///
+    /// Each started dithering gets a higher generation than the ones before,
+    /// so events of outdated ditherings can be told apart from the latest one
+    pub generation: u64,
+}
+
+/// The generation of the last dithering which was started, see [`GrassChunkReady::generation`]
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub(crate) struct DitheringGeneration(pub u64);
+
+/// A running dithering of a chunk in the background
+#[derive(Component)]
+pub(crate) struct DitheringTask {
+    task: Task<Option<DitheredBuffer>>,
+    generation: u64,
+}
+
+/// Starts dithering the [`DensityMap`] of an entity in the background
+///
+/// Entities with the [`GpuDithering`] component are skipped if the gpu is able to dither them
+/// and the [`Scattering`] is supported by the gpu.
+/// If the density map isn't loaded yet, the dithering starts once the image is available
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub(crate) fn add_dither_to_density(
     mut commands: Commands,
-    grasses: Query<(Entity, &DensityMap, &Aabb), Or<(Changed<DensityMap>, Changed<Aabb>)>>,
+    changed: Query<
+        (Entity, &DensityMap, &Aabb, Option<&GpuDithering>),
+        Or<(Changed<DensityMap>, Changed<Aabb>)>,
+    >,
+    grasses: Query<(Entity, &DensityMap, &Aabb, Option<&GpuDithering>)>,
     images: Res<Assets<Image>>,
-    mut dithered: ResMut<Assets<DitheredBuffer>>,
-    mut storage: Local<Vec<(Entity, DensityMap, Aabb)>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    render_device: Option<Res<RenderDevice>>,
+    mut ready_events: EventWriter<GrassChunkReady>,
+    mut generation: ResMut<DitheringGeneration>,
 ) {
-    let stored = std::mem::take(&mut *storage);
-    for (e, density_map, aabb) in grasses
+    let use_gpu = render_device.is_some_and(|device| gpu_dithering_supported(&device));
+    // chunks need to be dithered again if their density map got loaded or modified
+    let updated_images: HashSet<Handle<Image>> = image_events
         .iter()
-        .chain(stored.iter().map(|(e, map, aabb)| (*e, map, aabb)))
-    {
-        if let Some(image) = images.get(&density_map.density_map) {
-            let xz = aabb.half_extents.xz() * 2.;
-            let Some(buffer) = dither_density_map(image, density_map.density, xz) else {
-                warn!("Couldn't dither density map. Maybe the image format is not supported?");
-                continue
-            };
-            let handle = dithered.add(buffer);
-            commands.entity(e).insert(handle);
-        } else {
-            storage.push((e, density_map.clone(), *aabb));
+        .filter_map(|event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                Some(handle.clone_weak())
+            }
+            AssetEvent::Removed { .. } => None,
+        })
+        .collect();
+    let with_updated_image = grasses.iter().filter(|(e, density_map, ..)| {
+        updated_images.contains(&density_map.density_map) && !changed.contains(*e)
+    });
+    let task_pool = AsyncComputeTaskPool::get();
+    for (e, density_map, aabb, gpu_dithering) in changed.iter().chain(with_updated_image) {
+        if use_gpu && gpu_dithering.is_some() && density_map.scattering == Scattering::Bayer {
+            // the chunk might have been dithered on the cpu with another algorithm before
+            commands
+                .entity(e)
+                .remove::<(Handle<DitheredBuffer>, DitheringTask)>();
+            generation.0 += 1;
+            ready_events.send(GrassChunkReady {
+                entity: e,
+                generation: generation.0,
+            });
+            continue;
+        }
+        let Some(image) = images.get(&density_map.density_map) else {
+            continue;
+        };
+        let image = image.clone();
+        let density = density_map.density;
+        let scattering = density_map.scattering;
+        let xz = aabb.half_extents.xz() * 2.;
+        let task =
+            task_pool.spawn(async move { scatter_density_map(&image, density, xz, scattering) });
+        generation.0 += 1;
+        // an already running task of the entity gets cancelled when it is replaced
+        commands.entity(e).insert(DitheringTask {
+            task,
+            generation: generation.0,
+        });
+    }
+}
+
+/// Inserts the [`DitheredBuffer`] of finished dithering tasks
+///
+/// If the entity already has a [`DitheredBuffer`], the asset is replaced instead of adding a new one
+pub(crate) fn poll_dithering_tasks(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut DitheringTask, Option<&Handle<DitheredBuffer>>)>,
+    mut dithered: ResMut<Assets<DitheredBuffer>>,
+    mut ready_events: EventWriter<GrassChunkReady>,
+) {
+    for (e, mut task, current) in tasks.iter_mut() {
+        let Some(result) = future::block_on(future::poll_once(&mut task.task)) else {
+            continue;
+        };
+        commands.entity(e).remove::<DitheringTask>();
+        let Some(buffer) = result else {
+            warn!("Couldn't dither density map. Maybe the image format is not supported?");
+            continue;
+        };
+        match current.and_then(|handle| dithered.get_mut(handle)) {
+            Some(current) => *current = buffer,
+            None => {
+                let handle = dithered.add(buffer);
+                commands.entity(e).insert(handle);
+            }
+        }
+        ready_events.send(GrassChunkReady {
+            entity: e,
+            generation: task.generation,
+        });
+    }
+}
+
+/// Removes the [`DitheredBuffer`] and running dithering tasks of entities whose [`DensityMap`] got removed
+///
+/// The buffer is freed once the last handle to it is dropped
+#[allow(clippy::type_complexity)]
+pub(crate) fn remove_dither_of_removed_density(
+    mut commands: Commands,
+    mut removed: RemovedComponents<DensityMap>,
+    chunks: Query<(), Or<(With<Handle<DitheredBuffer>>, With<DitheringTask>)>>,
+) {
+    for e in removed.iter() {
+        // despawned entities don't need to be cleaned up
+        if chunks.contains(e) {
+            commands
+                .entity(e)
+                .remove::<(Handle<DitheredBuffer>, DitheringTask)>();
         }
     }
 }
@@ -157,7 +624,7 @@ mod tests {
     fn dither_density() {
         let image = Image::default(); // 1x1x1 image all white
         let dither = super::dither_density_map(&image, 2., Vec2::new(1., 1.));
-        assert_eq!(dither.unwrap().positions.len(), (1 * 2) * (1 * 2));
+        assert_eq!(dither.unwrap().positions.len(), 2 * 2);
         let dither = super::dither_density_map(&image, 2., Vec2::new(10., 5.));
         assert!(dither.unwrap().positions.len() == (10 * 2) * (5 * 2));
         let dither = super::dither_density_map(&image, 5., Vec2::new(1., 1.));
@@ -219,4 +686,59 @@ mod tests {
         assert!(dither.is_some());
         assert_eq!(dither.unwrap().positions.len(), 50);
     }
+    #[test]
+    fn poisson_disk() {
+        use crate::density_map::Scattering;
+        let image = Image::default(); // 1x1x1 image all white
+        let scattering = Scattering::PoissonDisk { seed: 3 };
+        let dither = super::scatter_density_map(&image, 2., Vec2::new(50., 20.), scattering);
+        let positions = dither.unwrap().positions;
+        // as many blades as the bayer matrix would spawn, within 10%
+        let expected = (50 * 2) * (20 * 2);
+        assert!(positions.len().abs_diff(expected) < expected / 10);
+        // no two blades are closer than the radius, even across the borders of the tiles
+        let radius = super::POISSON_RADIUS / 2.;
+        for (i, a) in positions.iter().enumerate() {
+            assert!(positions[i + 1..]
+                .iter()
+                .all(|b| a.distance(*b) >= radius * 0.999));
+        }
+        assert!(positions
+            .iter()
+            .all(|p| p.x >= 0. && p.x < 50. && p.y >= 0. && p.y < 20.));
+        // the same seed results in the same blades
+        let again = super::scatter_density_map(&image, 2., Vec2::new(50., 20.), scattering);
+        assert_eq!(positions, again.unwrap().positions);
+
+        let dither = super::scatter_density_map(&image, -1., Vec2::new(1., 1.), scattering);
+        assert!(dither.is_none());
+        let dither = super::scatter_density_map(&image, 0., Vec2::new(1., 1.), scattering);
+        assert!(dither.unwrap().positions.is_empty());
+        // the tile is only generated once per seed and radius
+        let first = super::cached_poisson_disk_tile(32., 0.4, 5);
+        let second = super::cached_poisson_disk_tile(32., 0.4, 5);
+        assert!(std::sync::Arc::ptr_eq(&first, &second));
+        assert_eq!(*first, super::poisson_disk_tile(32., 0.4, 5));
+        let other = super::cached_poisson_disk_tile(32., 0.8, 5);
+        assert!(!std::sync::Arc::ptr_eq(&first, &other));
+    }
+    #[test]
+    fn blue_noise() {
+        use crate::density_map::Scattering;
+        let image = Image::default(); // 1x1x1 image all white
+        let scattering = Scattering::BlueNoise { seed: 3 };
+        let dither = super::scatter_density_map(&image, 2., Vec2::new(10., 5.), scattering);
+        assert_eq!(dither.unwrap().positions.len(), (10 * 2) * (5 * 2));
+
+        // every threshold is used exactly once
+        let mut ranks = super::blue_noise_matrix(3);
+        ranks.sort_unstable();
+        assert!(ranks.iter().enumerate().all(|(i, rank)| i as u32 == *rank));
+
+        // the matrix is only generated once per seed
+        let first = super::cached_blue_noise_matrix(5);
+        let second = super::cached_blue_noise_matrix(5);
+        assert!(std::sync::Arc::ptr_eq(&first, &second));
+        assert_eq!(*first, super::blue_noise_matrix(5));
+    }
 }
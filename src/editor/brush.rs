@@ -1,4 +1,6 @@
-use bevy::{prelude::*, render::render_resource::TextureFormat};
+use bevy::prelude::*;
+
+use crate::height_map::add_to_texel;
 
 pub trait BrushBehavior: Sync + Send {
     /// position should be between 0 and 1
@@ -10,20 +12,12 @@ pub struct Stencil;
 
 impl BrushBehavior for Stencil {
     fn draw(&self, image: &mut Image, position: Vec2, brush_size: u32, strength: f32) {
-        let Ok(dynamic_image)  = image.clone().try_into_dynamic() else {
-            warn!("couldn't convert image");
-            return;
-        };
-        let mut buffer = dynamic_image.into_rgba8();
-
         for (x, y) in pixel_positions(brush_size, image.size(), position).into_iter() {
-            let pixel = &mut buffer.get_pixel_mut(x, y).0;
-            paint_gray(pixel, strength);
+            if !paint_gray(image, UVec2::new(x, y), strength) {
+                warn!("couldn't draw on image");
+                return;
+            }
         }
-
-        *image = Image::from_dynamic(buffer.into(), true)
-            .convert(TextureFormat::Rgba8UnormSrgb)
-            .unwrap();
     }
 }
 
@@ -32,11 +26,6 @@ impl BrushBehavior for Stencil {
 pub struct Airbrush;
 impl BrushBehavior for Airbrush {
     fn draw(&self, image: &mut Image, position: Vec2, brush_size: u32, strength: f32) {
-        let Ok(dynamic_image)  = image.clone().try_into_dynamic() else {
-            warn!("couldn't convert image");
-            return;
-        };
-        let mut buffer = dynamic_image.into_rgba8();
         let positions = pixel_positions(brush_size, image.size(), position);
         let mut max = (u32::MIN, u32::MIN);
         let mut center = positions
@@ -58,20 +47,17 @@ impl BrushBehavior for Airbrush {
             (max.0 as f32 - center.0 as f32).powf(2.) + (max.1 as f32 - center.1 as f32).powf(2.);
 
         for (x, y) in positions.into_iter() {
-            let pixel = &mut buffer.get_pixel_mut(x, y).0;
-
             let distance = (((x as f32 - center.0 as f32).powf(2.)
                 + (y as f32 - center.1 as f32).powf(2.))
                 / max_distance)
                 .powf(0.1);
             let total_strength = strength - (strength * distance);
 
-            paint_gray(pixel, total_strength);
+            if !paint_gray(image, UVec2::new(x, y), total_strength) {
+                warn!("couldn't draw on image");
+                return;
+            }
         }
-
-        *image = Image::from_dynamic(buffer.into(), true)
-            .convert(TextureFormat::Rgba8UnormSrgb)
-            .unwrap();
     }
 }
 
@@ -90,22 +76,11 @@ fn pixel_positions(brush_size: u32, image_dimensions: Vec2, position: Vec2) -> V
         .map(|(x, y)| (x as u32, y as u32))
         .collect()
 }
-fn paint_gray(pixel: &mut [u8; 4], strength: f32) {
-    if strength >= 0. {
-        let strength = strength as u8;
-        *pixel = [
-            pixel[0].saturating_add(strength),
-            pixel[1].saturating_add(strength),
-            pixel[2].saturating_add(strength),
-            255,
-        ];
-    } else {
-        let strength = -strength as u8;
-        *pixel = [
-            pixel[0].saturating_sub(strength),
-            pixel[1].saturating_sub(strength),
-            pixel[2].saturating_sub(strength),
-            255,
-        ];
-    }
+/// Raises or lowers the height of a pixel.
+///
+/// The strength is given in steps of an 8 bit image and applied in the encoding of the image,
+/// so 16 bit and float images keep their precision and each step changes srgb images evenly.
+/// Returns `false` if the format of the image isn't supported
+fn paint_gray(image: &mut Image, pixel: UVec2, strength: f32) -> bool {
+    add_to_texel(image, pixel, strength / u8::MAX as f32)
 }
@@ -1,8 +1,15 @@
 use std::path::PathBuf;
 
-use bevy::prelude::{info, warn, Assets, DetectChanges, Image, Query, Res, Resource};
+use bevy::{
+    prelude::{info, warn, Assets, DetectChanges, Image, Query, Res, Resource, UVec2},
+    render::render_resource::TextureFormat,
+};
+use image::{ImageBuffer, Luma};
 
-use crate::prelude::{DensityMap, HeightMap, WarblerHeight};
+use crate::{
+    height_map::load_texel,
+    prelude::{DensityMap, HeightMap, WarblerHeight},
+};
 
 use super::{ray_cast::SelectedMap, ActiveEditorChunk};
 
@@ -47,7 +54,7 @@ pub fn check_for_save_files(
             };
             let Some(image) = images.get(image_handle) else {
                 info!("Image was not yet loaded. Saving failed");
-                return
+                return;
             };
             match saver.save(image) {
                 Ok(_) => info!("Successfully saved image to {:?}", saver.path),
@@ -66,13 +73,25 @@ impl ImageSaver {
         let Some(path) = self.path.as_ref() else {
             return Err(SaveError::NoPathFound);
         };
-        let Ok(image) = image.clone().try_into_dynamic() else {
-            return Err(SaveError::WrongImageFormat);
+        let format = image.texture_descriptor.format;
+        if is_float(format) {
+            // png can't store floats, so saving them would lose precision
+            return Err(SaveError::FloatImage(format));
+        }
+        let error = if is_16_bit(format) {
+            // 16 bit maps are saved as 16 bit grayscale image to keep their precision
+            let size = image.texture_descriptor.size;
+            let buffer = ImageBuffer::from_fn(size.width, size.height, |x, y| {
+                let height = load_texel(image, UVec2::new(x, y)).unwrap_or(0.);
+                Luma([(height.clamp(0., 1.) * u16::MAX as f32).round() as u16])
+            });
+            buffer.save(path)
+        } else {
+            let Ok(image) = image.clone().try_into_dynamic() else {
+                return Err(SaveError::WrongImageFormat);
+            };
+            image.to_luma8().save(path)
         };
-        let image = image.to_luma8();
-
-        let error = image.save(path);
-        // Isn't best practise but allows us to not import the image crate as dep
         if let Err(e) = error {
             let message = e.to_string();
             if message.contains("The image format could not be determined") {
@@ -83,9 +102,30 @@ impl ImageSaver {
         Ok(())
     }
 }
+/// Whether the map stores 16 bits per channel
+fn is_16_bit(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::R16Unorm
+            | TextureFormat::Rg16Unorm
+            | TextureFormat::Rgba16Unorm
+            | TextureFormat::R16Uint
+            | TextureFormat::Rg16Uint
+            | TextureFormat::Rgba16Uint
+    )
+}
+/// Whether the map stores floats, which can't be saved as png
+fn is_float(format: TextureFormat) -> bool {
+    matches!(
+        format,
+        TextureFormat::R32Float | TextureFormat::Rg32Float | TextureFormat::Rgba32Float
+    )
+}
 #[derive(Debug)]
 pub enum SaveError {
     NoPathFound,
     ImageError(String),
     WrongImageFormat,
+    /// Float maps can't be saved without losing their precision
+    FloatImage(TextureFormat),
 }
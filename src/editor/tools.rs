@@ -1,4 +1,6 @@
-use bevy::prelude::Image;
+use bevy::prelude::{Image, UVec2};
+
+use crate::height_map::store_texel;
 /// The [`Eraser`] is used to erase the content of a map
 ///
 /// This is a very destructive tool and should be used carefully
@@ -8,13 +10,7 @@ pub struct Eraser;
 impl Eraser {
     pub fn erase(image: &mut Image) {
         // erase all pixels to black
-        let new_image = Image::new_fill(
-            image.texture_descriptor.size,
-            image.texture_descriptor.dimension,
-            &[0, 0, 0, 255],
-            image.texture_descriptor.format,
-        );
-        *image = new_image;
+        fill_height(image, 0.);
     }
 }
 /// The [`Filler`] is used to fill the content of a map with the maximum value
@@ -26,12 +22,15 @@ pub struct Filler;
 impl Filler {
     // fills all pixels with white
     pub fn fill(image: &mut Image) {
-        let new_image = Image::new_fill(
-            image.texture_descriptor.size,
-            image.texture_descriptor.dimension,
-            &[255, 255, 255, 255],
-            image.texture_descriptor.format,
-        );
-        *image = new_image;
+        fill_height(image, 1.);
+    }
+}
+/// Sets every pixel to the height while keeping the format of the image
+fn fill_height(image: &mut Image, height: f32) {
+    let size = image.texture_descriptor.size;
+    for y in 0..size.height {
+        for x in 0..size.width {
+            store_texel(image, UVec2::new(x, y), height);
+        }
     }
 }
@@ -0,0 +1,423 @@
+//! Contains the systems splitting a [`GrassGrid`] into chunks
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    render::{
+        primitives::Aabb,
+        render_resource::{Extent3d, TextureDimension},
+    },
+    utils::HashSet,
+};
+
+use crate::{
+    bundle::{GrassColor, GrassGrid, GrassGridChunk, WarblerHeight, WarblersBundle},
+    density_map::DensityMap,
+    height_map::HeightMap,
+};
+
+/// Spawns the chunks of each [`GrassGrid`] as children of its entity
+///
+/// The chunks are spawned again if the [`GrassGrid`] changed or one of its maps got loaded or modified.
+/// If the maps aren't loaded yet, the grid is split once they are available
+#[allow(clippy::type_complexity)]
+pub(crate) fn split_grass_grids(
+    mut commands: Commands,
+    changed: Query<Entity, Changed<GrassGrid>>,
+    grids: Query<(
+        Entity,
+        &GrassGrid,
+        &Handle<Mesh>,
+        &GrassColor,
+        Option<&Children>,
+    )>,
+    grid_chunks: Query<(), With<GrassGridChunk>>,
+    mut images: ResMut<Assets<Image>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+) {
+    let updated_images: HashSet<Handle<Image>> = image_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                Some(handle.clone_weak())
+            }
+            AssetEvent::Removed { .. } => None,
+        })
+        .collect();
+    for (entity, grid, mesh, color, children) in grids.iter() {
+        let height_texture = match &grid.height {
+            WarblerHeight::Texture(texture) => Some(texture),
+            WarblerHeight::Uniform(_) => None,
+        };
+        let maps = [
+            Some(&grid.density_map.density_map),
+            Some(&grid.height_map.height_map),
+            height_texture,
+        ];
+        let updated = maps
+            .iter()
+            .flatten()
+            .any(|map| updated_images.contains(*map));
+        if !changed.contains(entity) && !updated {
+            continue;
+        }
+        let chunks = grid.chunks.max(UVec2::ONE);
+        // the chunks are bounded by the pixels of the height map, so its chunks cover the same area as the whole map
+        let Some(reference) = images.get(&grid.height_map.height_map) else {
+            continue;
+        };
+        let reference = reference.texture_descriptor.size;
+        let columns = chunk_extents(reference.width, chunks.x);
+        let rows = chunk_extents(reference.height, chunks.y);
+        let split = |map: &Handle<Image>, border: u32| {
+            let Some(image) = images.get(map) else {
+                // the grid is split once the image is loaded
+                return None;
+            };
+            let split = split_image(image, &columns, &rows, border);
+            if split.is_none() {
+                warn!("Can't split the compressed image {map:?} of a grass grid");
+            }
+            split
+        };
+        let Some(density_maps) = split(&grid.density_map.density_map, 0) else {
+            continue;
+        };
+        // the height maps of neighbouring chunks overlap by the pixels the filter reads,
+        // so the interpolated terrain is seamless
+        let border = grid.height_map.filter.border();
+        let Some(height_maps) = split(&grid.height_map.height_map, border) else {
+            continue;
+        };
+        // the height texture is filtered like the height map, so it needs the same border
+        let heights = match height_texture {
+            Some(texture) => match split(texture, border) {
+                Some(heights) => Some(heights),
+                None => continue,
+            },
+            None => None,
+        };
+
+        // remove the chunks of the last split
+        for child in children.into_iter().flatten() {
+            if grid_chunks.contains(*child) {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+        let mut height_maps = height_maps.into_iter();
+        let mut heights = heights.map(|heights| heights.into_iter());
+        let mut cells = Vec::with_capacity(density_maps.len());
+        for (i, density_map) in density_maps.into_iter().enumerate() {
+            let cell = UVec2::new(i as u32 % chunks.x, i as u32 / chunks.x);
+            let (column, row) = (columns[cell.x as usize], rows[cell.y as usize]);
+            let offset = Vec2::new(column.0, row.0) * grid.size.xz();
+            let cell_size = Vec2::new(column.1 - column.0, row.1 - row.0) * grid.size.xz();
+            let height = match heights.as_mut().and_then(|heights| heights.next()) {
+                Some(texture) => WarblerHeight::Texture(images.add(texture)),
+                None => grid.height.clone(),
+            };
+            cells.push((
+                WarblersBundle {
+                    grass_mesh: mesh.clone(),
+                    density_map: DensityMap {
+                        density_map: images.add(density_map),
+                        ..grid.density_map.clone()
+                    },
+                    height_map: HeightMap {
+                        height_map: images.add(height_maps.next().unwrap()),
+                        filter: grid.height_map.filter,
+                        border,
+                    },
+                    height,
+                    grass_color: color.clone(),
+                    aabb: Aabb::from_min_max(
+                        Vec3::ZERO,
+                        Vec3::new(cell_size.x, grid.size.y, cell_size.y),
+                    ),
+                    spatial: SpatialBundle {
+                        transform: Transform::from_xyz(offset.x, 0., offset.y),
+                        ..default()
+                    },
+                },
+                GrassGridChunk { cell },
+            ));
+        }
+        commands.entity(entity).with_children(|parent| {
+            for cell in cells {
+                parent.spawn(cell);
+            }
+        });
+    }
+}
+
+/// Splits the image into a grid of images with the given extents of the columns and rows.
+///
+/// The extents are fractions of the image, which are rounded to the closest pixels.
+/// Each cell is padded by `border` pixels of its neighbours, so interpolating at the edge of a cell
+/// reads the same pixels as interpolating the whole image. At the edges of the image, its border pixels are repeated.
+/// The cells are returned row by row.
+/// Returns `None` if the image is compressed
+fn split_image(
+    image: &Image,
+    columns: &[(f32, f32)],
+    rows: &[(f32, f32)],
+    border: u32,
+) -> Option<Vec<Image>> {
+    let format = image.texture_descriptor.format;
+    let format_info = format.describe();
+    if format_info.block_dimensions != (1, 1) {
+        return None;
+    }
+    let pixel_size = format_info.block_size as usize;
+    let size = image.texture_descriptor.size;
+    let columns = pixel_bounds(columns, size.width);
+    let rows = pixel_bounds(rows, size.height);
+    // the pixels of the cell including its border, clamped to the image
+    let padded = |(start, end): (u32, u32), pixels: u32| {
+        let start = start as i64 - border as i64;
+        let end = end as i64 + border as i64;
+        (start..end).map(move |i| i.clamp(0, pixels as i64 - 1) as usize)
+    };
+    let mut images = Vec::with_capacity(columns.len() * rows.len());
+    for row in rows.iter().copied() {
+        for column in columns.iter().copied() {
+            let width = column.1 - column.0 + 2 * border;
+            let height = row.1 - row.0 + 2 * border;
+            let mut data = Vec::with_capacity((width * height) as usize * pixel_size);
+            for y in padded(row, size.height) {
+                for x in padded(column, size.width) {
+                    let start = (y * size.width as usize + x) * pixel_size;
+                    data.extend_from_slice(&image.data[start..start + pixel_size]);
+                }
+            }
+            let mut cell = Image::new(
+                Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                data,
+                format,
+            );
+            cell.sampler_descriptor = image.sampler_descriptor.clone();
+            cell.texture_descriptor.usage = image.texture_descriptor.usage;
+            images.push(cell);
+        }
+    }
+    Some(images)
+}
+/// Divides the pixels of one axis into the given amount of cells.
+///
+/// Each cell contains at least one pixel, even if the image is smaller than the amount of cells
+fn cell_bounds(pixels: u32, cells: u32) -> Vec<(u32, u32)> {
+    (0..cells)
+        .map(|i| {
+            let start = (i as u64 * pixels as u64 / cells as u64) as u32;
+            let end = ((i as u64 + 1) * pixels as u64 / cells as u64) as u32;
+            let start = start.min(pixels.saturating_sub(1));
+            (start, end.max(start + 1))
+        })
+        .collect()
+}
+/// Divides one axis of a map with the given amount of pixels into chunks, as fractions of the map.
+///
+/// The chunks end at the borders of the pixels, so each chunk covers exactly the area of its pixels,
+/// even if the resolution isn't divisible by the amount of chunks.
+/// If the map has less pixels than chunks, the axis is divided evenly
+fn chunk_extents(pixels: u32, chunks: u32) -> Vec<(f32, f32)> {
+    if pixels < chunks {
+        let chunks = chunks as f32;
+        return (0..chunks as u32)
+            .map(|i| (i as f32 / chunks, (i + 1) as f32 / chunks))
+            .collect();
+    }
+    cell_bounds(pixels, chunks)
+        .into_iter()
+        .map(|(start, end)| (start as f32 / pixels as f32, end as f32 / pixels as f32))
+        .collect()
+}
+/// The pixels of one axis closest to the given extents.
+///
+/// Each cell contains at least one pixel
+fn pixel_bounds(extents: &[(f32, f32)], pixels: u32) -> Vec<(u32, u32)> {
+    let pixel = |fraction: f32| (fraction * pixels as f32).round() as u32;
+    extents
+        .iter()
+        .map(|(start, end)| {
+            let start = pixel(*start).min(pixels.saturating_sub(1));
+            (start, pixel(*end).max(start + 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        prelude::*,
+        render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    };
+
+    use super::{cell_bounds, chunk_extents, pixel_bounds, split_image};
+    use crate::height_map::{load_texel, sample_height_map, HeightMap, HeightMapFilter};
+
+    /// An 8 bit image where each pixel has a different value
+    fn gradient(width: u32, height: u32) -> Image {
+        let data = (0..width * height)
+            .map(|i| ((i * 37 + i / width * 11) % 256) as u8)
+            .collect();
+        Image::new(
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::R8Unorm,
+        )
+    }
+    #[test]
+    fn cell_bounds_cover_all_pixels() {
+        assert_eq!(cell_bounds(10, 1), vec![(0, 10)]);
+        assert_eq!(cell_bounds(10, 2), vec![(0, 5), (5, 10)]);
+        assert_eq!(cell_bounds(10, 3), vec![(0, 3), (3, 6), (6, 10)]);
+        // every cell has at least one pixel
+        assert_eq!(cell_bounds(2, 3), vec![(0, 1), (0, 1), (1, 2)]);
+        assert_eq!(cell_bounds(1, 2), vec![(0, 1), (0, 1)]);
+    }
+    #[test]
+    fn chunk_extents_follow_the_pixels() {
+        assert_eq!(chunk_extents(10, 2), vec![(0., 0.5), (0.5, 1.)]);
+        assert_eq!(
+            chunk_extents(4, 3),
+            vec![(0., 0.25), (0.25, 0.5), (0.5, 1.)]
+        );
+        assert_eq!(pixel_bounds(&chunk_extents(7, 3), 7), cell_bounds(7, 3));
+        // maps with another resolution are split at the closest pixels
+        assert_eq!(
+            pixel_bounds(&chunk_extents(4, 3), 8),
+            vec![(0, 2), (2, 4), (4, 8)]
+        );
+        // maps with less pixels than chunks are divided evenly
+        assert_eq!(chunk_extents(1, 2), vec![(0., 0.5), (0.5, 1.)]);
+        assert_eq!(pixel_bounds(&chunk_extents(1, 2), 1), vec![(0, 1), (0, 1)]);
+    }
+    #[test]
+    fn split_without_border() {
+        let image = gradient(5, 3);
+        let cells = split_image(&image, &chunk_extents(5, 2), &[(0., 1.)], 0).unwrap();
+        assert_eq!(cells.len(), 2);
+        assert_eq!(cells[0].size(), Vec2::new(2., 3.));
+        assert_eq!(cells[1].size(), Vec2::new(3., 3.));
+        for y in 0..3 {
+            for x in 0..5 {
+                let (cell, cell_x) = if x < 2 {
+                    (&cells[0], x)
+                } else {
+                    (&cells[1], x - 2)
+                };
+                assert_eq!(
+                    load_texel(cell, UVec2::new(cell_x, y)),
+                    load_texel(&image, UVec2::new(x, y))
+                );
+            }
+        }
+    }
+    #[test]
+    fn split_with_border() {
+        let image = gradient(4, 4);
+        let extents = chunk_extents(4, 2);
+        let cells = split_image(&image, &extents, &extents, 2).unwrap();
+        assert_eq!(cells.len(), 4);
+        for (i, cell) in cells.iter().enumerate() {
+            assert_eq!(cell.size(), Vec2::new(6., 6.));
+            let origin = IVec2::new(i as i32 % 2, i as i32 / 2) * 2 - 2;
+            for y in 0..6 {
+                for x in 0..6 {
+                    // the pixels outside of the image are clamped to its edges
+                    let source = (origin + IVec2::new(x, y)).clamp(IVec2::ZERO, IVec2::splat(3));
+                    assert_eq!(
+                        load_texel(cell, UVec2::new(x as u32, y as u32)),
+                        load_texel(&image, source.as_uvec2())
+                    );
+                }
+            }
+        }
+    }
+    #[test]
+    fn split_keeps_format() {
+        let mut image = gradient(4, 2);
+        image.texture_descriptor.format = TextureFormat::Rg8Unorm;
+        image.texture_descriptor.size.width = 2;
+        let cells = split_image(&image, &chunk_extents(2, 2), &[(0., 1.)], 1).unwrap();
+        assert_eq!(cells[0].texture_descriptor.format, TextureFormat::Rg8Unorm);
+        // the red and green channels of a pixel are copied together
+        assert_eq!(
+            cells[0].data[..6],
+            [
+                image.data[0],
+                image.data[1],
+                image.data[0],
+                image.data[1],
+                image.data[2],
+                image.data[3]
+            ]
+        );
+    }
+    #[test]
+    fn sampling_is_seamless() {
+        // the resolution isn't divisible by the amount of chunks
+        let image = gradient(7, 5);
+        let grid_size = Vec2::new(10., 6.);
+        let columns = chunk_extents(7, 3);
+        let rows = chunk_extents(5, 2);
+        for filter in [
+            HeightMapFilter::Nearest,
+            HeightMapFilter::Bilinear,
+            HeightMapFilter::Bicubic,
+        ] {
+            let whole = HeightMap {
+                filter,
+                ..default()
+            };
+            let split = HeightMap {
+                filter,
+                border: filter.border(),
+                ..default()
+            };
+            let chunks = split_image(&image, &columns, &rows, split.border).unwrap();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let (column, row) = (columns[i % 3], rows[i / 3]);
+                // the area of the chunk in world space
+                let min = Vec2::new(column.0, row.0) * grid_size;
+                let max = Vec2::new(column.1, row.1) * grid_size;
+                // samples the edges and the inside of the chunk
+                for u in [0., 0.01, 0.3, 0.6, 0.99, 1.] {
+                    for v in [0., 0.4, 1.] {
+                        let uv = Vec2::new(u, v);
+                        // the closest pixel is ambiguous at the edges, which lie between two pixels
+                        let at_edge = uv.min_element() == 0. || uv.max_element() == 1.;
+                        if filter == HeightMapFilter::Nearest && at_edge {
+                            continue;
+                        }
+                        let world = min + (max - min) * uv;
+                        let position = split.pixel_position(chunk.size(), uv);
+                        let expected = whole.pixel_position(image.size(), world / grid_size);
+                        let sampled = sample_height_map(chunk, filter, position).unwrap();
+                        let expected = sample_height_map(&image, filter, expected).unwrap();
+                        assert!(
+                            (sampled - expected).abs() < 1e-4,
+                            "{filter:?} at {world} in chunk {i}: {sampled} != {expected}"
+                        );
+                    }
+                }
+            }
+        }
+        // the chunks cover the grid without gaps or overlaps
+        for extents in [&columns, &rows] {
+            assert_eq!(extents.first().unwrap().0, 0.);
+            assert_eq!(extents.last().unwrap().1, 1.);
+            assert!(extents.windows(2).all(|pair| pair[0].1 == pair[1].0));
+        }
+    }
+}
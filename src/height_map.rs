@@ -2,8 +2,13 @@
 use bevy::{
     asset::Handle,
     ecs::{component::Component, query::QueryItem},
-    reflect::Reflect,
-    render::{extract_component::ExtractComponent, texture::Image},
+    math::{IVec2, UVec2, Vec2, Vec4},
+    reflect::{FromReflect, Reflect},
+    render::{
+        extract_component::ExtractComponent,
+        render_resource::TextureFormat,
+        texture::{Image, DEFAULT_IMAGE_HANDLE},
+    },
 };
 
 /// The height map defining the y position of the grass blades.
@@ -16,14 +21,78 @@ use bevy::{
 /// The height map texture will be scaled over all grassblades.
 /// It is recommended to use a rather small heightmap if you don't need much detail
 ///
+/// Besides 8 bit images, 16 bit images (like 16 bit pngs) and `R32Float` images are supported.
+/// 16 bit images are converted to `R32Float` after loading, so no precision is lost on any device.
+///
 /// For a simple example, take a look at the `load_grass` example
 #[derive(Reflect, Clone, Component)]
 pub struct HeightMap {
     pub height_map: Handle<Image>,
+    /// How the height map is sampled between its pixels
+    pub filter: HeightMapFilter,
+    /// The amount of pixels at each edge of the height map that lie outside of the chunk.
+    ///
+    /// They are only read when interpolating near the edges of the chunk,
+    /// which lets the chunks of a [`GrassGrid`](crate::bundle::GrassGrid) share their border pixels
+    pub border: u32,
+}
+impl Default for HeightMap {
+    fn default() -> Self {
+        DEFAULT_IMAGE_HANDLE.typed().into()
+    }
 }
 impl From<Handle<Image>> for HeightMap {
     fn from(value: Handle<Image>) -> Self {
-        HeightMap { height_map: value }
+        HeightMap {
+            height_map: value,
+            filter: HeightMapFilter::default(),
+            border: 0,
+        }
+    }
+}
+/// The interpolation used to sample a [`HeightMap`]
+///
+/// Small height maps stretched over large chunks produce visible steps when using [`HeightMapFilter::Nearest`],
+/// which the other filters smooth out.
+/// The [`GrassQuery`](crate::query::GrassQuery) uses the same interpolation as the shader.
+#[derive(Reflect, FromReflect, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeightMapFilter {
+    /// Uses the height of the closest pixel
+    #[default]
+    Nearest,
+    /// Interpolates linearly between the four closest pixels
+    Bilinear,
+    /// Interpolates smoothly between the sixteen closest pixels using Catmull-Rom splines.
+    ///
+    /// Note that the height might slightly overshoot between pixels with very different heights
+    Bicubic,
+}
+impl HeightMapFilter {
+    /// The value identifying the filter in the shader
+    pub(crate) fn shader_id(&self) -> u32 {
+        match self {
+            HeightMapFilter::Nearest => 0,
+            HeightMapFilter::Bilinear => 1,
+            HeightMapFilter::Bicubic => 2,
+        }
+    }
+    /// The amount of pixels next to a position the filter reads in each direction
+    pub(crate) fn border(&self) -> u32 {
+        match self {
+            HeightMapFilter::Nearest => 0,
+            HeightMapFilter::Bilinear => 1,
+            HeightMapFilter::Bicubic => 2,
+        }
+    }
+}
+impl HeightMap {
+    /// The position in pixels of the height map image with the given size
+    /// at a position in the chunk given between `0.` and `1.`, skipping the border.
+    ///
+    /// Mirrors the texture position computed by `sample_chunk_texture` in the grass shader
+    pub(crate) fn pixel_position(&self, image_size: Vec2, uv: Vec2) -> Vec2 {
+        let border = Vec2::splat(self.border as f32);
+        uv * (image_size - 2. * border).max(Vec2::ONE) + border
     }
 }
 impl ExtractComponent for HeightMap {
@@ -36,6 +105,417 @@ impl ExtractComponent for HeightMap {
     fn extract_component(item: QueryItem<'_, Self::Query>) -> Option<Self::Out> {
         Some(HeightMap {
             height_map: item.height_map.clone_weak(),
+            filter: item.filter,
+            border: item.border,
         })
     }
 }
+
+/// Reads the red channel of a texel like `textureLoad` does in the shader.
+///
+/// Normalized formats are mapped between `0.` and `1.` and srgb textures are converted to linear.
+/// Returns `None` if the position is outside of the image or the format isn't supported
+pub(crate) fn load_texel(image: &Image, position: UVec2) -> Option<f32> {
+    let size = image.texture_descriptor.size;
+    if position.x >= size.width || position.y >= size.height {
+        return None;
+    }
+    let format = image.texture_descriptor.format;
+    let info = format.describe();
+    if info.block_dimensions != (1, 1) {
+        return None;
+    }
+    let pixel_size = info.block_size as usize;
+    let start = (position.y as usize * size.width as usize + position.x as usize) * pixel_size;
+    let texel = image.data.get(start..start + pixel_size)?;
+    let value = match format {
+        TextureFormat::R8Unorm | TextureFormat::Rg8Unorm | TextureFormat::Rgba8Unorm => {
+            texel[0] as f32 / u8::MAX as f32
+        }
+        TextureFormat::Bgra8Unorm => texel[2] as f32 / u8::MAX as f32,
+        TextureFormat::Rgba8UnormSrgb => srgb_to_linear(texel[0] as f32 / u8::MAX as f32),
+        TextureFormat::Bgra8UnormSrgb => srgb_to_linear(texel[2] as f32 / u8::MAX as f32),
+        // 16 bit pngs are loaded as uint textures by bevy, which are converted to floats by `convert_height_textures`
+        TextureFormat::R16Unorm
+        | TextureFormat::Rg16Unorm
+        | TextureFormat::Rgba16Unorm
+        | TextureFormat::R16Uint
+        | TextureFormat::Rg16Uint
+        | TextureFormat::Rgba16Uint => {
+            u16::from_le_bytes([texel[0], texel[1]]) as f32 / u16::MAX as f32
+        }
+        TextureFormat::R32Float | TextureFormat::Rg32Float | TextureFormat::Rgba32Float => {
+            f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]])
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+/// Writes a height to all color channels of a texel, the inverse of [`load_texel`].
+///
+/// Normalized formats are clamped between `0.` and `1.` and srgb textures are encoded again,
+/// so editing a map doesn't distort the heights of the unchanged texels.
+/// Returns `false` if the position is outside of the image or the format isn't supported
+#[cfg(any(feature = "editor", test))]
+pub(crate) fn store_texel(image: &mut Image, position: UVec2, value: f32) -> bool {
+    let size = image.texture_descriptor.size;
+    if position.x >= size.width || position.y >= size.height {
+        return false;
+    }
+    let format = image.texture_descriptor.format;
+    let info = format.describe();
+    if info.block_dimensions != (1, 1) {
+        return false;
+    }
+    let pixel_size = info.block_size as usize;
+    let start = (position.y as usize * size.width as usize + position.x as usize) * pixel_size;
+    let Some(texel) = image.data.get_mut(start..start + pixel_size) else {
+        return false;
+    };
+    let unorm8 = |value: f32| (value.clamp(0., 1.) * u8::MAX as f32).round() as u8;
+    let unorm16 =
+        |value: f32| ((value.clamp(0., 1.) * u16::MAX as f32).round() as u16).to_le_bytes();
+    match format {
+        TextureFormat::R8Unorm | TextureFormat::Rg8Unorm => texel[0] = unorm8(value),
+        TextureFormat::Rgba8Unorm | TextureFormat::Bgra8Unorm => {
+            texel[..3].fill(unorm8(value));
+        }
+        TextureFormat::Rgba8UnormSrgb | TextureFormat::Bgra8UnormSrgb => {
+            texel[..3].fill(unorm8(linear_to_srgb(value.clamp(0., 1.))));
+        }
+        TextureFormat::R16Unorm
+        | TextureFormat::Rg16Unorm
+        | TextureFormat::R16Uint
+        | TextureFormat::Rg16Uint => texel[..2].copy_from_slice(&unorm16(value)),
+        TextureFormat::Rgba16Unorm | TextureFormat::Rgba16Uint => {
+            let bytes = unorm16(value);
+            for channel in texel[..6].chunks_exact_mut(2) {
+                channel.copy_from_slice(&bytes);
+            }
+        }
+        TextureFormat::R32Float | TextureFormat::Rg32Float => {
+            texel[..4].copy_from_slice(&value.to_le_bytes());
+        }
+        TextureFormat::Rgba32Float => {
+            for channel in texel[..12].chunks_exact_mut(4) {
+                channel.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+/// Converts height textures which can't be loaded as float in the shader to [`TextureFormat::R32Float`].
+///
+/// Bevy loads 16 bit images as uint textures and 16 bit normalized textures require a feature,
+/// which isn't available on every device. Both are converted without losing precision.
+/// Returns `None` if the format can already be used by the shader
+pub(crate) fn to_float_height_texture(image: &Image) -> Option<Image> {
+    match image.texture_descriptor.format {
+        TextureFormat::R16Unorm
+        | TextureFormat::Rg16Unorm
+        | TextureFormat::Rgba16Unorm
+        | TextureFormat::R16Uint
+        | TextureFormat::Rg16Uint
+        | TextureFormat::Rgba16Uint => (),
+        _ => return None,
+    }
+    let size = image.texture_descriptor.size;
+    let mut data = Vec::with_capacity(size.width as usize * size.height as usize * 4);
+    for y in 0..size.height {
+        for x in 0..size.width {
+            let height = load_texel(image, UVec2::new(x, y)).unwrap_or(0.);
+            data.extend_from_slice(&height.to_le_bytes());
+        }
+    }
+    let mut converted = Image::new(
+        size,
+        image.texture_descriptor.dimension,
+        data,
+        TextureFormat::R32Float,
+    );
+    converted.sampler_descriptor = image.sampler_descriptor.clone();
+    Some(converted)
+}
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+/// Adds a value to the height of a texel in the encoding the image is stored in.
+///
+/// Srgb textures don't store the heights evenly spaced,
+/// so adding the value to the linear height would round small steps back to the same byte in parts of the range.
+/// Returns `false` if the position is outside of the image or the format isn't supported
+#[cfg(any(feature = "editor", test))]
+pub(crate) fn add_to_texel(image: &mut Image, position: UVec2, value: f32) -> bool {
+    let Some(height) = load_texel(image, position) else {
+        return false;
+    };
+    let height = if image.texture_descriptor.format.describe().srgb {
+        srgb_to_linear((linear_to_srgb(height) + value).clamp(0., 1.))
+    } else {
+        height + value
+    };
+    store_texel(image, position, height)
+}
+#[cfg(any(feature = "editor", test))]
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Samples the height map between `0.` and `1.` at a position given in pixels,
+/// like `sample_chunk_texture` in the grass shader.
+///
+/// Pixels outside of the image are clamped to its border.
+/// Returns `None` if the format of the image isn't supported
+pub(crate) fn sample_height_map(
+    image: &Image,
+    filter: HeightMapFilter,
+    position: Vec2,
+) -> Option<f32> {
+    let size = image.texture_descriptor.size;
+    let max = IVec2::new(size.width as i32 - 1, size.height as i32 - 1).max(IVec2::ZERO);
+    let load = |texel: IVec2| load_texel(image, texel.clamp(IVec2::ZERO, max).as_uvec2());
+    match filter {
+        HeightMapFilter::Nearest => load(position.as_ivec2()),
+        HeightMapFilter::Bilinear => {
+            // pixel centers lie in the middle of the pixels
+            let position = position - 0.5;
+            let texel = position.floor().as_ivec2();
+            let fraction = position - position.floor();
+            let bottom = lerp(load(texel)?, load(texel + IVec2::new(1, 0))?, fraction.x);
+            let top = lerp(
+                load(texel + IVec2::new(0, 1))?,
+                load(texel + IVec2::new(1, 1))?,
+                fraction.x,
+            );
+            Some(lerp(bottom, top, fraction.y))
+        }
+        HeightMapFilter::Bicubic => {
+            let position = position - 0.5;
+            let texel = position.floor().as_ivec2();
+            let fraction = position - position.floor();
+            let weights_x = catmull_rom_weights(fraction.x);
+            let weights_y = catmull_rom_weights(fraction.y);
+            let mut height = 0.;
+            for y in 0..4 {
+                let mut row = 0.;
+                for x in 0..4 {
+                    row += load(texel + IVec2::new(x - 1, y - 1))? * weights_x[x as usize];
+                }
+                height += row * weights_y[y as usize];
+            }
+            Some(height)
+        }
+    }
+}
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+/// The weights of the four pixels around a position for Catmull-Rom interpolation
+fn catmull_rom_weights(t: f32) -> Vec4 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    Vec4::new(
+        -0.5 * t3 + t2 - 0.5 * t,
+        1.5 * t3 - 2.5 * t2 + 1.,
+        -1.5 * t3 + 2. * t2 + 0.5 * t,
+        0.5 * t3 - 0.5 * t2,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        math::{UVec2, Vec2},
+        render::{
+            render_resource::{Extent3d, TextureDimension, TextureFormat},
+            texture::Image,
+        },
+    };
+
+    use super::{
+        add_to_texel, load_texel, sample_height_map, store_texel, to_float_height_texture,
+        HeightMapFilter,
+    };
+
+    fn image(width: u32, height: u32, format: TextureFormat) -> Image {
+        let pixel_size = format.describe().block_size as usize;
+        Image::new(
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            vec![0; width as usize * height as usize * pixel_size],
+            format,
+        )
+    }
+    /// A float image with the given heights in its first row, repeated in every row
+    fn float_image(heights: &[f32], rows: u32) -> Image {
+        let mut image = image(heights.len() as u32, rows, TextureFormat::R32Float);
+        for y in 0..rows {
+            for (x, height) in heights.iter().enumerate() {
+                assert!(store_texel(&mut image, UVec2::new(x as u32, y), *height));
+            }
+        }
+        image
+    }
+    #[test]
+    fn round_trip() {
+        // the formats with their precision
+        let formats = [
+            (TextureFormat::R8Unorm, 0.5 / 255.),
+            (TextureFormat::Rgba8Unorm, 0.5 / 255.),
+            (TextureFormat::Rgba8UnormSrgb, 0.01),
+            (TextureFormat::R16Unorm, 0.5 / 65535.),
+            (TextureFormat::R16Uint, 0.5 / 65535.),
+            (TextureFormat::Rgba16Uint, 0.5 / 65535.),
+            (TextureFormat::R32Float, 0.),
+            (TextureFormat::Rgba32Float, 0.),
+        ];
+        for (format, precision) in formats {
+            let mut image = image(3, 2, format);
+            for (i, height) in [0., 0.1, 0.25, 0.5, 0.9, 1.].into_iter().enumerate() {
+                let position = UVec2::new(i as u32 % 3, i as u32 / 3);
+                assert!(store_texel(&mut image, position, height));
+                let loaded = load_texel(&image, position).unwrap();
+                assert!(
+                    (loaded - height).abs() <= precision + 1e-6,
+                    "{format:?}: {loaded} != {height}"
+                );
+            }
+            assert_eq!(load_texel(&image, UVec2::new(3, 0)), None);
+            assert!(!store_texel(&mut image, UVec2::new(0, 2), 0.5));
+        }
+        // normalized formats are clamped, floats aren't
+        let mut unorm = image(1, 1, TextureFormat::R8Unorm);
+        store_texel(&mut unorm, UVec2::ZERO, 1.5);
+        assert_eq!(load_texel(&unorm, UVec2::ZERO), Some(1.));
+        let mut float = image(1, 1, TextureFormat::R32Float);
+        store_texel(&mut float, UVec2::ZERO, 1.5);
+        assert_eq!(load_texel(&float, UVec2::ZERO), Some(1.5));
+
+        let mut unsupported = image(1, 1, TextureFormat::R8Snorm);
+        assert_eq!(load_texel(&unsupported, UVec2::ZERO), None);
+        assert!(!store_texel(&mut unsupported, UVec2::ZERO, 0.5));
+    }
+    #[test]
+    fn srgb_is_decoded() {
+        let mut image = image(1, 1, TextureFormat::Rgba8UnormSrgb);
+        image.data[0] = 188;
+        // 188 is about half the brightness in srgb
+        assert!((load_texel(&image, UVec2::ZERO).unwrap() - 0.5).abs() < 0.005);
+    }
+    #[test]
+    fn float_height_texture() {
+        let mut image = image(2, 2, TextureFormat::R16Uint);
+        for (i, height) in [0., 0.3, 0.7, 1.].into_iter().enumerate() {
+            store_texel(&mut image, UVec2::new(i as u32 % 2, i as u32 / 2), height);
+        }
+        let converted = to_float_height_texture(&image).unwrap();
+        assert_eq!(converted.texture_descriptor.format, TextureFormat::R32Float);
+        assert_eq!(converted.size(), image.size());
+        for y in 0..2 {
+            for x in 0..2 {
+                let position = UVec2::new(x, y);
+                assert_eq!(
+                    load_texel(&converted, position),
+                    load_texel(&image, position)
+                );
+            }
+        }
+        assert!(to_float_height_texture(&self::image(1, 1, TextureFormat::R8Unorm)).is_none());
+        assert!(to_float_height_texture(&self::image(1, 1, TextureFormat::R32Float)).is_none());
+    }
+    #[test]
+    fn sample_filters() {
+        let heights = [0., 0.2, 0.6, 1.];
+        let image = float_image(&heights, 2);
+        let sample = |filter, x: f32| sample_height_map(&image, filter, Vec2::new(x, 1.)).unwrap();
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+        for filter in [
+            HeightMapFilter::Nearest,
+            HeightMapFilter::Bilinear,
+            HeightMapFilter::Bicubic,
+        ] {
+            // all filters return the height of a pixel at its center
+            for (x, height) in heights.iter().enumerate() {
+                assert!(close(sample(filter, x as f32 + 0.5), *height), "{filter:?}");
+            }
+            // positions outside of the image are clamped to the border
+            assert!(close(sample(filter, -3.), 0.), "{filter:?}");
+            assert!(close(sample(filter, 10.), 1.), "{filter:?}");
+        }
+        // the outer half pixels are flat, except for the overshoot of the bicubic filter
+        for filter in [HeightMapFilter::Nearest, HeightMapFilter::Bilinear] {
+            assert!(close(sample(filter, 0.), 0.), "{filter:?}");
+            assert!(close(sample(filter, 4.), 1.), "{filter:?}");
+        }
+        assert!(close(sample(HeightMapFilter::Bicubic, 0.), -0.0125));
+        // nearest uses the pixel containing the position
+        assert!(close(sample(HeightMapFilter::Nearest, 1.), 0.2));
+        assert!(close(sample(HeightMapFilter::Nearest, 1.99), 0.2));
+        assert!(close(sample(HeightMapFilter::Nearest, 2.), 0.6));
+        // bilinear interpolates linearly between the centers
+        assert!(close(sample(HeightMapFilter::Bilinear, 1.), 0.1));
+        assert!(close(sample(HeightMapFilter::Bilinear, 2.), 0.4));
+        assert!(close(sample(HeightMapFilter::Bilinear, 2.25), 0.5));
+        // Catmull-Rom splines reproduce linear slopes exactly
+        let linear = float_image(&[0., 0.25, 0.5, 0.75, 1.], 1);
+        let sample = |x: f32| {
+            sample_height_map(&linear, HeightMapFilter::Bicubic, Vec2::new(x, 0.5)).unwrap()
+        };
+        assert!(close(sample(2.), 0.375));
+        assert!(close(sample(2.75), 0.5625));
+        // and are smooth around a peak
+        let peak = float_image(&[0., 0., 1., 0., 0.], 1);
+        let bicubic =
+            sample_height_map(&peak, HeightMapFilter::Bicubic, Vec2::new(2., 0.5)).unwrap();
+        let bilinear =
+            sample_height_map(&peak, HeightMapFilter::Bilinear, Vec2::new(2., 0.5)).unwrap();
+        assert!(close(bicubic, 0.5625));
+        assert!(close(bilinear, 0.5));
+    }
+    #[test]
+    fn sample_unsupported_format() {
+        let image = image(2, 2, TextureFormat::R8Snorm);
+        for filter in [
+            HeightMapFilter::Nearest,
+            HeightMapFilter::Bilinear,
+            HeightMapFilter::Bicubic,
+        ] {
+            assert_eq!(sample_height_map(&image, filter, Vec2::ONE), None);
+        }
+    }
+    #[test]
+    fn add_in_stored_encoding() {
+        let step = 1. / 255.;
+        for format in [TextureFormat::Rgba8UnormSrgb, TextureFormat::R8Unorm] {
+            let mut image = image(1, 1, format);
+            // every step of the brush changes the byte, in dark and bright areas
+            for byte in [0u8, 3, 20, 128, 250] {
+                image.data[0] = byte;
+                assert!(add_to_texel(&mut image, UVec2::ZERO, step));
+                assert_eq!(image.data[0], byte + 1, "{format:?}");
+                assert!(add_to_texel(&mut image, UVec2::ZERO, -step));
+                assert_eq!(image.data[0], byte, "{format:?}");
+            }
+            image.data[0] = 255;
+            assert!(add_to_texel(&mut image, UVec2::ZERO, step));
+            assert_eq!(image.data[0], 255);
+        }
+        let mut float = image(1, 1, TextureFormat::R32Float);
+        assert!(add_to_texel(&mut float, UVec2::ZERO, 0.001));
+        assert_eq!(load_texel(&float, UVec2::ZERO), Some(0.001));
+        assert!(!add_to_texel(&mut float, UVec2::ONE, step));
+    }
+}
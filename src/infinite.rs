@@ -0,0 +1,601 @@
+//! Streams procedurally generated grass chunks around a camera
+//!
+//! Add the [`InfiniteGrassPlugin`] and the [`InfiniteGrassTarget`] component to your camera.
+//! The grass is configured by the [`InfiniteGrass`] resource.
+//!
+//! # Example
+//! ```rust
+//! use bevy::prelude::*;
+//! use warbler_grass::infinite::{InfiniteGrass, InfiniteGrassPlugin, InfiniteGrassTarget};
+//!
+//! App::new()
+//!     .add_plugin(InfiniteGrassPlugin)
+//!     .insert_resource(InfiniteGrass {
+//!         chunk_size: 20.,
+//!         radius: 5,
+//!         ..default()
+//!     });
+//! // spawn your camera with the InfiniteGrassTarget component
+//! ```
+use std::sync::Arc;
+
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    render::{
+        primitives::Aabb,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+    utils::HashMap,
+};
+
+use crate::{
+    bundle::{GrassColor, WarblerHeight, WarblersBundle},
+    cutting::{clear_cut_mask, GrassCutMask},
+    density_map::DensityMap,
+    dithering::{DitheringGeneration, DitheringTask, GrassChunkReady},
+    height_map::HeightMap,
+    trample::TrampleMask,
+};
+
+/// A [`Plugin`] that keeps grass chunks around the entity with the [`InfiniteGrassTarget`] component.
+///
+/// The chunks are normal chunks spawned with the [`WarblersBundle`],
+/// which get recycled once they are too far away from the target.
+/// Their [`DensityMap`] and [`HeightMap`] are generated using the [`GrassGenerator`] of the [`InfiniteGrass`] resource.
+/// A recycled chunk is hidden until its blades are generated again at its new position.
+///
+/// The [`WarblersPlugin`](crate::warblers_plugin::WarblersPlugin) is still needed to render the grass
+pub struct InfiniteGrassPlugin;
+impl Plugin for InfiniteGrassPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InfiniteGrass>()
+            .init_resource::<DitheringGeneration>()
+            .add_systems((show_recycled_chunks, stream_infinite_grass).chain());
+    }
+}
+
+/// Marks the entity around which the grass of the [`InfiniteGrassPlugin`] is spawned
+///
+/// Usually, this is your camera. Only one entity should have this component
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct InfiniteGrassTarget;
+
+/// Marks a chunk spawned by the [`InfiniteGrassPlugin`]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InfiniteGrassChunk {
+    /// The cell of the chunk, chunks are placed at `cell * chunk_size` on the x,z plane
+    pub cell: IVec2,
+}
+
+/// Marks a recycled chunk which is hidden until it is dithered at its new position
+#[derive(Component, Clone, Copy, Debug)]
+struct RecycledChunk {
+    /// The generation of the last dithering started before the chunk was recycled.
+    ///
+    /// Ditherings up to this generation belong to the old maps of the chunk
+    outdated: u64,
+}
+
+/// The configuration of the grass spawned by the [`InfiniteGrassPlugin`]
+///
+/// All chunks are spawned again if the configuration changes
+#[derive(Resource, Clone)]
+pub struct InfiniteGrass {
+    /// Generates the density and height of the grass
+    pub generator: GrassGenerator,
+    /// The width and depth of a single chunk
+    pub chunk_size: f32,
+    /// The amount of chunks spawned in each direction around the target.
+    ///
+    /// Chunks are kept in a circle, so a radius of `r` keeps roughly `PI * r * r` chunks around the target
+    pub radius: u32,
+    /// The resolution of the generated maps of each chunk
+    pub resolution: u32,
+    /// The density of the grass, as used in the [`DensityMap`]
+    pub density: f32,
+    /// The height of the terrain at a height of `1` returned by the generator
+    pub max_height: f32,
+    /// The height of the grass blades
+    pub blade_height: f32,
+    /// The color of the grass
+    pub grass_color: GrassColor,
+    /// The maximal amount of chunks generated in a single frame
+    pub chunks_per_frame: usize,
+}
+impl Default for InfiniteGrass {
+    fn default() -> Self {
+        InfiniteGrass {
+            generator: GrassGenerator::default(),
+            chunk_size: 30.,
+            radius: 4,
+            resolution: 32,
+            density: 2.,
+            max_height: 5.,
+            blade_height: 1.,
+            grass_color: GrassColor::default(),
+            chunks_per_frame: 4,
+        }
+    }
+}
+
+/// The density and height of the grass at a position
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GrassSample {
+    /// The density of the grass between `0.` and `1.`
+    pub density: f32,
+    /// The height of the terrain between `0.` and `1.`
+    ///
+    /// Scaled by the `max_height` of the [`InfiniteGrass`]
+    pub height: f32,
+}
+
+/// Generates the grass of the [`InfiniteGrassPlugin`]
+#[derive(Clone)]
+pub enum GrassGenerator {
+    /// Generates the density and height using fractal value noise
+    Noise {
+        /// The seed of the noise
+        seed: u32,
+        /// The size of the features of the noise in world units
+        scale: f32,
+    },
+    /// Calls the function with the world position on the x,z plane for each pixel of the maps
+    Custom(Arc<dyn Fn(Vec2) -> GrassSample + Send + Sync>),
+}
+impl Default for GrassGenerator {
+    fn default() -> Self {
+        GrassGenerator::Noise {
+            seed: 0,
+            scale: 50.,
+        }
+    }
+}
+impl GrassGenerator {
+    /// Creates a generator from a function returning the [`GrassSample`] of a world position on the x,z plane
+    pub fn custom(generator: impl Fn(Vec2) -> GrassSample + Send + Sync + 'static) -> Self {
+        GrassGenerator::Custom(Arc::new(generator))
+    }
+    /// Returns the [`GrassSample`] at the world position on the x,z plane
+    pub fn sample(&self, position: Vec2) -> GrassSample {
+        match self {
+            GrassGenerator::Noise { seed, scale } => {
+                let position = position / scale.max(f32::EPSILON);
+                GrassSample {
+                    density: fractal_noise(position, *seed),
+                    // the terrain is smoother than the grass
+                    height: fractal_noise(position * 0.5, seed.wrapping_add(1)),
+                }
+            }
+            GrassGenerator::Custom(generator) => generator(position),
+        }
+    }
+}
+
+/// Shows the recycled chunks again once their blades are generated
+///
+/// Events of ditherings started before the chunk was recycled are ignored,
+/// since they stem from the old maps of the chunk
+fn show_recycled_chunks(
+    mut commands: Commands,
+    mut ready_events: EventReader<GrassChunkReady>,
+    mut chunks: Query<(&mut Visibility, &RecycledChunk)>,
+) {
+    for event in ready_events.iter() {
+        let Ok((mut visibility, recycled)) = chunks.get_mut(event.entity) else {
+            continue;
+        };
+        if event.generation > recycled.outdated {
+            *visibility = Visibility::Inherited;
+            commands.entity(event.entity).remove::<RecycledChunk>();
+        }
+    }
+}
+
+/// Spawns the missing chunks around the target and recycles the chunks which are too far away
+#[allow(clippy::type_complexity)]
+fn stream_infinite_grass(
+    mut commands: Commands,
+    config: Res<InfiniteGrass>,
+    targets: Query<&GlobalTransform, With<InfiniteGrassTarget>>,
+    chunks: Query<(
+        Entity,
+        &InfiniteGrassChunk,
+        &DensityMap,
+        &HeightMap,
+        Option<&TrampleMask>,
+        Option<&GrassCutMask>,
+    )>,
+    mut transforms: Query<(&mut Transform, &mut Visibility), With<InfiniteGrassChunk>>,
+    mut images: ResMut<Assets<Image>>,
+    generation: Res<DitheringGeneration>,
+) {
+    if config.is_changed() {
+        // the maps and aabbs of all chunks might be outdated
+        for (entity, ..) in chunks.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        if !config.is_added() {
+            return;
+        }
+    }
+    let Some(target) = targets.iter().next() else {
+        return;
+    };
+    let chunk_size = config.chunk_size.max(f32::EPSILON);
+    let center = (target.translation().xz() / chunk_size).floor().as_ivec2();
+    let existing: HashMap<IVec2, Entity> = chunks
+        .iter()
+        .map(|(entity, chunk, ..)| (chunk.cell, entity))
+        .collect();
+    let mut missing = missing_cells(center, config.radius, &existing);
+    let complete = missing.len() <= config.chunks_per_frame;
+    missing.truncate(config.chunks_per_frame);
+
+    let mut unused = chunks
+        .iter()
+        .filter(|(_, chunk, ..)| !is_in_range(chunk.cell, center, config.radius));
+    for cell in missing {
+        let (density_map, height_map) = generate_maps(&config, cell);
+        let translation = Vec3::new(cell.x as f32, 0., cell.y as f32) * chunk_size;
+        if let Some((entity, _, density, height, trample_mask, cut_mask)) = unused.next() {
+            // the chunk is moved and gets new maps, which lets it dither again
+            if let Some(image) = images.get_mut(&density.density_map) {
+                *image = density_map;
+            }
+            if let Some(image) = images.get_mut(&height.height_map) {
+                *image = height_map;
+            }
+            // the trails and cuts belong to the old position of the chunk
+            if let Some(image) = trample_mask.and_then(|mask| images.get_mut(&mask.mask)) {
+                image.data.fill(0);
+            }
+            if let Some(image) = cut_mask.and_then(|mask| images.get_mut(&mask.mask)) {
+                clear_cut_mask(image);
+            }
+            if let Ok((mut transform, mut visibility)) = transforms.get_mut(entity) {
+                transform.translation = translation;
+                // the old blades would be drawn at the new position until the chunk is dithered again
+                *visibility = Visibility::Hidden;
+            }
+            commands
+                .entity(entity)
+                .insert((
+                    InfiniteGrassChunk { cell },
+                    RecycledChunk {
+                        outdated: generation.0,
+                    },
+                ))
+                // a running dithering of the old maps must not show the chunk
+                .remove::<DitheringTask>();
+            continue;
+        }
+        commands.spawn((
+            WarblersBundle {
+                density_map: DensityMap {
+                    density_map: images.add(density_map),
+                    density: config.density,
+                    ..default()
+                },
+                height_map: HeightMap {
+                    height_map: images.add(height_map),
+                    ..default()
+                },
+                height: WarblerHeight::Uniform(config.blade_height),
+                grass_color: config.grass_color.clone(),
+                aabb: Aabb::from_min_max(
+                    Vec3::ZERO,
+                    Vec3::new(chunk_size, config.max_height, chunk_size),
+                ),
+                spatial: SpatialBundle {
+                    transform: Transform::from_translation(translation),
+                    ..default()
+                },
+                ..default()
+            },
+            InfiniteGrassChunk { cell },
+        ));
+    }
+    // the chunks which are left are only removed once all cells are filled,
+    // since they might be recycled in the next frames
+    if complete {
+        for (entity, ..) in unused {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Checks whether the cell is within the radius around the center cell
+fn is_in_range(cell: IVec2, center: IVec2, radius: u32) -> bool {
+    let x = cell.x as i64 - center.x as i64;
+    let z = cell.y as i64 - center.y as i64;
+    x * x + z * z <= radius as i64 * radius as i64
+}
+/// The cells in range of the center which have no chunk yet, the closest cells first
+fn missing_cells(center: IVec2, radius: u32, existing: &HashMap<IVec2, Entity>) -> Vec<IVec2> {
+    let radius = radius as i32;
+    let mut missing: Vec<IVec2> = (-radius..=radius)
+        .flat_map(|x| (-radius..=radius).map(move |z| center + IVec2::new(x, z)))
+        .filter(|cell| is_in_range(*cell, center, radius as u32) && !existing.contains_key(cell))
+        .collect();
+    missing.sort_by_key(|cell| {
+        let offset = *cell - center;
+        offset.dot(offset)
+    });
+    missing
+}
+
+/// Generates the density and height map of the chunk in the given cell
+fn generate_maps(config: &InfiniteGrass, cell: IVec2) -> (Image, Image) {
+    let resolution = config.resolution.max(1);
+    let pixel_size = config.chunk_size / resolution as f32;
+    let origin = cell.as_vec2() * config.chunk_size;
+    let mut density = Vec::with_capacity((resolution * resolution) as usize);
+    let mut height = Vec::with_capacity((resolution * resolution) as usize);
+    for y in 0..resolution {
+        for x in 0..resolution {
+            // samples the center of each pixel
+            let position = origin + (Vec2::new(x as f32, y as f32) + 0.5) * pixel_size;
+            let sample = config.generator.sample(position);
+            density.push((sample.density.clamp(0., 1.) * 255.) as u8);
+            height.push((sample.height.clamp(0., 1.) * 255.) as u8);
+        }
+    }
+    let size = Extent3d {
+        width: resolution,
+        height: resolution,
+        depth_or_array_layers: 1,
+    };
+    (
+        Image::new(size, TextureDimension::D2, density, TextureFormat::R8Unorm),
+        Image::new(size, TextureDimension::D2, height, TextureFormat::R8Unorm),
+    )
+}
+
+/// Sums up multiple octaves of value noise, returns a value between `0.` and `1.`
+fn fractal_noise(position: Vec2, seed: u32) -> f32 {
+    let mut value = 0.;
+    let mut amplitude = 0.5;
+    let mut frequency = 1.;
+    for octave in 0..4 {
+        value += value_noise(position * frequency, seed.wrapping_add(octave)) * amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.;
+    }
+    value / (1. - amplitude * 2.)
+}
+/// Interpolates random values at the corners of a unit grid smoothly
+fn value_noise(position: Vec2, seed: u32) -> f32 {
+    let cell = position.floor();
+    let fraction = position - cell;
+    let smooth = fraction * fraction * (3. - 2. * fraction);
+    let cell = cell.as_ivec2();
+    let corner = |x: i32, y: i32| hash(cell.x.wrapping_add(x), cell.y.wrapping_add(y), seed);
+    let bottom = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * smooth.x;
+    let top = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * smooth.x;
+    bottom + (top - bottom) * smooth.y
+}
+/// Hashes the grid position to a value between `0.` and `1.`
+fn hash(x: i32, y: i32, seed: u32) -> f32 {
+    let mut h = (x as u32)
+        .wrapping_mul(0x8da6_b343)
+        .wrapping_add((y as u32).wrapping_mul(0xd816_3841))
+        .wrapping_add(seed.wrapping_mul(0xcb1a_b31f));
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297a_2d39);
+    h ^= h >> 15;
+    h as f32 / u32::MAX as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{asset::AssetPlugin, prelude::*, utils::HashMap};
+
+    use super::{
+        generate_maps, is_in_range, missing_cells, GrassGenerator, GrassSample, InfiniteGrass,
+        InfiniteGrassChunk, InfiniteGrassPlugin, InfiniteGrassTarget, RecycledChunk,
+    };
+    use crate::{cutting::GrassCutMask, dithering::GrassChunkReady, trample::TrampleMask};
+
+    #[test]
+    fn noise_generator() {
+        let generator = GrassGenerator::Noise {
+            seed: 3,
+            scale: 20.,
+        };
+        let other_seed = GrassGenerator::Noise {
+            seed: 4,
+            scale: 20.,
+        };
+        let mut differs = false;
+        for i in 0..500 {
+            let position = Vec2::new(i as f32 * 7.3 - 1000., i as f32 * -3.1 + 200.);
+            let sample = generator.sample(position);
+            assert!((0. ..=1.).contains(&sample.density));
+            assert!((0. ..=1.).contains(&sample.height));
+            // the same seed always generates the same grass
+            assert_eq!(sample, generator.sample(position));
+            differs |= sample != other_seed.sample(position);
+            // the noise is continuous
+            let next = generator.sample(position + Vec2::splat(0.01));
+            assert!((next.density - sample.density).abs() < 0.05);
+            assert!((next.height - sample.height).abs() < 0.05);
+        }
+        assert!(differs);
+    }
+    #[test]
+    fn maps_sample_pixel_centers() {
+        let config = InfiniteGrass {
+            generator: GrassGenerator::custom(|position| GrassSample {
+                density: position.x / 100.,
+                height: position.y / 100.,
+            }),
+            chunk_size: 20.,
+            resolution: 4,
+            ..default()
+        };
+        let (density, height) = generate_maps(&config, IVec2::new(2, -3));
+        assert_eq!(density.size(), Vec2::splat(4.));
+        assert_eq!(height.size(), Vec2::splat(4.));
+        // the chunk starts at (40, -60) and each pixel is 5 units wide
+        for y in 0..4 {
+            for x in 0..4 {
+                let i = y * 4 + x;
+                let expected = (40. + (x as f32 + 0.5) * 5.) / 100. * 255.;
+                assert_eq!(density.data[i], expected as u8);
+                // negative heights are clamped
+                assert_eq!(height.data[i], 0);
+            }
+        }
+    }
+    #[test]
+    fn ring_of_cells() {
+        assert!(is_in_range(IVec2::new(3, 4), IVec2::ZERO, 5));
+        assert!(!is_in_range(IVec2::new(3, 5), IVec2::ZERO, 5));
+        assert!(is_in_range(IVec2::new(-8, 2), IVec2::new(-10, 2), 2));
+        // far away cells don't overflow
+        let far = IVec2::new(100_000, 0);
+        assert!(!is_in_range(far, -far, 1000));
+        assert!(is_in_range(far, -far, 200_000));
+
+        let existing = HashMap::default();
+        for (radius, count) in [(0, 1), (1, 5), (2, 13), (3, 29)] {
+            assert_eq!(missing_cells(IVec2::ZERO, radius, &existing).len(), count);
+        }
+        let center = IVec2::new(5, -2);
+        let mut existing = HashMap::default();
+        existing.insert(center, Entity::from_raw(0));
+        existing.insert(center + IVec2::X, Entity::from_raw(1));
+        let missing = missing_cells(center, 2, &existing);
+        assert_eq!(missing.len(), 11);
+        assert!(!missing.contains(&center));
+        assert!(!missing.contains(&(center + IVec2::X)));
+        // the closest cells come first
+        let distances: Vec<i32> = missing
+            .iter()
+            .map(|cell| (*cell - center).dot(*cell - center))
+            .collect();
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+    #[test]
+    fn recycled_chunks_are_hidden_until_ready() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<Image>()
+            .add_event::<GrassChunkReady>()
+            .add_plugin(InfiniteGrassPlugin)
+            .insert_resource(InfiniteGrass {
+                chunk_size: 10.,
+                radius: 1,
+                resolution: 2,
+                chunks_per_frame: 10,
+                ..default()
+            });
+        let target = app
+            .world
+            .spawn((GlobalTransform::default(), InfiniteGrassTarget))
+            .id();
+        app.update();
+        let chunks = |app: &mut App| {
+            let mut chunks: Vec<(Entity, IVec2, Visibility, bool)> = app
+                .world
+                .query::<(
+                    Entity,
+                    &InfiniteGrassChunk,
+                    &Visibility,
+                    Option<&RecycledChunk>,
+                )>()
+                .iter(&app.world)
+                .map(|(entity, chunk, visibility, recycled)| {
+                    (entity, chunk.cell, *visibility, recycled.is_some())
+                })
+                .collect();
+            chunks.sort_by_key(|(_, cell, ..)| (cell.x, cell.y));
+            chunks
+        };
+        let spawned = chunks(&mut app);
+        assert_eq!(spawned.len(), 5);
+        assert!(spawned
+            .iter()
+            .all(|(_, _, visibility, recycled)| *visibility == Visibility::Inherited && !recycled));
+        // all chunks are trampled and cut
+        for (entity, ..) in &spawned {
+            let mut images = app.world.resource_mut::<Assets<Image>>();
+            let trample_mask = TrampleMask::new(&mut images, UVec2::splat(2));
+            images.get_mut(&trample_mask.mask).unwrap().data.fill(1);
+            let cut_mask = GrassCutMask::new(&mut images, UVec2::splat(2));
+            images.get_mut(&cut_mask.mask).unwrap().data.fill(0);
+            app.world
+                .entity_mut(*entity)
+                .insert((trample_mask, cut_mask));
+        }
+
+        // moves the target two cells, so all chunks except one are recycled
+        *app.world.get_mut::<GlobalTransform>(target).unwrap() =
+            GlobalTransform::from_xyz(25., 0., 5.);
+        app.update();
+        let moved = chunks(&mut app);
+        let cells: Vec<IVec2> = moved.iter().map(|(_, cell, ..)| *cell).collect();
+        assert_eq!(
+            cells,
+            [(1, 0), (2, -1), (2, 0), (2, 1), (3, 0)].map(IVec2::from)
+        );
+        for (entity, cell, visibility, recycled) in &moved {
+            // no new chunks were spawned
+            assert!(spawned.iter().any(|(spawned, ..)| spawned == entity));
+            let kept = *cell == IVec2::new(1, 0);
+            assert_eq!(*recycled, !kept);
+            assert_eq!(*visibility == Visibility::Hidden, !kept);
+            // the trails and cuts stay at the old position
+            let trample_mask = &app.world.get::<TrampleMask>(*entity).unwrap().mask;
+            let cut_mask = &app.world.get::<GrassCutMask>(*entity).unwrap().mask;
+            let images = app.world.resource::<Assets<Image>>();
+            let floats = |mask| -> Vec<f32> {
+                let data = &images.get(mask).unwrap().data;
+                data.chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                    .collect()
+            };
+            let trampled = floats(trample_mask).iter().any(|value| *value != 0.);
+            let cut = floats(cut_mask).iter().any(|value| *value != 1.);
+            assert_eq!(trampled, kept);
+            assert_eq!(cut, kept);
+        }
+
+        // a dithering of the old maps doesn't show the chunk
+        let (ready, ..) = moved[1];
+        app.world.send_event(GrassChunkReady {
+            entity: ready,
+            generation: 0,
+        });
+        app.update();
+        assert!(chunks(&mut app)
+            .iter()
+            .any(|(entity, _, visibility, recycled)| *entity == ready
+                && *visibility == Visibility::Hidden
+                && *recycled));
+        // the chunk is shown once it is dithered at its new position
+        app.world.send_event(GrassChunkReady {
+            entity: ready,
+            generation: 1,
+        });
+        app.update();
+        let shown = chunks(&mut app);
+        for (entity, _, visibility, recycled) in shown {
+            if entity == ready {
+                assert_eq!(visibility, Visibility::Inherited);
+                assert!(!recycled);
+            }
+        }
+        assert_eq!(
+            chunks(&mut app)
+                .iter()
+                .filter(|(_, _, visibility, _)| *visibility == Visibility::Hidden)
+                .count(),
+            3
+        );
+    }
+}
@@ -5,10 +5,11 @@
 //! More information can be found on the [`github repository`](https://github.com/EmiOnGit/warbler_grass)
 use bevy::{
     asset::{Assets, Handle},
-    ecs::prelude::{FromWorld, ReflectResource, Resource, World},
+    ecs::prelude::{Component, FromWorld, ReflectResource, Resource, World},
     math::Vec2,
-    reflect::Reflect,
+    reflect::{FromReflect, Reflect},
     render::{
+        extract_component::ExtractComponent,
         extract_resource::ExtractResource,
         prelude::Image,
         texture::{CompressedImageFormats, ImageType},
@@ -16,13 +17,19 @@ use bevy::{
 };
 
 pub mod bundle;
+pub mod cutting;
 pub mod dithering;
 
 pub mod diagnostic;
 #[cfg(feature = "editor")]
 pub mod editor;
+pub mod infinite;
+pub mod query;
+pub mod trample;
+pub mod wind;
 
 mod density_map;
+mod grid;
 mod height_map;
 mod update;
 
@@ -37,7 +44,7 @@ pub mod prelude {
     pub use crate::bundle::*;
     pub use crate::maps::*;
     pub use crate::warblers_plugin::WarblersPlugin;
-    pub use crate::GrassConfiguration;
+    pub use crate::{GrassConfiguration, GrassLod, GrassWindTime, WindModel};
 }
 
 /// A [resource](bevy::prelude::Resource) used to globally define parameters about the grass.
@@ -56,12 +63,126 @@ pub struct GrassConfiguration {
     /// If you want to change the generel look of the wind and not only the wind direction/ speed
     /// you can also change the noise texture used for the wind that is stored in the
     /// [`GrassNoiseTexture`] resource
+    /// or tune the [`WindModel`] in `wind_model`
     pub wind: Vec2,
+    /// Controls how the wind moves over the grass, like its speed and gusts
+    pub wind_model: WindModel,
+    /// The maximal rotation of a grass blade around the y axis in radians.
+    ///
+    /// Each blade gets a rotation between `-max_blade_rotation` and `max_blade_rotation`
+    /// that is derived from its position, so the rotation stays the same between frames.
+    /// Setting this to `0.` lets all blades face the same direction.
+    ///
+    /// The rotation can also be disabled for single chunks
+    /// using the [`NoBladeRotation`](crate::bundle::NoBladeRotation) component
+    pub max_blade_rotation: f32,
+    /// The fraction of blades that cast shadows, between `0.` and `1.`
+    ///
+    /// Only chunks with the [`GrassCastShadows`](crate::bundle::GrassCastShadows) component cast shadows.
+    /// Since dense grass often looks the same with fewer shadow casters,
+    /// lowering this value is an easy way to make the shadow pass cheaper.
+    pub shadow_blade_fraction: f32,
+    /// Thins out the grass with increasing distance to the camera
+    ///
+    /// If `None`, all blades are drawn regardless of the distance.
+    /// Single chunks can use other distances by adding a [`GrassLod`] component to them.
+    pub lod: Option<GrassLod>,
+    /// The seed of all per-blade randomness, like the jitter of the positions and the rotation of the blades.
+    ///
+    /// The randomness is independent of the [`GrassNoiseTexture`] used for the wind,
+    /// so the same seed always results in the same grass layout.
+    pub seed: u32,
 }
 impl Default for GrassConfiguration {
     fn default() -> Self {
         GrassConfiguration {
             wind: Vec2::new(1.0, 1.0),
+            wind_model: WindModel::default(),
+            max_blade_rotation: std::f32::consts::PI,
+            shadow_blade_fraction: 1.,
+            lod: None,
+            seed: 0,
+        }
+    }
+}
+
+/// Defines how the wind noise moves over the grass
+///
+/// The direction and strength of the wind are set by `wind` of the [`GrassConfiguration`].
+#[derive(Clone, Copy, Debug, PartialEq, Reflect, FromReflect)]
+pub struct WindModel {
+    /// How much the [`GrassNoiseTexture`] is stretched over the grass.
+    ///
+    /// Higher values result in smaller waves
+    pub noise_scale: f32,
+    /// How fast the noise scrolls over the grass in the direction of the wind
+    pub speed: f32,
+    /// How much stronger the wind gets during a gust.
+    ///
+    /// A gust strength of `1.` doubles the wind at the peak of the gust, `0.` disables gusts
+    pub gust_strength: f32,
+    /// How many gusts roll over the grass per second of wind time
+    pub gust_frequency: f32,
+    /// Scales the speed at which the [`GrassWindTime`] advances.
+    ///
+    /// Can be used to slow down the wind during slow motion, `0.` pauses the wind
+    pub time_scale: f32,
+}
+impl Default for WindModel {
+    fn default() -> Self {
+        WindModel {
+            noise_scale: 35.,
+            speed: 50.,
+            gust_strength: 0.,
+            gust_frequency: 0.2,
+            time_scale: 1.,
+        }
+    }
+}
+
+/// The time used to animate the wind in seconds.
+///
+/// It advances each frame by the frame time scaled by the `time_scale` of the [`WindModel`],
+/// independently of the global time used by other shaders.
+/// Setting it directly makes it possible to jump to a certain state of the wind.
+///
+/// A [`GrassWindTime`] is inserted by the [`WarblersPlugin`](crate::warblers_plugin::WarblersPlugin).
+#[derive(Resource, Clone, Copy, Debug, Default, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub struct GrassWindTime {
+    /// The elapsed wind time in seconds.
+    ///
+    /// It is stored with double precision and wrapped to the periods of the wind animation
+    /// before it is sent to the shader, so the wind keeps moving smoothly in long running sessions
+    pub elapsed: f64,
+}
+
+/// Defines how the grass density decreases with the distance to the camera
+///
+/// Up to the `near` distance all blades are drawn.
+/// Between `near` and `far` the blades are thinned out smoothly,
+/// and chunks further away than `far` aren't drawn at all.
+///
+/// The thinned out blades aren't drawn at all, as chunks beyond `near` are culled by a compute shader
+/// like with the [`GpuCulling`](crate::bundle::GpuCulling) component.
+/// Chunks spawned with the [`WarblersExplicitBundle`](crate::bundle::WarblersExplicitBundle), batched chunks
+/// and devices without compute shaders (e.g. WebGL2) still draw all blades in range and only shrink the thinned out ones.
+///
+/// It is used for all chunks if set in the [`GrassConfiguration`].
+/// Inserted as component, it overrides the [`GrassConfiguration`] for a single chunk,
+/// for example to let large fields of grass reach further than small patches.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Reflect, FromReflect, ExtractComponent)]
+pub struct GrassLod {
+    /// The distance to the camera at which the grass starts to get thinner
+    pub near: f32,
+    /// The distance to the camera at which no grass is drawn anymore
+    pub far: f32,
+}
+impl Default for GrassLod {
+    fn default() -> Self {
+        GrassLod {
+            near: 50.,
+            far: 150.,
         }
     }
 }
@@ -0,0 +1,673 @@
+//! Contains the [`GrassQuery`], which answers questions about the grass on the cpu
+use bevy::{
+    ecs::system::SystemParam, math::Vec3Swizzles, prelude::*, render::primitives::Aabb,
+    utils::HashSet,
+};
+
+use crate::{
+    bundle::Grass,
+    dithering::{DitheredBuffer, GrassChunkReady},
+    height_map::{sample_height_map, HeightMap},
+    GrassConfiguration,
+};
+
+/// A [`SystemParam`] to query the grass on the cpu.
+///
+/// Can be used by gameplay code, for example to place objects on the ground the grass stands on.
+///
+/// # Example
+/// ```rust
+/// use bevy::{math::Vec3Swizzles, prelude::*};
+/// use warbler_grass::query::GrassQuery;
+///
+/// fn snap_to_ground(grass: GrassQuery, mut cameras: Query<&mut Transform, With<Camera>>) {
+///     for mut transform in &mut cameras {
+///         if let Some(height) = grass.height(transform.translation.xz()) {
+///             transform.translation.y = height;
+///         }
+///     }
+/// }
+/// # bevy::ecs::system::assert_is_system(snap_to_ground);
+/// ```
+#[derive(SystemParam)]
+pub struct GrassQuery<'w, 's> {
+    chunks: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static HeightMap,
+            &'static Aabb,
+            &'static GlobalTransform,
+        ),
+    >,
+    images: Res<'w, Assets<Image>>,
+    indices: Query<'w, 's, &'static BladeIndex>,
+}
+
+impl<'w, 's> GrassQuery<'w, 's> {
+    /// Returns the world space y position of the blades of a chunk at the given world position on the x,z plane.
+    ///
+    /// The position is sampled from the [`HeightMap`] exactly like the shader places the blades.
+    /// Rotations of the chunk around other axes than y are not taken into account.
+    ///
+    /// Returns `None` if the entity isn't a chunk with a [`HeightMap`], the position is outside of
+    /// the chunk or the [`HeightMap`] isn't loaded yet
+    pub fn height_at(&self, entity: Entity, world_xz: Vec2) -> Option<f32> {
+        let (_, height_map, aabb, transform) = self.chunks.get(entity).ok()?;
+        self.chunk_height(height_map, aabb, transform, world_xz)
+    }
+
+    /// Returns the world space y position of the blades at the given world position on the x,z plane.
+    ///
+    /// If multiple chunks overlap the position, the height of the highest chunk is returned.
+    /// See [`GrassQuery::height_at`] for more details
+    pub fn height(&self, world_xz: Vec2) -> Option<f32> {
+        self.chunks
+            .iter()
+            .filter_map(|(_, height_map, aabb, transform)| {
+                self.chunk_height(height_map, aabb, transform, world_xz)
+            })
+            .reduce(f32::max)
+    }
+
+    fn chunk_height(
+        &self,
+        height_map: &HeightMap,
+        aabb: &Aabb,
+        transform: &GlobalTransform,
+        world_xz: Vec2,
+    ) -> Option<f32> {
+        let size = Vec3::from(aabb.half_extents) * 2.;
+        let affine = transform.affine();
+        let local = affine
+            .inverse()
+            .transform_point3(Vec3::new(world_xz.x, 0., world_xz.y))
+            .xz();
+        if local.x < 0. || local.y < 0. || local.x >= size.x || local.y >= size.z {
+            return None;
+        }
+        let image = self.images.get(&height_map.height_map)?;
+        let height = chunk_height_map(image, height_map, size, local)?;
+        Some(
+            affine
+                .transform_point3(Vec3::new(local.x, height, local.y))
+                .y,
+        )
+    }
+
+    /// Returns the world space root positions of all blades within the radius around the center
+    ///
+    /// Only chunks with the [`IndexedGrass`] component are searched
+    pub fn blades_in_radius(&self, center: Vec3, radius: f32) -> impl Iterator<Item = Vec3> + '_ {
+        self.indices
+            .iter()
+            .flat_map(move |index| index.within_radius(center, radius))
+    }
+
+    /// Returns the world space root positions of all blades within the box spanned by `min` and `max`
+    ///
+    /// Only chunks with the [`IndexedGrass`] component are searched
+    pub fn blades_in_box(&self, min: Vec3, max: Vec3) -> impl Iterator<Item = Vec3> + '_ {
+        self.indices
+            .iter()
+            .flat_map(move |index| index.within_box(min, max))
+    }
+
+    /// Returns the world space root position of the blade closest to the point
+    ///
+    /// Only chunks with the [`IndexedGrass`] component are searched
+    pub fn nearest_blade(&self, point: Vec3) -> Option<Vec3> {
+        self.indices
+            .iter()
+            .filter_map(|index| index.nearest(point))
+            .min_by(|a, b| {
+                a.distance_squared(point)
+                    .total_cmp(&b.distance_squared(point))
+            })
+    }
+
+    /// Returns the first blade hit by the ray and its distance along the ray.
+    ///
+    /// Blades are hit if their root is within `radius` of the ray.
+    /// Only chunks with the [`IndexedGrass`] component are searched
+    pub fn raycast_blades(&self, ray: Ray, max_distance: f32, radius: f32) -> Option<(Vec3, f32)> {
+        self.indices
+            .iter()
+            .filter_map(|index| index.raycast(ray, max_distance, radius))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// Lets the [`BladeIndex`] of a chunk be created, so its blades can be found using the [`GrassQuery`]
+///
+/// Works for chunks spawned with the [`WarblersBundle`](crate::bundle::WarblersBundle)
+/// or the [`WarblersExplicitBundle`](crate::bundle::WarblersExplicitBundle).
+/// Chunks with the [`GpuDithering`](crate::maps::GpuDithering) component only have their blades on the gpu,
+/// so they can only be indexed if the cpu is used as fallback.
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct IndexedGrass;
+
+/// The world space root positions of the blades of a chunk, sorted into a grid on the x,z plane
+///
+/// The positions match the rendered blades, including their jitter and the [`HeightMap`].
+/// The index is created again each time the blades or the [`GlobalTransform`] of the chunk change.
+#[derive(Component, Clone, Debug, Default)]
+pub struct BladeIndex {
+    /// The positions sorted by their cell
+    positions: Vec<Vec3>,
+    /// The first position of each cell, the last entry is the amount of positions
+    cell_starts: Vec<u32>,
+    /// The world space x,z position of the first cell
+    origin: Vec2,
+    cell_size: f32,
+    cells: UVec2,
+    /// The bounds of all positions
+    min: Vec3,
+    max: Vec3,
+}
+/// The amount of blades stored in a cell on average
+const BLADES_PER_CELL: f32 = 16.;
+/// The maximal amount of cells in each direction
+const MAX_CELLS: u32 = 1024;
+
+impl BladeIndex {
+    /// Sorts the world space positions into a grid
+    ///
+    /// # Example
+    /// ```rust
+    /// use bevy::prelude::*;
+    /// use warbler_grass::query::BladeIndex;
+    ///
+    /// let positions = (0..100)
+    ///     .map(|i| Vec3::new((i % 10) as f32, 0., (i / 10) as f32))
+    ///     .collect();
+    /// let index = BladeIndex::new(positions);
+    /// assert_eq!(index.within_radius(Vec3::new(5., 0., 5.), 1.).count(), 5);
+    /// assert_eq!(index.nearest(Vec3::new(20., 0., 3.2)), Some(Vec3::new(9., 0., 3.)));
+    /// let ray = Ray {
+    ///     origin: Vec3::new(-5., 0., 2.),
+    ///     direction: Vec3::X,
+    /// };
+    /// assert_eq!(index.raycast(ray, 100., 0.1), Some((Vec3::new(0., 0., 2.), 5.)));
+    /// ```
+    pub fn new(positions: Vec<Vec3>) -> Self {
+        if positions.is_empty() {
+            return Self::default();
+        }
+        let (min, max) = positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), position| (min.min(*position), max.max(*position)),
+        );
+        let extent = (max - min).xz().max(Vec2::splat(f32::EPSILON));
+        let cell_size = (extent.x * extent.y * BLADES_PER_CELL / positions.len() as f32)
+            .sqrt()
+            .max(extent.max_element() / MAX_CELLS as f32)
+            .max(f32::EPSILON);
+        let cells = (extent / cell_size)
+            .ceil()
+            .as_uvec2()
+            .clamp(UVec2::ONE, UVec2::splat(MAX_CELLS));
+        let mut index = BladeIndex {
+            positions: Vec::new(),
+            cell_starts: vec![0; (cells.x * cells.y) as usize + 1],
+            origin: min.xz(),
+            cell_size,
+            cells,
+            min,
+            max,
+        };
+        // a counting sort by the cell of the positions
+        let cell_ids: Vec<usize> = positions
+            .iter()
+            .map(|position| index.cell_id(position.xz()))
+            .collect();
+        for id in &cell_ids {
+            index.cell_starts[id + 1] += 1;
+        }
+        for i in 1..index.cell_starts.len() {
+            index.cell_starts[i] += index.cell_starts[i - 1];
+        }
+        let mut next = index.cell_starts.clone();
+        let mut sorted = vec![Vec3::ZERO; positions.len()];
+        for (position, id) in positions.into_iter().zip(cell_ids) {
+            sorted[next[id] as usize] = position;
+            next[id] += 1;
+        }
+        index.positions = sorted;
+        index
+    }
+    /// Returns all blade positions of the chunk
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+    /// Returns the positions within the radius around the center
+    pub fn within_radius(&self, center: Vec3, radius: f32) -> impl Iterator<Item = Vec3> + '_ {
+        let radius_squared = radius * radius;
+        self.within_box(center - radius, center + radius)
+            .filter(move |position| position.distance_squared(center) <= radius_squared)
+    }
+    /// Returns the positions within the box spanned by `min` and `max`
+    pub fn within_box(&self, min: Vec3, max: Vec3) -> impl Iterator<Item = Vec3> + '_ {
+        let overlaps =
+            !self.positions.is_empty() && min.cmple(self.max).all() && max.cmpge(self.min).all();
+        // an empty range of cells if the box doesn't overlap
+        let (first, last) = if overlaps {
+            (self.cell(min.xz()), self.cell(max.xz()))
+        } else {
+            (UVec2::ONE, UVec2::ZERO)
+        };
+        (first.y..=last.y)
+            .flat_map(move |z| (first.x..=last.x).map(move |x| UVec2::new(x, z)))
+            .flat_map(move |cell| self.cell_positions(cell).iter().copied())
+            .filter(move |position| position.cmpge(min).all() && position.cmple(max).all())
+    }
+    /// Returns the position closest to the point
+    pub fn nearest(&self, point: Vec3) -> Option<Vec3> {
+        if self.positions.is_empty() {
+            return None;
+        }
+        let start = self.cell(point.xz()).as_ivec2();
+        // the distance of the point to the grid, if it lies outside of it
+        let outside = (self.origin - point.xz())
+            .max(point.xz() - self.origin - self.cells.as_vec2() * self.cell_size)
+            .max(Vec2::ZERO)
+            .length();
+        let mut nearest: Option<(Vec3, f32)> = None;
+        // searches the cells in growing rings around the cell of the point
+        for ring in 0..=self.cells.max_element() as i32 {
+            // all cells of this and further rings are at least this far away.
+            // Points outside of the grid are at least as far from a cell as their projection onto the grid
+            let min_distance = outside.max((ring - 1).max(0) as f32 * self.cell_size);
+            if nearest.is_some_and(|(_, distance)| distance < min_distance * min_distance) {
+                break;
+            }
+            for z in -ring..=ring {
+                for x in -ring..=ring {
+                    if x.abs() != ring && z.abs() != ring {
+                        continue;
+                    }
+                    let cell = start + IVec2::new(x, z);
+                    if cell.cmplt(IVec2::ZERO).any() || cell.cmpge(self.cells.as_ivec2()).any() {
+                        continue;
+                    }
+                    for position in self.cell_positions(cell.as_uvec2()) {
+                        let distance = position.distance_squared(point);
+                        if nearest.map_or(true, |(_, nearest)| distance < nearest) {
+                            nearest = Some((*position, distance));
+                        }
+                    }
+                }
+            }
+        }
+        nearest.map(|(position, _)| position)
+    }
+    /// Returns the first position within `radius` of the ray and its distance along the ray
+    pub fn raycast(&self, ray: Ray, max_distance: f32, radius: f32) -> Option<(Vec3, f32)> {
+        let direction = ray.direction.normalize_or_zero();
+        if self.positions.is_empty() || direction == Vec3::ZERO {
+            return None;
+        }
+        // clips the ray to the bounds of the positions
+        let inverse = direction.recip();
+        let t0 = (self.min - radius - ray.origin) * inverse;
+        let t1 = (self.max + radius - ray.origin) * inverse;
+        let enter = t0.min(t1).max_element().max(0.);
+        let exit = t0.max(t1).min_element().min(max_distance);
+        if enter > exit {
+            return None;
+        }
+        let start = ray.origin + direction * enter;
+        let end = ray.origin + direction * exit;
+        self.within_box(start.min(end) - radius, start.max(end) + radius)
+            .filter_map(|position| {
+                let distance = (position - ray.origin)
+                    .dot(direction)
+                    .clamp(0., max_distance);
+                let closest = ray.origin + direction * distance;
+                (closest.distance_squared(position) <= radius * radius)
+                    .then_some((position, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+    fn cell(&self, position: Vec2) -> UVec2 {
+        ((position - self.origin) / self.cell_size)
+            .max(Vec2::ZERO)
+            .as_uvec2()
+            .min(self.cells - 1)
+    }
+    fn cell_id(&self, position: Vec2) -> usize {
+        let cell = self.cell(position);
+        (cell.y * self.cells.x + cell.x) as usize
+    }
+    fn cell_positions(&self, cell: UVec2) -> &[Vec3] {
+        let id = (cell.y * self.cells.x + cell.x) as usize;
+        &self.positions[self.cell_starts[id] as usize..self.cell_starts[id + 1] as usize]
+    }
+}
+
+/// Creates the [`BladeIndex`] of chunks with the [`IndexedGrass`] component once their blades are available or changed
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub(crate) fn update_blade_indices(
+    mut commands: Commands,
+    changed: Query<
+        Entity,
+        (
+            With<IndexedGrass>,
+            Or<(
+                Added<IndexedGrass>,
+                Changed<GlobalTransform>,
+                Changed<Aabb>,
+                Changed<HeightMap>,
+                Changed<Grass>,
+            )>,
+        ),
+    >,
+    chunks: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            Option<&Grass>,
+            Option<(&Handle<DitheredBuffer>, &HeightMap, &Aabb)>,
+        ),
+        With<IndexedGrass>,
+    >,
+    config: Res<GrassConfiguration>,
+    dithered: Res<Assets<DitheredBuffer>>,
+    images: Res<Assets<Image>>,
+    mut ready_events: EventReader<GrassChunkReady>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut removed: RemovedComponents<IndexedGrass>,
+    mut pending: Local<HashSet<Entity>>,
+    mut last_seed: Local<Option<u32>>,
+) {
+    for entity in removed.iter() {
+        if let Some(mut entity) = commands.get_entity(entity) {
+            entity.remove::<BladeIndex>();
+        }
+    }
+    let updated_images: HashSet<Handle<Image>> = image_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                Some(handle.clone_weak())
+            }
+            AssetEvent::Removed { .. } => None,
+        })
+        .collect();
+    // the jitter of all blades depends on the seed
+    let seed_changed = last_seed.replace(config.seed) != Some(config.seed);
+    // chunks whose blades weren't available yet are retried, as the dithered buffer of a ready chunk
+    // is only inserted once the commands of the dithering are applied
+    let mut outdated: HashSet<Entity> = std::mem::take(&mut *pending);
+    outdated.extend(changed.iter());
+    outdated.extend(ready_events.iter().map(|event| event.entity));
+    for (entity, transform, grass, dithered_chunk) in chunks.iter() {
+        let image_changed = dithered_chunk
+            .is_some_and(|(_, height_map, _)| updated_images.contains(&height_map.height_map));
+        if !seed_changed && !image_changed && !outdated.contains(&entity) {
+            continue;
+        }
+        let jitter = |position: Vec2| position + jitter_offset(position, config.seed);
+        let local_positions: Vec<Vec3> = if let Some(grass) = grass {
+            grass
+                .positions
+                .iter()
+                .map(|position| {
+                    let xz = jitter(position.xz());
+                    Vec3::new(xz.x, position.y, xz.y)
+                })
+                .collect()
+        } else if let Some((handle, height_map, aabb)) = dithered_chunk {
+            let (Some(blades), Some(image)) =
+                (dithered.get(handle), images.get(&height_map.height_map))
+            else {
+                pending.insert(entity);
+                continue;
+            };
+            let size = Vec3::from(aabb.half_extents) * 2.;
+            blades
+                .positions
+                .iter()
+                .map(|position| {
+                    let xz = jitter(*position);
+                    let y = chunk_height_map(image, height_map, size, xz).unwrap_or(0.);
+                    Vec3::new(xz.x, y, xz.y)
+                })
+                .collect()
+        } else {
+            pending.insert(entity);
+            continue;
+        };
+        let affine = transform.affine();
+        let positions = local_positions
+            .into_iter()
+            .map(|position| affine.transform_point3(position))
+            .collect();
+        commands.entity(entity).insert(BladeIndex::new(positions));
+    }
+}
+
+/// Samples the height map of a chunk with the given size like the grass shader does
+fn chunk_height_map(image: &Image, height_map: &HeightMap, size: Vec3, local: Vec2) -> Option<f32> {
+    let position = height_map.pixel_position(image.size(), (local / size.xz()).abs());
+    Some(sample_height_map(image, height_map.filter, position)? * size.y)
+}
+/// Mirrors `jitter_offset` of the grass shader
+fn jitter_offset(position: Vec2, seed: u32) -> Vec2 {
+    Vec2::new(
+        random_from_position(position, JITTER_X_CHANNEL, seed),
+        random_from_position(position, JITTER_Z_CHANNEL, seed),
+    ) - 0.5
+}
+const JITTER_X_CHANNEL: u32 = 3;
+const JITTER_Z_CHANNEL: u32 = 4;
+/// Mirrors `random_from_position` of the grass shader
+fn random_from_position(position: Vec2, channel: u32, seed: u32) -> f32 {
+    let seed = shader_hash(
+        position.x.to_bits()
+            ^ shader_hash(position.y.to_bits() ^ shader_hash(channel ^ shader_hash(seed))),
+    );
+    seed as f32 / u32::MAX as f32
+}
+fn shader_hash(input: u32) -> u32 {
+    let state = input.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{update_blade_indices, BladeIndex, IndexedGrass};
+    use crate::{
+        density_map::DensityMap,
+        dithering::{
+            add_dither_to_density, poll_dithering_tasks, DitheredBuffer, DitheringGeneration,
+            GrassChunkReady,
+        },
+        height_map::HeightMap,
+        GrassConfiguration,
+    };
+    use bevy::{
+        math::{Ray, Vec3},
+        prelude::*,
+        render::primitives::Aabb,
+    };
+
+    /// A small xorshift generator, so the tests are reproducible
+    struct Random(u32);
+    impl Random {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as f32 / u32::MAX as f32
+        }
+        fn vec3(&mut self, min: f32, max: f32) -> Vec3 {
+            Vec3::new(self.next(), self.next(), self.next()) * (max - min) + min
+        }
+    }
+    fn sorted(mut positions: Vec<Vec3>) -> Vec<Vec3> {
+        positions.sort_by(|a, b| {
+            a.x.total_cmp(&b.x)
+                .then(a.y.total_cmp(&b.y))
+                .then(a.z.total_cmp(&b.z))
+        });
+        positions
+    }
+    fn random_positions(random: &mut Random, count: usize) -> Vec<Vec3> {
+        (0..count)
+            .map(|_| random.vec3(0., 50.) * Vec3::new(1., 0.1, 1.))
+            .collect()
+    }
+    #[test]
+    fn nearest_matches_brute_force() {
+        let mut random = Random(12345);
+        for _ in 0..2000 {
+            let count = 1 + (random.next() * 300.) as usize;
+            let positions = random_positions(&mut random, count);
+            let index = BladeIndex::new(positions.clone());
+            for _ in 0..10 {
+                // also places points far outside of the grid
+                let point = random.vec3(-300., 350.);
+                let expected = positions
+                    .iter()
+                    .map(|position| position.distance(point))
+                    .fold(f32::MAX, f32::min);
+                let nearest = index.nearest(point).unwrap();
+                assert_eq!(nearest.distance(point), expected);
+            }
+        }
+        assert_eq!(BladeIndex::new(Vec::new()).nearest(Vec3::ZERO), None);
+    }
+    #[test]
+    fn within_radius_and_box_match_brute_force() {
+        let mut random = Random(987);
+        for _ in 0..100 {
+            let count = (random.next() * 300.) as usize;
+            let positions = random_positions(&mut random, count);
+            let index = BladeIndex::new(positions.clone());
+            for _ in 0..10 {
+                let center = random.vec3(-20., 70.);
+                let radius = random.next() * 20.;
+                let expected: Vec<Vec3> = positions
+                    .iter()
+                    .copied()
+                    .filter(|position| position.distance_squared(center) <= radius * radius)
+                    .collect();
+                assert_eq!(
+                    sorted(index.within_radius(center, radius).collect()),
+                    sorted(expected)
+                );
+
+                let (a, b) = (random.vec3(-20., 70.), random.vec3(-20., 70.));
+                let (min, max) = (a.min(b), a.max(b));
+                let expected: Vec<Vec3> = positions
+                    .iter()
+                    .copied()
+                    .filter(|position| position.cmpge(min).all() && position.cmple(max).all())
+                    .collect();
+                assert_eq!(
+                    sorted(index.within_box(min, max).collect()),
+                    sorted(expected)
+                );
+            }
+        }
+    }
+    #[test]
+    fn raycast_matches_brute_force() {
+        let mut random = Random(4242);
+        for _ in 0..100 {
+            let count = 1 + (random.next() * 300.) as usize;
+            let positions = random_positions(&mut random, count);
+            let index = BladeIndex::new(positions.clone());
+            for _ in 0..10 {
+                let ray = Ray {
+                    origin: random.vec3(-50., 100.),
+                    direction: (random.vec3(-1., 1.) * Vec3::new(1., 0.2, 1.)).normalize(),
+                };
+                let (max_distance, radius) = (random.next() * 150., random.next() * 3.);
+                let expected = positions
+                    .iter()
+                    .filter_map(|position| {
+                        let distance = (*position - ray.origin)
+                            .dot(ray.direction)
+                            .clamp(0., max_distance);
+                        let closest = ray.origin + ray.direction * distance;
+                        (closest.distance_squared(*position) <= radius * radius).then_some(distance)
+                    })
+                    .fold(None, |nearest: Option<f32>, distance| {
+                        Some(nearest.map_or(distance, |nearest| nearest.min(distance)))
+                    });
+                let hit = index.raycast(ray, max_distance, radius);
+                assert_eq!(hit.is_some(), expected.is_some());
+                if let (Some((_, distance)), Some(expected)) = (hit, expected) {
+                    assert!((distance - expected).abs() < 1e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn density_chunks_are_indexed_once_dithered() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<Image>()
+            .add_asset::<DitheredBuffer>()
+            .add_event::<GrassChunkReady>()
+            .init_resource::<DitheringGeneration>()
+            .init_resource::<GrassConfiguration>()
+            .add_systems(
+                (
+                    add_dither_to_density,
+                    poll_dithering_tasks,
+                    update_blade_indices,
+                )
+                    .chain(),
+            );
+        let mut images = app.world.resource_mut::<Assets<Image>>();
+        // a 1x1 white image
+        let image = images.add(Image::default());
+        let chunk = app
+            .world
+            .spawn((
+                DensityMap {
+                    density_map: image.clone(),
+                    density: 1.,
+                    ..default()
+                },
+                HeightMap {
+                    height_map: image,
+                    ..default()
+                },
+                Aabb::from_min_max(Vec3::ZERO, Vec3::new(4., 2., 4.)),
+                GlobalTransform::default(),
+                IndexedGrass,
+            ))
+            .id();
+        // whether the blade index was inserted during the next frame
+        let update = |app: &mut App| {
+            let last_change_tick = app.world.read_change_tick();
+            app.update();
+            let ticks = app.world.entity(chunk).get_change_ticks::<BladeIndex>();
+            ticks.map(|ticks| ticks.is_changed(last_change_tick, app.world.read_change_tick()))
+        };
+        // the dithering runs in a background task
+        let mut frames = 0;
+        while update(&mut app).is_none() {
+            assert!(frames < 100, "the chunk never got a blade index");
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            frames += 1;
+        }
+        let index = app.world.get::<BladeIndex>(chunk).unwrap();
+        assert_eq!(index.positions().len(), 4 * 4);
+        assert!(index.positions().iter().all(|position| position.y == 2.));
+
+        // only a new seed moves the blades
+        app.world.resource_mut::<GrassConfiguration>().wind = Vec2::ONE;
+        assert_eq!(update(&mut app), Some(false));
+        app.world.resource_mut::<GrassConfiguration>().seed += 1;
+        assert_eq!(update(&mut app), Some(true));
+    }
+}
@@ -4,10 +4,13 @@ use bevy::{
 };
 
 use self::draw::{
-    SetColorBindGroup, SetHeightBindGroup, SetUniformBindGroup, SetVertexBuffer, SetYBindGroup,
+    DrawGrassBatch, SetBatchBindGroup, SetColorBindGroup, SetHeightBindGroup,
+    SetShadowViewBindGroup, SetUniformBindGroup, SetVertexBuffer, SetYBindGroup,
 };
 
 pub(crate) mod cache;
+pub(crate) mod cull_pipeline;
+pub(crate) mod dither_pipeline;
 mod draw;
 pub(crate) mod extract;
 pub(crate) mod grass_pipeline;
@@ -33,3 +36,35 @@ pub(crate) type GrassDrawCall = (
     // Binds the xz position of the grass instances to the vertex buffer
     SetVertexBuffer,
 );
+
+// The render call used to draw the grass into the shadow maps of lights
+pub(crate) type GrassShadowDrawCall = (
+    SetItemPipeline,
+    // Binds the view of the light
+    SetShadowViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    SetUniformBindGroup<2>,
+    SetColorBindGroup<3>,
+    SetYBindGroup<4>,
+    SetHeightBindGroup<5>,
+    SetVertexBuffer,
+);
+
+// The render call used to draw all chunks of a batch at once
+pub(crate) type GrassBatchDrawCall = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    // Binds the transforms, colors and maps of all chunks in the batch
+    SetBatchBindGroup<1>,
+    SetUniformBindGroup<2>,
+    DrawGrassBatch,
+);
+
+// The render call used to draw a batch into the shadow maps of lights
+pub(crate) type GrassBatchShadowDrawCall = (
+    SetItemPipeline,
+    SetShadowViewBindGroup<0>,
+    SetBatchBindGroup<1>,
+    SetUniformBindGroup<2>,
+    DrawGrassBatch,
+);
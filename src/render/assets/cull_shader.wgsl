@@ -0,0 +1,87 @@
+// Culls the blades of a chunk against the frustum of a view, a maximal distance and the lod of the chunk.
+// The visible blades are written compacted into a new buffer, which is drawn indirectly
+struct CullConfiguration {
+    model: mat4x4<f32>,
+    // the left, right, bottom, top and near plane of the view frustum
+    planes: array<vec4<f32>, 5>,
+    // the position of the view in xyz and the maximal distance in w
+    view: vec4<f32>,
+    // the height of the bounding sphere of a blade (in local space) in x and its radius (in world space) in y
+    bounds: vec4<f32>,
+    // the near and far distance of the lod of the chunk in x and y, zero if the chunk has none
+    lod: vec4<f32>,
+    // the seed of the grass configuration
+    seed: u32,
+}
+// the blade count of the chunk is read from the instance count of its indirect draw arguments
+struct SourceArgs {
+    count: u32,
+    instance_count: u32,
+    first: u32,
+    base_vertex: u32,
+    first_instance: u32,
+}
+struct DrawIndirectArgs {
+    count: u32,
+    instance_count: atomic<u32>,
+    first: u32,
+    base_vertex: u32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> cull_config: CullConfiguration;
+@group(0) @binding(1)
+var<storage, read> source: array<vec2<f32>>;
+@group(0) @binding(2)
+var<storage, read> source_args: SourceArgs;
+@group(0) @binding(3)
+var<storage, read_write> positions: array<vec2<f32>>;
+@group(0) @binding(4)
+var<storage, read_write> indirect_args: DrawIndirectArgs;
+
+const WORKGROUP_SIZE: u32 = 64u;
+// has to match the grass shader
+const LOD_CHANNEL: u32 = 2u;
+
+// the same random values as in the grass shader
+fn hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+fn random_from_position(position: vec2<f32>, channel: u32) -> f32 {
+    let seed = hash(bitcast<u32>(position.x) ^ hash(bitcast<u32>(position.y) ^ hash(channel ^ hash(cull_config.seed))));
+    return f32(seed) / 4294967295.;
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn cull(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) workgroups: vec3<u32>) {
+    // big chunks need more workgroups than allowed in one dimension
+    let index = id.y * workgroups.x * WORKGROUP_SIZE + id.x;
+    if index >= source_args.instance_count {
+        return;
+    }
+    let position = source[index];
+    let center = (cull_config.model * vec4<f32>(position.x, cull_config.bounds.x, position.y, 1.)).xyz;
+    let radius = cull_config.bounds.y;
+    let view_distance = max(distance(center, cull_config.view.xyz) - radius, 0.);
+    if view_distance > cull_config.view.w {
+        return;
+    }
+    for (var i = 0; i < 5; i++) {
+        if dot(cull_config.planes[i], vec4<f32>(center, 1.)) + radius <= 0. {
+            return;
+        }
+    }
+    // the grass shader shrinks these blades into the ground completely.
+    // The closest distance of the blade is used, so it never thins out more blades than the grass shader
+    if cull_config.lod.y > 0. {
+        let density = 1. - smoothstep(cull_config.lod.x, cull_config.lod.y, view_distance);
+        if random_from_position(position, LOD_CHANNEL) >= density {
+            return;
+        }
+    }
+    let culled_index = atomicAdd(&indirect_args.instance_count, 1u);
+    positions[culled_index] = position;
+}
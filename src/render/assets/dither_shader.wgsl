@@ -0,0 +1,75 @@
+// Generates the positions of the grass blades from a density map on the gpu.
+// Mirrors the ordered dithering done on the cpu in `dithering.rs`
+struct DitherConfiguration {
+    field_size: vec2<f32>,
+    density: f32,
+    flags: u32,
+}
+// same layout as the indirect draw arguments of wgpu.
+// The indexed and non indexed variant only differ in the fields we don't touch
+struct DrawIndirectArgs {
+    count: u32,
+    instance_count: atomic<u32>,
+    first: u32,
+    base_vertex: u32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var density_map: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> dither_config: DitherConfiguration;
+@group(0) @binding(2)
+var<storage, read_write> positions: array<vec2<f32>>;
+@group(0) @binding(3)
+var<storage, read_write> indirect_args: DrawIndirectArgs;
+
+const FLAG_SRGB: u32 = 1u;
+const FLAG_SINGLE_CHANNEL: u32 = 2u;
+
+// see https://surma.dev/things/ditherpunk/ for a good resource regarding ordered dithering
+var<private> BAYER_DITHER: array<u32, 64> = array<u32, 64>(
+    0u, 32u, 8u, 40u, 2u, 34u, 10u, 42u,
+    48u, 16u, 56u, 24u, 50u, 18u, 58u, 26u,
+    12u, 44u, 4u, 36u, 14u, 46u, 6u, 38u,
+    60u, 28u, 52u, 20u, 62u, 30u, 54u, 22u,
+    3u, 35u, 11u, 43u, 1u, 33u, 9u, 41u,
+    51u, 19u, 59u, 27u, 49u, 17u, 57u, 25u,
+    15u, 47u, 7u, 39u, 13u, 45u, 5u, 37u,
+    61u, 31u, 55u, 23u, 61u, 29u, 53u, 21u,
+);
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let lower = color * 12.92;
+    let higher = 1.055 * pow(color, vec3<f32>(1. / 2.4)) - 0.055;
+    return select(higher, lower, color <= vec3<f32>(0.0031308));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn dither(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i_count = u32(abs(dither_config.density * dither_config.field_size.x));
+    let j_count = u32(abs(dither_config.density * dither_config.field_size.y));
+    if id.x >= i_count || id.y >= j_count {
+        return;
+    }
+    let threshold = BAYER_DITHER[(id.x % 8u) * 8u + id.y % 8u];
+
+    // normalize i,j between 0,1
+    let i = f32(id.x) / f32(i_count);
+    let j = f32(id.y) / f32(j_count);
+
+    let dim = textureDimensions(density_map, 0);
+    var color = textureLoad(density_map, vec2<i32>(i32(i * f32(dim.x)), i32(j * f32(dim.y))), 0).rgb;
+    // the cpu works on the stored bytes, so we undo the conversion of srgb textures
+    if (dither_config.flags & FLAG_SRGB) != 0u {
+        color = linear_to_srgb(color);
+    }
+    var luma = color.r;
+    if (dither_config.flags & FLAG_SINGLE_CHANNEL) == 0u {
+        luma = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    }
+    if u32(luma * 255.) > threshold * 4u {
+        let index = atomicAdd(&indirect_args.instance_count, 1u);
+        positions[index] = vec2<f32>(i * dither_config.field_size.x, j * dither_config.field_size.y);
+    }
+}
@@ -1,20 +1,68 @@
 #import bevy_pbr::mesh_types
-#import bevy_pbr::mesh_view_bindings
+#ifdef SHADOW_PASS
+    #import bevy_pbr::mesh_view_types
+
+    @group(0) @binding(0)
+    var<uniform> view: View;
+#else
+    #import bevy_pbr::mesh_view_bindings
+#endif
 
 struct ShaderRegionConfiguration {
     wind: vec2<f32>,
-    _wasm_padding: vec2<f32>,
+    // how far the wind moved the noise texture, wrapped to its size on the cpu
+    wind_offset: vec2<f32>,
+    max_blade_rotation: f32,
+    shadow_blade_fraction: f32,
+    seed: u32,
+    wind_noise_scale: f32,
+    gust_strength: f32,
+    gust_frequency: f32,
+    // the phases of the two waves forming the gusts, wrapped to a single period on the cpu
+    gust_phases: vec2<f32>,
 };
 struct Vertex {
     @location(0) vertex_position: vec3<f32>,
+#ifdef VERTEX_NORMALS
+    @location(1) vertex_normal: vec3<f32>,
+#endif
     @location(3) xz_position: vec2<f32>,
+#ifdef INSTANCE_DATA
+    // height, width and rotation of the blade
+    @location(7) blade_attributes: vec4<f32>,
+    @location(8) blade_color: vec4<f32>,
+#endif
 }
     struct Color {
         main_color: vec4<f32>,
         bottom_color: vec4<f32>,
+        // the near and far distance of the lod of the chunk
+        lod: vec4<f32>,
     }
-@group(1) @binding(0)
-var<uniform> mesh: Mesh;
+#ifdef BATCHED
+    // all chunks of a batch are drawn in a single draw call.
+    // The data of the chunk a blade belongs to is copied into the private variables,
+    // so the rest of the shader works the same way for batched and single chunks
+    struct BatchedChunk {
+        model: mat4x4<f32>,
+        inverse_transpose_model: mat4x4<f32>,
+        main_color: vec4<f32>,
+        bottom_color: vec4<f32>,
+        // the size of the aabb in xyz and the uniform height in w
+        aabb: vec4<f32>,
+        first_instance: u32,
+        height_map_filter: u32,
+        height_map_border: u32,
+        _padding_z: u32,
+        lod: vec4<f32>,
+    }
+    @group(1) @binding(0)
+    var<storage> chunks: array<BatchedChunk>;
+    var<private> mesh: Mesh;
+#else
+    @group(1) @binding(0)
+    var<uniform> mesh: Mesh;
+#endif
 
 @group(2) @binding(0)
 var<uniform> config: ShaderRegionConfiguration;
@@ -22,75 +70,368 @@ var<uniform> config: ShaderRegionConfiguration;
 @group(2) @binding(1)
 var noise_texture: texture_2d<f32>;
 
-@group(3) @binding(0)
-var<uniform> color: Color;
+// a `WindZone` or `WindImpulse`, see `wind.rs`
+struct WindSource {
+    // xyz: the center in world space, w: the kind of the source
+    center: vec4<f32>,
+    // xyz: the half extents of directional sources, x: the radius of the other sources
+    extents: vec4<f32>,
+    // xy: the wind of directional sources, x: the strength of the other sources
+    wind: vec4<f32>,
+};
+// has to match `MAX_WIND_SOURCES` in `wind.rs`
+const MAX_WIND_SOURCES: u32 = 64u;
+struct WindSources {
+    count: u32,
+    _padding_x: u32,
+    _padding_y: u32,
+    _padding_z: u32,
+    sources: array<WindSource, MAX_WIND_SOURCES>,
+};
+@group(2) @binding(2)
+var<uniform> wind_sources: WindSources;
+
+// a `GrassInteractor`, see `trample.rs`
+struct Interactor {
+    // xyz: the position in world space, w: the radius
+    position: vec4<f32>,
+    strength: f32,
+    _padding_x: f32,
+    _padding_y: f32,
+    _padding_z: f32,
+};
+// has to match `MAX_GRASS_INTERACTORS` in `trample.rs`
+const MAX_GRASS_INTERACTORS: u32 = 32u;
+struct Interactors {
+    count: u32,
+    _padding_x: u32,
+    _padding_y: u32,
+    _padding_z: u32,
+    interactors: array<Interactor, MAX_GRASS_INTERACTORS>,
+};
+@group(2) @binding(3)
+var<uniform> interactors: Interactors;
+
+#ifdef BATCHED
+    var<private> color: Color;
+#else
+    @group(3) @binding(0)
+    var<uniform> color: Color;
+#endif
 #ifdef EXPLICIT
     @group(4) @binding(0)
     var y_positions: texture_2d<f32>;
+    #ifdef CUT_MASK
+        @group(4) @binding(1)
+        var cut_mask: texture_2d<f32>;
+    #endif
+    // the area of the chunk the trample and cut masks are stretched over
+    struct MaskBounds {
+        min: vec2<f32>,
+        size: vec2<f32>,
+    }
+    @group(4) @binding(2)
+    var<uniform> mask_bounds: MaskBounds;
+    #ifdef TRAMPLE_MASK
+        @group(4) @binding(3)
+        var trample_mask: texture_2d<f32>;
+    #endif
 #else
-    @group(4) @binding(0)
-    var height_map: texture_2d<f32>;
-
     struct ShaderAabb {
         vect: vec3<f32>,
-        _wasm_padding: f32,
+        // how the height map is interpolated, see `sample_height_map`
+        height_map_filter: u32,
+        // the pixels at each edge of the height map lying outside of the chunk
+        height_map_border: u32,
     }
-
-    @group(4) @binding(1)
-    var<uniform> aabb: ShaderAabb;
+    #ifdef BATCHED
+        @group(1) @binding(1)
+        var height_map: texture_2d<f32>;
+        var<private> aabb: ShaderAabb;
+    #else
+        @group(4) @binding(0)
+        var height_map: texture_2d<f32>;
+        @group(4) @binding(1)
+        var<uniform> aabb: ShaderAabb;
+        #ifdef TRAMPLE_MASK
+            @group(4) @binding(2)
+            var trample_mask: texture_2d<f32>;
+        #endif
+        #ifdef CUT_MASK
+            @group(4) @binding(3)
+            var cut_mask: texture_2d<f32>;
+        #endif
+    #endif
 #endif
 #ifdef HEIGHT_TEXTURE
- @group(5) @binding(0)
-    var heights: texture_2d<f32>;
+    #ifdef BATCHED
+        @group(1) @binding(2)
+        var heights: texture_2d<f32>;
+    #else
+        @group(5) @binding(0)
+        var heights: texture_2d<f32>;
+    #endif
 #else
     struct ShaderHeightUniform {
         height: f32,
         _wasm_padding: vec2<f32>,
     }
-    @group(5) @binding(0)
-    var<uniform> height_uniform: ShaderHeightUniform;
+    #ifdef BATCHED
+        var<private> height_uniform: ShaderHeightUniform;
+    #else
+        @group(5) @binding(0)
+        var<uniform> height_uniform: ShaderHeightUniform;
+    #endif
 #endif
 #import bevy_pbr::mesh_functions
 
+#ifdef LIT
+    #import bevy_pbr::pbr_types
+    #import bevy_pbr::utils
+    #import bevy_pbr::clustered_forward
+    #import bevy_pbr::lighting
+    #import bevy_pbr::pbr_ambient
+    #import bevy_pbr::shadows
+    #import bevy_pbr::fog
+    #import bevy_pbr::pbr_functions
+#endif
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec4<f32>,
+#ifdef LIT
+    @location(1) world_position: vec4<f32>,
+    @location(2) world_normal: vec3<f32>,
+#endif
 };
 
-const NOISE_TEXTURE_SPEED: f32 = 50.;
-const NOISE_TEXTURE_ZOOM: f32 = 35.;
+// how much the gusts lag behind in the direction of the wind per unit of distance
+const GUST_TRAVEL_DELAY: f32 = 0.05;
+const TAU: f32 = 6.283185;
+// scales the wind strength with gusts rolling over the grass in the direction of the wind
+fn gust_factor(vertex_position: vec2<f32>) -> f32 {
+    let wind_length = length(config.wind);
+    if config.gust_strength <= 0. || wind_length == 0. {
+        return 1.;
+    }
+    let along_wind = dot(vertex_position, config.wind / wind_length);
+    let delay = along_wind * GUST_TRAVEL_DELAY * config.gust_frequency;
+    // two overlapping waves make the gusts less regular, the second one is 2.31 times faster
+    let phase = (config.gust_phases.x - delay) * TAU;
+    let second_phase = (config.gust_phases.y - delay * 2.31) * TAU + 1.7;
+    let wave = sin(phase) * 0.6 + sin(second_phase) * 0.4;
+    return 1. + config.gust_strength * max(wave, 0.);
+}
 fn wind_offset(vertex_position: vec2<f32>) -> vec2<f32> {
-    var texture_offset = config.wind.xy * globals.time * NOISE_TEXTURE_SPEED;
-    var texture_position = vec2<f32>(vertex_position.x ,vertex_position.y) * NOISE_TEXTURE_ZOOM + texture_offset;
+    var texture_position = vec2<f32>(vertex_position.x ,vertex_position.y) * config.wind_noise_scale + config.wind_offset;
     
     // dimensions of noise texture in vec2<u32>
-    let dim = textureDimensions(noise_texture, 0);
+    let dim = vec2<f32>(textureDimensions(noise_texture, 0));
 
-    // read just position in case of a over/under flow of tex. coords
-    texture_position = abs(texture_position % vec2<f32>(dim));
+    // repeats the texture, the offset is wrapped to its size so the wrapping must not flip negative positions
+    texture_position = texture_position - floor(texture_position / dim) * dim;
     var texture_pixel = textureLoad(noise_texture, vec2<i32>(i32(texture_position.x),i32(texture_position.y)), 0);
-    return texture_pixel.xy * config.wind;
+    return texture_pixel.xy * config.wind * gust_factor(vertex_position);
 }
-const BIG_PRIME: f32 = 7759.;
-
-fn density_map_offset(vertex_position: vec2<f32>) -> vec2<f32> {
-    var texture_position = vec2<f32>(vertex_position.x ,vertex_position.y) * BIG_PRIME ;
-    
-    // dimensions of noise texture in vec2<u32>
-    let dim = textureDimensions(noise_texture, 0);
-
-    // read just position in case of a over/under flow of tex. coords
-    texture_position = abs(texture_position % vec2<f32>(dim));
-    var texture_pixel = textureLoad(noise_texture, vec2<i32>(i32(texture_position.x),i32(texture_position.y)), 0);
-    return texture_pixel.xz - vec2<f32>(0.5,0.5) ;
+const WIND_SOURCE_DIRECTIONAL: u32 = 0u;
+const WIND_SOURCE_RADIAL: u32 = 1u;
+const WIND_SOURCE_VORTEX: u32 = 2u;
+// sums up the offset of all local wind sources at the world position of a blade
+fn local_wind_offset(world_position: vec3<f32>) -> vec2<f32> {
+    var offset = vec2<f32>(0.);
+    for (var i = 0u; i < min(wind_sources.count, MAX_WIND_SOURCES); i++) {
+        let source = wind_sources.sources[i];
+        let kind = u32(source.center.w);
+        let to_blade = world_position - source.center.xyz;
+        if kind == WIND_SOURCE_DIRECTIONAL {
+            let inside = abs(to_blade) / max(source.extents.xyz, vec3<f32>(0.0001));
+            if all(inside <= vec3<f32>(1.)) {
+                // fades out over the outer fifth of the box
+                let fade = clamp((1. - max(inside.x, max(inside.y, inside.z))) * 5., 0., 1.);
+                offset += source.wind.xy * fade;
+            }
+        } else {
+            let radius = source.extents.x;
+            let distance = length(to_blade);
+            let horizontal = length(to_blade.xz);
+            if distance < radius && horizontal > 0. {
+                let fade = 1. - distance / radius;
+                let outwards = to_blade.xz / horizontal;
+                if kind == WIND_SOURCE_RADIAL {
+                    offset += outwards * source.wind.x * fade;
+                } else if kind == WIND_SOURCE_VORTEX {
+                    offset += vec2<f32>(outwards.y, -outwards.x) * source.wind.x * fade;
+                }
+            }
+        }
+    }
+    return offset;
+}
+// sums up how far the interactors push the tip of a blade at the given world position away from them
+fn interaction_offset(world_position: vec3<f32>) -> vec2<f32> {
+    var offset = vec2<f32>(0.);
+    for (var i = 0u; i < min(interactors.count, MAX_GRASS_INTERACTORS); i++) {
+        let interactor = interactors.interactors[i];
+        let to_blade = world_position - interactor.position.xyz;
+        let distance = length(to_blade);
+        let horizontal = length(to_blade.xz);
+        if distance < interactor.position.w && horizontal > 0. {
+            let fade = 1. - distance / interactor.position.w;
+            offset += to_blade.xz / horizontal * interactor.strength * fade;
+        }
+    }
+    return offset;
+}
+// the position in the chunk relative to the area the trample and cut masks are stretched over
+fn mask_uv(vertex_position: vec2<f32>) -> vec2<f32> {
+    #ifdef EXPLICIT
+        return (vertex_position - mask_bounds.min) / max(mask_bounds.size, vec2<f32>(0.0001));
+    #else
+        return abs(vertex_position / aabb.vect.xz);
+    #endif
+}
+#ifdef TRAMPLE_MASK
+    // how much the blade at the position in the chunk was trampled, between 0 and 1
+    fn sample_trample_mask(vertex_position: vec2<f32>) -> f32 {
+        let dim = vec2<i32>(textureDimensions(trample_mask, 0));
+        let texel = vec2<i32>(floor(mask_uv(vertex_position) * vec2<f32>(dim)));
+        return textureLoad(trample_mask, clamp(texel, vec2<i32>(0), dim - 1), 0).r;
+    }
+#endif
+#ifdef CUT_MASK
+    // the remaining height (x) and density (y) of the grass at the position in the chunk
+    fn sample_cut_mask(vertex_position: vec2<f32>) -> vec2<f32> {
+        let dim = vec2<i32>(textureDimensions(cut_mask, 0));
+        let texel = vec2<i32>(floor(mask_uv(vertex_position) * vec2<f32>(dim)));
+        return textureLoad(cut_mask, clamp(texel, vec2<i32>(0), dim - 1), 0).rg;
+    }
+#endif
+// pcg hash, see https://www.jcgt.org/published/0009/03/02/
+fn hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+// returns a pseudo random value between 0 and 1 which only depends on the given position and the configured seed.
+// Different channels give independent values for the same position
+fn random_from_position(position: vec2<f32>, channel: u32) -> f32 {
+    let seed = hash(bitcast<u32>(position.x) ^ hash(bitcast<u32>(position.y) ^ hash(channel ^ hash(config.seed))));
+    return f32(seed) / 4294967295.;
+}
+// moves the blade by up to half a unit in each direction, which hides the grid of the dithering
+fn jitter_offset(position: vec2<f32>) -> vec2<f32> {
+    let jitter = vec2<f32>(random_from_position(position, JITTER_X_CHANNEL), random_from_position(position, JITTER_Z_CHANNEL));
+    return jitter - vec2<f32>(0.5, 0.5);
+}
+const LOD_FADE_SHARPNESS: f32 = 10.;
+const ROTATION_CHANNEL: u32 = 0u;
+const SHADOW_CHANNEL: u32 = 1u;
+// has to match the cull shader, which removes the blades thinned out by the lod
+const LOD_CHANNEL: u32 = 2u;
+const JITTER_X_CHANNEL: u32 = 3u;
+const JITTER_Z_CHANNEL: u32 = 4u;
+const TRAMPLE_CHANNEL: u32 = 5u;
+const GROWTH_CHANNEL: u32 = 6u;
+fn rotate_y(position: vec3<f32>, angle: f32) -> vec3<f32> {
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec3<f32>(c * position.x + s * position.z, position.y, c * position.z - s * position.x);
 }
 #ifdef EXPLICIT
 #else
+    const FILTER_BILINEAR: u32 = 1u;
+    const FILTER_BICUBIC: u32 = 2u;
+    // the height maps are bound as non filterable textures, so the interpolation is done by hand.
+    // Pixels outside of the texture are clamped to its border
+    fn load_height(texture: texture_2d<f32>, texel: vec2<i32>, dim: vec2<i32>) -> f32 {
+        return textureLoad(texture, clamp(texel, vec2<i32>(0), dim - 1), 0).r;
+    }
+    // the weights of the four pixels around a position for Catmull-Rom interpolation
+    fn catmull_rom_weights(t: f32) -> vec4<f32> {
+        let t2 = t * t;
+        let t3 = t2 * t;
+        return vec4<f32>(
+            -0.5 * t3 + t2 - 0.5 * t,
+            1.5 * t3 - 2.5 * t2 + 1.,
+            -1.5 * t3 + 2. * t2 + 0.5 * t,
+            0.5 * t3 - 0.5 * t2,
+        );
+    }
+    // samples a texture stretched over the chunk at a position in the chunk
+    // using the filter and border of the height map of the chunk.
+    // Mirrored on the cpu in `height_map.rs`
+    fn sample_chunk_texture(texture: texture_2d<f32>, vertex_position: vec2<f32>) -> f32 {
+        let dim = vec2<i32>(textureDimensions(texture, 0));
+        // the border is only read by the interpolation
+        let border = vec2<f32>(f32(aabb.height_map_border));
+        let texture_position = abs(vertex_position / aabb.vect.xz) * max(vec2<f32>(dim) - 2. * border, vec2<f32>(1.)) + border;
+        var value = 0.;
+        if aabb.height_map_filter == FILTER_BILINEAR {
+            // pixel centers lie in the middle of the pixels
+            let position = texture_position - 0.5;
+            let texel = vec2<i32>(floor(position));
+            let fraction = position - floor(position);
+            let bottom = mix(load_height(texture, texel, dim), load_height(texture, texel + vec2<i32>(1, 0), dim), fraction.x);
+            let top = mix(load_height(texture, texel + vec2<i32>(0, 1), dim), load_height(texture, texel + vec2<i32>(1, 1), dim), fraction.x);
+            value = mix(bottom, top, fraction.y);
+        } else if aabb.height_map_filter == FILTER_BICUBIC {
+            let position = texture_position - 0.5;
+            let texel = vec2<i32>(floor(position));
+            let fraction = position - floor(position);
+            let weights_x = catmull_rom_weights(fraction.x);
+            let weights_y = catmull_rom_weights(fraction.y);
+            for (var y = 0; y < 4; y++) {
+                var row = 0.;
+                for (var x = 0; x < 4; x++) {
+                    row += load_height(texture, texel + vec2<i32>(x - 1, y - 1), dim) * weights_x[x];
+                }
+                value += row * weights_y[y];
+            }
+        } else {
+            value = load_height(texture, vec2<i32>(texture_position), dim);
+        }
+        return value;
+    }
+    // samples the height map at a position in the chunk
+    fn sample_height_map(vertex_position: vec2<f32>) -> f32 {
+        return sample_chunk_texture(height_map, vertex_position) * aabb.vect.y;
+    }
+    // samples the `WarblerHeight::Texture` at a position in the chunk, filtered like the height map
     fn texture2d_offset(texture: texture_2d<f32>, vertex_position: vec2<f32>) -> f32 {
-        let dim = textureDimensions(texture, 0);
-        let texture_position = abs((vertex_position.xy / aabb.vect.xz ) * vec2<f32>(dim)) ;
-        var texture_r = textureLoad(texture, vec2<i32>(i32(texture_position.x),i32(texture_position.y)), 0).r;
-        return texture_r * aabb.vect.y;
+        return sample_chunk_texture(texture, vertex_position) * aabb.vect.y;
+    }
+#endif
+#ifdef BATCHED
+    // loads the data of the chunk the instance belongs to
+    fn load_batched_chunk(instance_index: u32) {
+        // the chunks are sorted by their first instance
+        var low = 0u;
+        var high = arrayLength(&chunks) - 1u;
+        loop {
+            if low >= high {
+                break;
+            }
+            let mid = (low + high + 1u) / 2u;
+            if chunks[mid].first_instance <= instance_index {
+                low = mid;
+            } else {
+                high = mid - 1u;
+            }
+        }
+        let chunk = chunks[low];
+        mesh.model = chunk.model;
+        mesh.inverse_transpose_model = chunk.inverse_transpose_model;
+        mesh.flags = 0u;
+        color.main_color = chunk.main_color;
+        color.bottom_color = chunk.bottom_color;
+        color.lod = chunk.lod;
+        aabb.vect = chunk.aabb.xyz;
+        aabb.height_map_filter = chunk.height_map_filter;
+        aabb.height_map_border = chunk.height_map_border;
+        #ifdef HEIGHT_TEXTURE
+        #else
+            height_uniform.height = chunk.aabb.w;
+        #endif
     }
 #endif
 // 2d textures are used to store vertex information.
@@ -106,18 +447,29 @@ fn storage_pixel_from_texture(index: u32, texture: texture_2d<f32>) -> vec4<f32>
 @vertex
 fn vertex(vertex: Vertex, @builtin(instance_index) instance_index: u32) -> VertexOutput {
     var out: VertexOutput;
-    
+    #ifdef BATCHED
+        load_batched_chunk(instance_index);
+    #endif
+
+    #ifdef SHADOW_PASS
+        // only a part of the blades cast shadows, the others are moved out of the view of the light
+        if random_from_position(vertex.xz_position, SHADOW_CHANNEL) > config.shadow_blade_fraction {
+            out.clip_position = vec4<f32>(2., 2., 2., 1.);
+            return out;
+        }
+    #endif
+
     var position_field_offset = vec3<f32>(vertex.xz_position.x, 0.,vertex.xz_position.y);
 
-    let density_offset = density_map_offset(position_field_offset.xz) / 1.;
-    position_field_offset += vec3<f32>(density_offset.x, 0.,density_offset.y);
+    let jitter = jitter_offset(vertex.xz_position);
+    position_field_offset += vec3<f32>(jitter.x, 0., jitter.y);
     // ---Y_POSITIONS---
     #ifdef EXPLICIT
         // from explicit y positions
         position_field_offset.y = storage_pixel_from_texture(instance_index, y_positions).r;
     #else
        // from height map
-        position_field_offset.y = texture2d_offset(height_map, position_field_offset.xz);
+        position_field_offset.y = sample_height_map(position_field_offset.xz);
     #endif
     // ---HEIGHT---
     var height = 0.;
@@ -126,7 +478,54 @@ fn vertex(vertex: Vertex, @builtin(instance_index) instance_index: u32) -> Verte
     #else
         height = height_uniform.height;
     #endif
-    var position = vertex.vertex_position * vec3<f32>(1.,height, 1.) + position_field_offset;
+    #ifdef INSTANCE_DATA
+        height = vertex.blade_attributes.x;
+    #endif
+    // ---ROTATION---
+    var blade_position = vertex.vertex_position;
+    #ifdef INSTANCE_DATA
+        blade_position.x *= vertex.blade_attributes.y;
+        blade_position.z *= vertex.blade_attributes.y;
+    #endif
+    // ---CUT---
+    #ifdef CUT_MASK
+        let growth = sample_cut_mask(position_field_offset.xz);
+        height *= growth.x;
+        // only a part of the blades sprouted again where the grass was removed.
+        // Removed blades vanish instead of lying flat on the ground
+        if growth.y <= 0. || random_from_position(vertex.xz_position, GROWTH_CHANNEL) > growth.y {
+            blade_position = vec3<f32>(0.);
+        }
+    #endif
+    #ifdef LOD
+        // the further away a blade is, the more likely it is to be thinned out.
+        // Blades shrink into the ground before vanishing, so they don't pop
+        let root = mesh_position_local_to_world(mesh.model, vec4<f32>(position_field_offset, 1.0));
+        let camera_distance = distance(view.world_position.xyz, root.xyz);
+        let density = 1. - smoothstep(color.lod.x, color.lod.y, camera_distance);
+        let threshold = random_from_position(vertex.xz_position, LOD_CHANNEL);
+        blade_position *= clamp((density - threshold) * LOD_FADE_SHARPNESS, 0., 1.);
+    #endif
+    var angle = 0.;
+    #ifdef BLADE_ROTATION
+        angle += (random_from_position(vertex.xz_position, ROTATION_CHANNEL) * 2. - 1.) * config.max_blade_rotation;
+    #endif
+    #ifdef INSTANCE_DATA
+        angle += vertex.blade_attributes.z;
+    #endif
+    blade_position = rotate_y(blade_position, angle);
+    var position = blade_position * vec3<f32>(1.,height, 1.) + position_field_offset;
+
+    // ---TRAMPLE---
+    #ifdef TRAMPLE_MASK
+        // trampled blades lean over in a random direction
+        let trampled = sample_trample_mask(position_field_offset.xz);
+        let lean_angle = random_from_position(vertex.xz_position, TRAMPLE_CHANNEL) * TAU;
+        let height_above_ground = blade_position.y * height;
+        position.x += cos(lean_angle) * trampled * height_above_ground * 0.8;
+        position.z += sin(lean_angle) * trampled * height_above_ground * 0.8;
+        position.y -= trampled * height_above_ground * 0.6;
+    #endif
 
     // ---WIND---
     // only applies wind if the vertex is not on the bottom of the grass (or very small)
@@ -136,16 +535,62 @@ fn vertex(vertex: Vertex, @builtin(instance_index) instance_index: u32) -> Verte
     position.z += offset.y * strength;
     
     // ---CLIP_POSITION---
-    out.clip_position = mesh_position_local_to_clip(mesh.model, vec4<f32>(position, 1.0));
+    var world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(position, 1.0));
+    // local wind sources are placed in world space
+    let blade_world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(position_field_offset, 1.0));
+    let local_offset = local_wind_offset(blade_world_position.xyz);
+    world_position.x += local_offset.x * strength;
+    world_position.z += local_offset.y * strength;
+    // interactors bend the blades away, which also lowers their tips
+    let interaction = interaction_offset(blade_world_position.xyz) * clamp(vertex.vertex_position.y, 0., 1.);
+    world_position.x += interaction.x;
+    world_position.z += interaction.y;
+    world_position.y -= length(interaction) * 0.5;
+    out.clip_position = mesh_position_world_to_clip(world_position);
+    #ifdef DEPTH_CLAMP_ORTHO
+        out.clip_position.z = min(out.clip_position.z, 1.0);
+    #endif
+
+    // ---NORMAL---
+    #ifdef LIT
+        out.world_position = world_position;
+        // blades are lit like the ground they are standing on, unless the mesh brings its own normals
+        var normal = vec3<f32>(0., 1., 0.);
+        #ifdef VERTEX_NORMALS
+            normal = rotate_y(vertex.vertex_normal, angle);
+        #endif
+        out.world_normal = mesh_normal_local_to_world(normal);
+    #endif
 
     // ---COLOR---
     let lambda = clamp(vertex.vertex_position.y, 0.,1.);
     out.color = mix(color.bottom_color, color.main_color, lambda);
+    #ifdef INSTANCE_DATA
+        out.color *= vertex.blade_color;
+    #endif
     return out;
 }
 
 
 @fragment
 fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+#ifdef LIT
+    var pbr_input = pbr_input_new();
+    pbr_input.material.base_color = in.color;
+    // grass is a rough, non reflective surface
+    pbr_input.material.perceptual_roughness = 1.;
+    pbr_input.material.reflectance = 0.1;
+    pbr_input.frag_coord = in.clip_position;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = normalize(in.world_normal);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = pbr_input.world_normal;
+    pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
+    #ifdef RECEIVE_SHADOWS
+        pbr_input.flags = MESH_FLAGS_SHADOW_RECEIVER_BIT;
+    #endif
+    return pbr(pbr_input);
+#else
     return in.color;
+#endif
 }
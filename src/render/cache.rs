@@ -1,9 +1,19 @@
+use std::{
+    marker::PhantomData,
+    sync::{atomic::AtomicBool, Arc},
+};
+
 use bevy::{
     prelude::*,
-    render::render_resource::{BindGroup, Buffer},
-    utils::HashMap,
+    render::{
+        render_resource::{BindGroup, Buffer, BufferId, TextureView, TextureViewId},
+        renderer::RenderQueue,
+    },
+    utils::{hashbrown::hash_map::Entry, HashMap},
 };
 
+use crate::bundle::{Grass, GrassInstanceData};
+
 #[derive(Resource, DerefMut, Deref, Debug, Default)]
 pub(crate) struct ExplicitGrassCache {
     pub data: HashMap<Entity, CachedExplicitGrassChunk>,
@@ -11,8 +21,163 @@ pub(crate) struct ExplicitGrassCache {
 
 #[derive(Debug, Default)]
 pub(crate) struct CachedExplicitGrassChunk {
+    /// The grass extracted in this frame, if it changed since the buffers were created
+    pub changed_grass: Option<(Grass, Option<GrassInstanceData>)>,
     pub explicit_xz_buffer: Option<Buffer>,
+    /// The per blade attributes, if the chunk has the [`GrassInstanceData`] component
+    pub explicit_instance_buffer: Option<Buffer>,
     pub explicit_count: u32,
+    /// The y positions of the blades, stored in a texture
+    pub y_positions: Option<TextureView>,
+    pub y_bind_group: Option<BindGroup>,
+    /// The trample and cut masks and the area they are stretched over, which the `y_bind_group` was created with
+    pub mask_key: Option<([TextureViewId; 2], Vec4)>,
+    pub height_bind_group: Option<BindGroup>,
+}
+/// Caches the bind groups of the chunks between frames, so they are only created again if needed
+///
+/// `T` is the component the bind group is created from
+#[derive(Resource)]
+pub(crate) struct BindGroupCache<T> {
+    pub data: HashMap<Entity, CachedBindGroup>,
+    _inner: PhantomData<T>,
+}
+impl<T> Default for BindGroupCache<T> {
+    fn default() -> Self {
+        Self {
+            data: Default::default(),
+            _inner: PhantomData,
+        }
+    }
+}
+impl<T> BindGroupCache<T> {
+    /// Returns the cached bind group of the entity.
+    ///
+    /// The bind group is created if it doesn't exist or if it was created for other textures
+    pub fn get_or_create(
+        &mut self,
+        entity: Entity,
+        textures: &[TextureViewId],
+        create: impl FnOnce() -> CachedBindGroup,
+    ) -> &mut CachedBindGroup {
+        match self.data.entry(entity) {
+            Entry::Occupied(mut entry) => {
+                if entry.get().textures != textures {
+                    entry.insert(create());
+                }
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => entry.insert(create()),
+        }
+    }
+}
+#[derive(Debug)]
+pub(crate) struct CachedBindGroup {
+    pub bind_group: BindGroup,
+    /// The textures bound in the bind group
+    pub textures: Vec<TextureViewId>,
+    /// The uniform buffer bound in the bind group and its current content
+    pub uniform: Option<(Buffer, Vec<u8>)>,
+}
+impl CachedBindGroup {
+    /// Writes the data into the uniform buffer, if it differs from the current content
+    pub fn write_uniform(&mut self, render_queue: &RenderQueue, data: &[u8]) {
+        if let Some((buffer, content)) = self.uniform.as_mut() {
+            if content.as_slice() != data {
+                render_queue.write_buffer(buffer, 0, data);
+                *content = data.to_vec();
+            }
+        }
+    }
+}
+/// Caches the blade positions of chunks dithered on the gpu
+#[derive(Resource, DerefMut, Deref, Debug, Default)]
+pub(crate) struct GpuDitherCache {
+    pub data: HashMap<Entity, CachedGpuDitheredChunk>,
+}
+
+#[derive(Debug)]
+pub(crate) struct CachedGpuDitheredChunk {
+    /// The inputs the positions were generated from
+    pub key: GpuDitherKey,
+    pub positions: Buffer,
+    /// Contains the arguments for an indirect draw call, the instance count is written by the compute shader
+    pub indirect_args: Buffer,
+    /// The index or vertex count of the mesh that is currently stored in the indirect arguments
+    pub vertex_count: u32,
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct GpuDitherKey {
+    pub density_map: TextureViewId,
+    pub density: f32,
+    pub field_size: Vec2,
+}
+/// The batches of all chunks with the [`BatchedGrass`](crate::bundle::BatchedGrass) component
+///
+/// The batches are stored by the first chunk entity of the batch, which is used to draw it
+#[derive(Resource, DerefMut, Deref, Default)]
+pub(crate) struct GrassBatchCache {
+    pub data: HashMap<Entity, CachedGrassBatch>,
+}
+/// Chunks are only batched if they share everything except the data stored per chunk
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct GrassBatchKey {
+    pub mesh: Handle<Mesh>,
+    pub height_map: Handle<Image>,
+    /// `None` if the chunks have a uniform height
+    pub heights: Option<Handle<Image>>,
+    pub blade_rotation: bool,
+    pub lit: bool,
+    pub receive_shadows: bool,
+    pub cast_shadows: bool,
+    /// Whether the chunks are thinned out with the distance to the camera
+    pub lod: bool,
+}
+pub(crate) struct CachedGrassBatch {
+    pub key: GrassBatchKey,
+    /// The chunks and blade buffers the batch was created from
+    pub chunks: Vec<(Entity, BufferId)>,
+    pub textures: (TextureViewId, TextureViewId),
+    /// The blade positions of all chunks
+    pub positions: Buffer,
+    pub instance_count: u32,
+    /// The transform, color and size of each chunk
+    pub chunk_buffer: Buffer,
+    pub bind_group: BindGroup,
+    /// The transform of the first chunk, used to sort the batch
+    pub transform: Mat4,
+    /// The center of a sphere containing all chunks of the batch in world space
+    pub center: Vec3,
+    /// The radius of the sphere containing all chunks
+    pub radius: f32,
+    /// The largest far distance of the [`GrassLod`](crate::GrassLod) of the chunks
+    pub lod_far: Option<f32>,
+}
+/// The culled blades of chunks with the [`GpuCulling`](crate::bundle::GpuCulling) component
+#[derive(Resource, Default)]
+pub(crate) struct GpuCullingCache {
+    /// The culled blades of each chunk, stored by the view and the chunk entity
+    pub data: HashMap<(Entity, Entity), CachedCulledChunk>,
+    /// Buffers containing the blade counts of previous frames, which are read back for diagnostics
+    pub readbacks: Vec<CullingReadback>,
+}
+pub(crate) struct CachedCulledChunk {
+    /// The blade buffer of the chunk the bind group was created with
+    pub source: BufferId,
+    /// Contains the blade count of chunks dithered on the cpu
+    pub source_args: Option<Buffer>,
+    /// The visible blades
+    pub positions: Buffer,
+    /// Contains the arguments for an indirect draw call, the instance count is written by the compute shader
+    pub indirect_args: Buffer,
+    pub config: Buffer,
+    pub bind_group: BindGroup,
+}
+pub(crate) struct CullingReadback {
+    /// Contains the total and visible blade count of each culled chunk
+    pub buffer: Buffer,
+    /// Set once the buffer is mapped
+    pub mapped: Arc<AtomicBool>,
 }
 #[derive(Resource, Default)]
 pub(crate) struct UniformBuffer(pub Option<BindGroup>);
@@ -24,3 +189,6 @@ impl UniformBuffer {
         self.0.as_ref().unwrap()
     }
 }
+/// The bind group of the views used to draw the shadow maps of lights
+#[derive(Resource, Default)]
+pub(crate) struct ShadowViewBindGroup(pub Option<BindGroup>);
@@ -0,0 +1,78 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType,
+            BufferBindingType, CachedComputePipelineId, ComputePipelineDescriptor, PipelineCache,
+            ShaderStages,
+        },
+        renderer::RenderDevice,
+    },
+};
+
+use crate::warblers_plugin::CULL_SHADER_HANDLE;
+
+/// The compute pipeline used to cull the blades of chunks with the
+/// [`GpuCulling`](crate::bundle::GpuCulling) component
+#[derive(Resource)]
+pub struct CullPipeline {
+    pub layout: BindGroupLayout,
+    pub pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for CullPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let storage = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("warbler_grass cull layout"),
+            entries: &[
+                // cull configuration
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // blade positions of the chunk
+                storage(1, true),
+                // indirect draw arguments of the chunk
+                storage(2, true),
+                // visible blade positions
+                storage(3, false),
+                // indirect draw arguments of the visible blades
+                storage(4, false),
+            ],
+        });
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("Grass Cull Pipeline".into()),
+            layout: vec![layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: CULL_SHADER_HANDLE.typed(),
+            shader_defs: Vec::new(),
+            entry_point: "cull".into(),
+        });
+        CullPipeline { layout, pipeline }
+    }
+}
+
+/// Checks whether the device is able to cull blades using compute shaders
+pub(crate) fn gpu_culling_supported(render_device: &RenderDevice) -> bool {
+    let limits = render_device.limits();
+    limits.max_compute_workgroups_per_dimension > 0
+        && limits.max_storage_buffers_per_shader_stage >= 4
+}
@@ -8,6 +8,7 @@ use bevy::{
         mesh::GpuBufferInfo,
         render_asset::RenderAssets,
         render_phase::{PhaseItem, RenderCommand, RenderCommandResult, TrackedRenderPass},
+        view::ViewUniformOffset,
     },
 };
 
@@ -18,7 +19,10 @@ use crate::{
 };
 
 use super::{
-    cache::{ExplicitGrassCache, UniformBuffer},
+    cache::{
+        ExplicitGrassCache, GpuCullingCache, GpuDitherCache, GrassBatchCache, ShadowViewBindGroup,
+        UniformBuffer,
+    },
     prepare::BindGroupBuffer,
 };
 pub(crate) struct SetUniformBindGroup<const I: usize>;
@@ -40,6 +44,27 @@ impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetUniformBindGroup<I> {
         RenderCommandResult::Success
     }
 }
+pub(crate) struct SetShadowViewBindGroup<const I: usize>;
+
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetShadowViewBindGroup<I> {
+    type Param = SRes<ShadowViewBindGroup>;
+    type ViewWorldQuery = Read<ViewUniformOffset>;
+    type ItemWorldQuery = ();
+
+    fn render<'w>(
+        _item: &P,
+        view_uniform_offset: &'_ ViewUniformOffset,
+        _entity: (),
+        bind_group: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(bind_group) = bind_group.into_inner().0.as_ref() else {
+            return RenderCommandResult::Failure;
+        };
+        pass.set_bind_group(I, bind_group, &[view_uniform_offset.offset]);
+        RenderCommandResult::Success
+    }
+}
 pub(crate) struct SetYBindGroup<const I: usize>;
 
 impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetYBindGroup<I> {
@@ -56,10 +81,9 @@ impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetYBindGroup<I> {
     ) -> RenderCommandResult {
         let Some(bind_group) = bind_group else {
             return RenderCommandResult::Failure;
-
         };
         pass.set_bind_group(I, &bind_group.bind_group, &[]);
-        return RenderCommandResult::Success;
+        RenderCommandResult::Success
     }
 }
 pub(crate) struct SetColorBindGroup<const I: usize>;
@@ -100,6 +124,66 @@ impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetHeightBindGroup<I> {
         RenderCommandResult::Success
     }
 }
+pub(crate) struct SetBatchBindGroup<const I: usize>;
+
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetBatchBindGroup<I> {
+    type Param = SRes<GrassBatchCache>;
+    type ViewWorldQuery = ();
+    type ItemWorldQuery = ();
+
+    fn render<'w>(
+        item: &P,
+        _view: (),
+        _entity: (),
+        batches: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(batch) = batches.into_inner().get(&item.entity()) else {
+            return RenderCommandResult::Failure;
+        };
+        pass.set_bind_group(I, &batch.bind_group, &[]);
+        RenderCommandResult::Success
+    }
+}
+/// Draws all blades of a batch
+pub(crate) struct DrawGrassBatch;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawGrassBatch {
+    type Param = (SRes<RenderAssets<Mesh>>, SRes<GrassBatchCache>);
+    type ViewWorldQuery = ();
+    type ItemWorldQuery = ();
+
+    fn render<'w>(
+        item: &P,
+        _view: (),
+        _entity: (),
+        (meshes, batches): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(batch) = batches.into_inner().get(&item.entity()) else {
+            return RenderCommandResult::Failure;
+        };
+        let Some(gpu_mesh) = meshes.into_inner().get(&batch.key.mesh) else {
+            return RenderCommandResult::Failure;
+        };
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, batch.positions.slice(..));
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                count,
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed(0..*count, 0, 0..batch.instance_count);
+            }
+            GpuBufferInfo::NonIndexed { vertex_count } => {
+                pass.draw(0..*vertex_count, 0..batch.instance_count);
+            }
+        }
+        RenderCommandResult::Success
+    }
+}
 pub(crate) struct SetVertexBuffer;
 
 impl<P: PhaseItem> RenderCommand<P> for SetVertexBuffer {
@@ -107,19 +191,25 @@ impl<P: PhaseItem> RenderCommand<P> for SetVertexBuffer {
         SRes<RenderAssets<Mesh>>,
         SRes<ExplicitGrassCache>,
         SRes<RenderAssets<DitheredBuffer>>,
+        SRes<GpuDitherCache>,
+        SRes<GpuCullingCache>,
     );
-    type ViewWorldQuery = ();
+    type ViewWorldQuery = Entity;
     type ItemWorldQuery = (Read<Handle<Mesh>>, Option<Read<Handle<DitheredBuffer>>>);
 
     #[inline]
     fn render<'w>(
         item: &P,
-        _view: (),
+        view: Entity,
         (mesh_handle, dither_handle): (
             &'w Handle<bevy::prelude::Mesh>,
             Option<&'w Handle<DitheredBuffer>>,
         ),
-        (meshes, cache, dither): SystemParamItem<'w, '_, Self::Param>,
+        (meshes, cache, dither, gpu_dither_cache, culling_cache): SystemParamItem<
+            'w,
+            '_,
+            Self::Param,
+        >,
         pass: &mut TrackedRenderPass<'w>,
     ) -> RenderCommandResult {
         let gpu_mesh = match meshes.into_inner().get(mesh_handle) {
@@ -128,6 +218,36 @@ impl<P: PhaseItem> RenderCommand<P> for SetVertexBuffer {
         };
 
         pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+
+        // the blades of culled chunks and chunks dithered on the gpu are only counted on the gpu
+        let indirect_chunk = culling_cache
+            .into_inner()
+            .data
+            .get(&(view, item.entity()))
+            .map(|chunk| (&chunk.positions, &chunk.indirect_args))
+            .or_else(|| {
+                gpu_dither_cache
+                    .into_inner()
+                    .get(&item.entity())
+                    .map(|chunk| (&chunk.positions, &chunk.indirect_args))
+            });
+        if let Some((positions, indirect_args)) = indirect_chunk {
+            pass.set_vertex_buffer(1, positions.slice(..));
+            match &gpu_mesh.buffer_info {
+                GpuBufferInfo::Indexed {
+                    buffer,
+                    index_format,
+                    ..
+                } => {
+                    pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                    pass.draw_indexed_indirect(indirect_args, 0);
+                }
+                GpuBufferInfo::NonIndexed { .. } => {
+                    pass.draw_indirect(indirect_args, 0);
+                }
+            }
+            return RenderCommandResult::Success;
+        }
         let blade_count;
 
         if let Some(dither_handle) = dither_handle {
@@ -149,6 +269,9 @@ impl<P: PhaseItem> RenderCommand<P> for SetVertexBuffer {
                 return RenderCommandResult::Failure;
             };
             pass.set_vertex_buffer(1, xz_buffer.slice(..));
+            if let Some(instance_buffer) = chunk.explicit_instance_buffer.as_ref() {
+                pass.set_vertex_buffer(2, instance_buffer.slice(..));
+            }
         }
 
         match &gpu_mesh.buffer_info {
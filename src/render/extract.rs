@@ -1,5 +1,10 @@
-use super::cache::{CachedExplicitGrassChunk, ExplicitGrassCache};
-use crate::{bundle::Grass, dithering::DitheredBuffer, height_map::HeightMap};
+use super::cache::ExplicitGrassCache;
+use crate::{
+    bundle::{BatchedGrass, Grass, GrassInstanceData},
+    density_map::{DensityMap, GpuDithering},
+    dithering::DitheredBuffer,
+    height_map::HeightMap,
+};
 use bevy::{
     prelude::*,
     render::{primitives::Aabb, Extract},
@@ -20,23 +25,93 @@ pub(crate) fn extract_grass(
     }
     commands.insert_or_spawn_batch(values);
 }
+/// Extracts the grass data of entities with the [`GpuDithering`] component into the render world
+///
+/// Chunks which were dithered on the cpu as fallback are skipped
+#[allow(clippy::type_complexity)]
+pub(crate) fn extract_gpu_dithered_grass(
+    mut commands: Commands,
+    grass_spawner: Extract<
+        Query<(Entity, &DensityMap, &Aabb), (With<GpuDithering>, Without<Handle<DitheredBuffer>>)>,
+    >,
+) {
+    let mut values = Vec::new();
+    for (entity, density_map, aabb) in grass_spawner.iter() {
+        let density_map = DensityMap {
+            density_map: density_map.density_map.clone_weak(),
+            density: density_map.density,
+            scattering: density_map.scattering,
+        };
+        values.push((entity, (density_map, *aabb, GpuDithering)));
+    }
+    commands.insert_or_spawn_batch(values);
+}
+/// Marks the entities of explicit grass chunks in the render world
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct ExplicitGrassChunk;
+
 /// Extracts the grass data of entities spawned with the [`WarblersExplicitBundle`](crate::bundle::WarblersExplicitBundle) into the render world
 ///
-/// The extraction only happens on change or creation of the entity,
+/// The positions are only extracted on change or creation of the entity,
 /// so it normally doesn't come at a high performance cost
 #[allow(clippy::type_complexity)]
 pub(crate) fn extract_grass_positions(
     mut commands: Commands,
-    grass_spawner: Extract<Query<(Entity, &Grass, &Aabb)>>,
+    grass_spawner: Extract<Query<(Entity, Ref<Grass>, Option<Ref<GrassInstanceData>>, &Aabb)>>,
     mut grass_cache: ResMut<ExplicitGrassCache>,
 ) {
+    // frees the buffers of despawned chunks
+    grass_cache.retain(|entity, _| grass_spawner.contains(*entity));
     let mut values = Vec::new();
 
-    for (entity, grass, aabb) in grass_spawner.iter() {
-        if !grass_cache.contains_key(&entity) {
-            grass_cache.insert(entity, CachedExplicitGrassChunk::default());
+    for (entity, grass, instance_data, aabb) in grass_spawner.iter() {
+        let chunk = grass_cache.entry(entity).or_default();
+        let instance_data_changed = match (&instance_data, &chunk.explicit_instance_buffer) {
+            (Some(instance_data), Some(_)) => instance_data.is_changed(),
+            (None, None) => false,
+            // the component was added or removed
+            _ => true,
+        };
+        if grass.is_changed() || instance_data_changed || chunk.explicit_xz_buffer.is_none() {
+            chunk.changed_grass = Some((
+                Grass::clone(&grass),
+                instance_data.map(|instance_data| GrassInstanceData::clone(&instance_data)),
+            ));
+        }
+        values.push((entity, (ExplicitGrassChunk, *aabb)));
+    }
+    commands.insert_or_spawn_batch(values);
+}
+
+/// The mesh and transform of a chunk with the [`BatchedGrass`] component
+#[derive(Component, Clone, Debug)]
+pub(crate) struct BatchedGrassChunk {
+    pub mesh: Handle<Mesh>,
+    pub transform: Mat4,
+}
+/// Extracts the chunks with the [`BatchedGrass`] component into the render world
+///
+/// Unlike the meshes, the chunks are also extracted if they are outside of the view,
+/// so the batches don't change whenever the camera moves. Hidden chunks are skipped
+#[allow(clippy::type_complexity)]
+pub(crate) fn extract_batched_grass(
+    mut commands: Commands,
+    chunks: Extract<
+        Query<(Entity, &Handle<Mesh>, &GlobalTransform, &ComputedVisibility), With<BatchedGrass>>,
+    >,
+) {
+    let mut values = Vec::new();
+    for (entity, mesh, transform, visibility) in chunks.iter() {
+        if !visibility.is_visible_in_hierarchy() {
+            continue;
         }
-        values.push((entity, (grass.clone(), *aabb)));
+        values.push((
+            entity,
+            BatchedGrassChunk {
+                mesh: mesh.clone_weak(),
+                transform: transform.compute_matrix(),
+            },
+        ));
     }
     commands.insert_or_spawn_batch(values);
 }
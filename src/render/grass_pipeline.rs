@@ -1,15 +1,18 @@
 use bevy::{
-    pbr::{MeshPipeline, MeshPipelineKey},
+    pbr::{MeshPipeline, MeshPipelineKey, SHADOW_FORMAT},
     prelude::*,
     render::{
         mesh::MeshVertexBufferLayout,
         render_resource::{
             BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType,
-            BufferBindingType, RenderPipelineDescriptor, ShaderStages, SpecializedMeshPipeline,
-            SpecializedMeshPipelineError, TextureSampleType, TextureViewDimension, VertexAttribute,
-            VertexBufferLayout, VertexFormat, VertexStepMode,
+            BufferBindingType, CompareFunction, DepthBiasState, DepthStencilState,
+            MultisampleState, RenderPipelineDescriptor, ShaderStages, ShaderType,
+            SpecializedMeshPipeline, SpecializedMeshPipelineError, StencilState, TextureSampleType,
+            TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexFormat,
+            VertexStepMode,
         },
         renderer::RenderDevice,
+        view::ViewUniform,
     },
 };
 
@@ -20,13 +23,12 @@ pub struct GrassPipeline {
     mesh_pipeline: MeshPipeline,
     pub region_layout: BindGroupLayout,
     pub height_map_layout: BindGroupLayout,
-    pub density_map_layout: BindGroupLayout,
     pub heights_texture_layout: BindGroupLayout,
     pub explicit_y_layout: BindGroupLayout,
-    pub explicit_height_layout: BindGroupLayout,
     pub uniform_height_layout: BindGroupLayout,
-    pub explicit_xz_layout: BindGroupLayout,
     pub color_layout: BindGroupLayout,
+    pub shadow_view_layout: BindGroupLayout,
+    pub batch_layout: BindGroupLayout,
 }
 
 impl FromWorld for GrassPipeline {
@@ -57,6 +59,28 @@ impl FromWorld for GrassPipeline {
                     },
                     count: None,
                 },
+                // local wind sources
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // grass interactors
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
         let height_map_layout =
@@ -85,15 +109,20 @@ impl FromWorld for GrassPipeline {
                         },
                         count: None,
                     },
-                ],
-            });
-        let density_map_layout =
-            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("warbler_grass density map layout"),
-                entries: &[
-                    // density map
+                    // trample mask, only used with the TRAMPLE_MASK shader def
                     BindGroupLayoutEntry {
-                        binding: 0,
+                        binding: 2,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: false },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // cut mask, only used with the CUT_MASK shader def
+                    BindGroupLayoutEntry {
+                        binding: 3,
                         visibility: ShaderStages::VERTEX,
                         ty: BindingType::Texture {
                             sample_type: TextureSampleType::Float { filterable: false },
@@ -107,30 +136,52 @@ impl FromWorld for GrassPipeline {
         let explicit_y_layout =
             render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
                 label: Some("warbler_grass explicit y layout"),
-                entries: &[BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::VERTEX,
-                    ty: BindingType::Texture {
-                        sample_type: TextureSampleType::Float { filterable: false },
-                        view_dimension: TextureViewDimension::D2,
-                        multisampled: false,
+                entries: &[
+                    // y positions
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: false },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
-            });
-        let explicit_height_layout =
-            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("warbler_grass explicit height layout"),
-                entries: &[BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::VERTEX,
-                    ty: BindingType::Texture {
-                        sample_type: TextureSampleType::Float { filterable: false },
-                        view_dimension: TextureViewDimension::D2,
-                        multisampled: false,
+                    // cut mask, only used with the CUT_MASK shader def
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: false },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    // the area the masks are stretched over
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // trample mask, only used with the TRAMPLE_MASK shader def
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: false },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
             });
         let heights_texture_layout =
             render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -146,23 +197,6 @@ impl FromWorld for GrassPipeline {
                     count: None,
                 }],
             });
-        let explicit_xz_layout =
-            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("warbler_grass explicit height layout"),
-                entries: &[
-                    // heights
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::VERTEX,
-                        ty: BindingType::Texture {
-                            sample_type: TextureSampleType::Float { filterable: false },
-                            view_dimension: TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                ],
-            });
         let uniform_height_layout =
             render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
                 label: Some("warbler_grasss configuration layout"),
@@ -190,20 +224,74 @@ impl FromWorld for GrassPipeline {
                 count: None,
             }],
         });
+        let shadow_view_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("warbler_grass shadow view layout"),
+                entries: &[
+                    // view of the light
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: Some(ViewUniform::min_size()),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let batch_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("warbler_grass batch layout"),
+            entries: &[
+                // the data of all chunks in the batch
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // height map
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // heights texture, only used if the blades don't have a uniform height
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
         let shader = GRASS_SHADER_HANDLE.typed::<Shader>();
         let mesh_pipeline = world.resource::<MeshPipeline>();
         GrassPipeline {
             shader,
             mesh_pipeline: mesh_pipeline.clone(),
             region_layout,
-            explicit_height_layout,
             uniform_height_layout,
             heights_texture_layout,
-            density_map_layout,
-            explicit_xz_layout,
             explicit_y_layout,
             height_map_layout,
             color_layout,
+            shadow_view_layout,
+            batch_layout,
         }
     }
 }
@@ -217,8 +305,14 @@ impl SpecializedMeshPipeline for GrassPipeline {
     ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
         let mut descriptor = self.mesh_pipeline.specialize(key.mesh_key, layout)?;
         descriptor.label = Some("Grass Render Pipeline".into());
-        descriptor.layout.push(self.region_layout.clone());
-        descriptor.layout.push(self.color_layout.clone());
+        if key.batched {
+            // the mesh, color, height map and height bind groups are replaced by a single one
+            descriptor.layout[1] = self.batch_layout.clone();
+            descriptor.layout.push(self.region_layout.clone());
+        } else {
+            descriptor.layout.push(self.region_layout.clone());
+            descriptor.layout.push(self.color_layout.clone());
+        }
         descriptor.vertex.buffers.push(VertexBufferLayout {
             array_stride: std::mem::size_of::<Vec2>() as u64,
             step_mode: VertexStepMode::Instance,
@@ -228,26 +322,100 @@ impl SpecializedMeshPipeline for GrassPipeline {
                 shader_location: 3, // shader locations 0-2 may be taken up by Position, Normal and UV attributes
             }],
         });
+        if key.instance_data {
+            descriptor.vertex.buffers.push(VertexBufferLayout {
+                array_stride: 2 * std::mem::size_of::<Vec4>() as u64,
+                step_mode: VertexStepMode::Instance,
+                attributes: vec![
+                    // height, width and rotation of the blade
+                    VertexAttribute {
+                        format: VertexFormat::Float32x4,
+                        offset: 0,
+                        shader_location: 7,
+                    },
+                    // color tint of the blade
+                    VertexAttribute {
+                        format: VertexFormat::Float32x4,
+                        offset: std::mem::size_of::<Vec4>() as u64,
+                        shader_location: 8,
+                    },
+                ],
+            });
+        }
         let vertex = &mut descriptor.vertex;
 
         vertex.shader = self.shader.clone();
+        if key.instance_data {
+            vertex.shader_defs.push("INSTANCE_DATA".into());
+        }
 
-        if key.is_explicit {
-            vertex.shader_defs.push("EXPLICIT".into());
-            descriptor.layout.push(self.explicit_y_layout.clone());
+        if key.batched {
+            vertex.shader_defs.push("BATCHED".into());
+            if !key.uniform_height {
+                vertex.shader_defs.push("HEIGHT_TEXTURE".into());
+            }
         } else {
-            descriptor.layout.push(self.height_map_layout.clone());
+            if key.is_explicit {
+                vertex.shader_defs.push("EXPLICIT".into());
+                descriptor.layout.push(self.explicit_y_layout.clone());
+            } else {
+                descriptor.layout.push(self.height_map_layout.clone());
+            }
+            if key.trample_mask {
+                vertex.shader_defs.push("TRAMPLE_MASK".into());
+            }
+            if key.cut_mask {
+                vertex.shader_defs.push("CUT_MASK".into());
+            }
+
+            if key.uniform_height {
+                descriptor.layout.push(self.uniform_height_layout.clone());
+            } else {
+                vertex.shader_defs.push("HEIGHT_TEXTURE".into());
+
+                descriptor.layout.push(self.heights_texture_layout.clone());
+            }
         }
 
-        if key.uniform_height {
-            descriptor.layout.push(self.uniform_height_layout.clone());
-        } else {
-            vertex.shader_defs.push("HEIGHT_TEXTURE".into());
+        if key.blade_rotation {
+            vertex.shader_defs.push("BLADE_ROTATION".into());
+        }
+        // the shadow pass doesn't know about the camera, so all blades are drawn there
+        if key.lod && !key.shadow_pass {
+            vertex.shader_defs.push("LOD".into());
+        }
+
+        if key.shadow_pass {
+            // The shadow pass only writes depth, so no fragment stage is needed
+            descriptor.label = Some("Grass Shadow Pipeline".into());
+            descriptor.layout[0] = self.shadow_view_layout.clone();
+            vertex.shader_defs.push("SHADOW_PASS".into());
+            if key.mesh_key.contains(MeshPipelineKey::DEPTH_CLAMP_ORTHO) {
+                vertex.shader_defs.push("DEPTH_CLAMP_ORTHO".into());
+            }
+            descriptor.fragment = None;
+            descriptor.depth_stencil = Some(DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::GreaterEqual,
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            });
+            descriptor.multisample = MultisampleState::default();
+            return Ok(descriptor);
+        }
 
-            descriptor.layout.push(self.heights_texture_layout.clone());
+        let fragment = descriptor.fragment.as_mut().unwrap();
+        // the output of the vertex shader changes, so the definition is needed in both stages
+        if key.lit {
+            vertex.shader_defs.push("LIT".into());
+            fragment.shader_defs.push("LIT".into());
+            if key.receive_shadows {
+                fragment.shader_defs.push("RECEIVE_SHADOWS".into());
+            }
         }
 
-        descriptor.fragment.as_mut().unwrap().shader = self.shader.clone();
+        fragment.shader = self.shader.clone();
         Ok(descriptor)
     }
 }
@@ -257,6 +425,15 @@ pub struct GrassRenderKey {
     pub mesh_key: MeshPipelineKey,
     pub is_explicit: bool,
     pub uniform_height: bool,
+    pub blade_rotation: bool,
+    pub lit: bool,
+    pub receive_shadows: bool,
+    pub shadow_pass: bool,
+    pub lod: bool,
+    pub instance_data: bool,
+    pub batched: bool,
+    pub trample_mask: bool,
+    pub cut_mask: bool,
 }
 
 impl From<MeshPipelineKey> for GrassRenderKey {
@@ -265,6 +442,15 @@ impl From<MeshPipelineKey> for GrassRenderKey {
             mesh_key,
             is_explicit: false,
             uniform_height: false,
+            blade_rotation: true,
+            lit: true,
+            receive_shadows: false,
+            shadow_pass: false,
+            lod: false,
+            instance_data: false,
+            batched: false,
+            trample_mask: false,
+            cut_mask: false,
         }
     }
 }
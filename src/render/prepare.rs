@@ -2,26 +2,52 @@ use std::marker::PhantomData;
 use std::mem;
 use std::num::{NonZeroU32, NonZeroU64};
 use std::ops::Mul;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use super::cache::UniformBuffer;
+use super::cache::{
+    BindGroupCache, CachedBindGroup, CachedCulledChunk, CachedGpuDitheredChunk, CachedGrassBatch,
+    CullingReadback, GpuCullingCache, GpuDitherCache, GpuDitherKey, GrassBatchCache, GrassBatchKey,
+    UniformBuffer,
+};
+use super::cull_pipeline::CullPipeline;
+use super::dither_pipeline::DitherPipeline;
+use super::extract::{BatchedGrassChunk, ExplicitGrassChunk};
 use super::grass_pipeline::GrassPipeline;
-use crate::bundle::{Grass, WarblerHeight};
+use crate::bundle::{
+    BatchedGrass, BladeAttributes, GpuCulling, GrassCastShadows, GrassReceiveShadows,
+    NoBladeRotation, UnlitGrass, WarblerHeight,
+};
+use crate::cutting::GrassCutMask;
+use crate::density_map::{DensityMap, GpuDithering};
+use crate::diagnostic::GpuCullingStats;
+use crate::dithering::{DitheredBuffer, GpuDitheredBuffer};
 use crate::height_map::HeightMap;
 use crate::prelude::GrassColor;
 use crate::render::cache::ExplicitGrassCache;
-use crate::{GrassConfiguration, GrassNoiseTexture};
-use bevy::math::Vec3Swizzles;
+use crate::trample::{ActiveInteractor, GrassInteractors, TrampleMask, MAX_GRASS_INTERACTORS};
+use crate::wind::{GrassWindSources, WindSource, MAX_WIND_SOURCES};
+use crate::{GrassConfiguration, GrassLod, GrassNoiseTexture, GrassWindTime};
+use bevy::core_pipeline::core_3d::Opaque3d;
+use bevy::math::{DVec2, Vec3Swizzles};
+use bevy::pbr::MeshUniform;
 use bevy::prelude::*;
-use bevy::render::primitives::Aabb;
+use bevy::render::mesh::GpuBufferInfo;
+use bevy::render::primitives::{Aabb, Frustum};
 use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_phase::RenderPhase;
 use bevy::render::render_resource::{
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource, BufferBinding,
-    BufferInitDescriptor, BufferUsages, Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d,
-    TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
-    TextureViewDescriptor, TextureViewDimension, TextureViewId,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, Buffer,
+    BufferBinding, BufferDescriptor, BufferId, BufferInitDescriptor, BufferUsages, CommandEncoder,
+    CommandEncoderDescriptor, ComputePassDescriptor, Extent3d, ImageCopyTexture, ImageDataLayout,
+    MapMode, Origin3d, PipelineCache, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+    TextureViewId,
 };
 use bevy::render::renderer::{RenderDevice, RenderQueue};
 use bevy::render::texture::FallbackImage;
+use bevy::render::view::{ExtractedView, VisibleEntities};
+use bevy::utils::HashMap;
 use bytemuck::{Pod, Zeroable};
 #[derive(Component)]
 pub(crate) struct BindGroupBuffer<T> {
@@ -32,20 +58,35 @@ impl<T> BindGroupBuffer<T> {
     pub fn new(bind_group: BindGroup) -> Self {
         BindGroupBuffer {
             bind_group,
-            _inner: PhantomData::default(),
+            _inner: PhantomData,
         }
     }
 }
+/// Creates the buffers of explicit grass chunks
+///
+/// The buffers are only created again if the [`Grass`] or [`GrassInstanceData`] component changed
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub(crate) fn prepare_explicit_positions_buffer(
     mut commands: Commands,
     mut cache: ResMut<ExplicitGrassCache>,
     pipeline: Res<GrassPipeline>,
     render_device: Res<RenderDevice>,
     render_queue: Res<RenderQueue>,
-    mut inserted_grass: Query<(Entity, &mut Grass)>,
+    fallback_img: Res<FallbackImage>,
+    images: Res<RenderAssets<Image>>,
+    chunks: Query<
+        (Entity, &Aabb, Option<&TrampleMask>, Option<&GrassCutMask>),
+        With<ExplicitGrassChunk>,
+    >,
 ) {
-    for (entity, grass) in inserted_grass.iter_mut() {
-        if let Some(chunk) = cache.get_mut(&entity) {
+    for (entity, aabb, trample_mask, cut_mask) in chunks.iter() {
+        let Some(chunk) = cache.get_mut(&entity) else {
+            warn!(
+                "Tried to prepare a entity buffer for a grass chunk which wasn't registered before"
+            );
+            continue;
+        };
+        if let Some((grass, instance_data)) = chunk.changed_grass.take() {
             chunk.explicit_count = grass.positions.len() as u32;
             let (xz, mut y): (Vec<Vec2>, Vec<f32>) =
                 grass.positions.iter().map(|v| (v.xz(), v.y)).unzip();
@@ -57,25 +98,31 @@ pub(crate) fn prepare_explicit_positions_buffer(
 
             chunk.explicit_xz_buffer = Some(buffer);
 
+            chunk.explicit_instance_buffer = instance_data.map(|instance_data| {
+                // blades without attributes use the height of the chunk
+                let default_blade = BladeAttributes {
+                    height: grass.height,
+                    ..default()
+                };
+                let blades: Vec<ShaderBladeAttributes> = (0..grass.positions.len())
+                    .map(|i| instance_data.blades.get(i).unwrap_or(&default_blade).into())
+                    .collect();
+                render_device.create_buffer_with_data(&BufferInitDescriptor {
+                    label: "blade attributes vertex buffer".into(),
+                    contents: bytemuck::cast_slice(blades.as_slice()),
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                })
+            });
+
             let view = prepare_texture_from_data(
                 &mut y,
                 &render_device,
                 &render_queue,
                 TextureFormat::R32Float,
             );
-            let layout = pipeline.explicit_y_layout.clone();
-            let bind_group_descriptor = BindGroupDescriptor {
-                label: Some("grass explicit y positions bind group"),
-                layout: &layout,
-                entries: &[BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&view),
-                }],
-            };
-            let bind_group = render_device.create_bind_group(&bind_group_descriptor);
-            commands
-                .entity(entity)
-                .insert(BindGroupBuffer::<HeightMap>::new(bind_group));
+            chunk.y_positions = Some(view);
+            // the bind group is created again below
+            chunk.y_bind_group = None;
 
             let layout = pipeline.uniform_height_layout.clone();
 
@@ -96,191 +143,1009 @@ pub(crate) fn prepare_explicit_positions_buffer(
                     }),
                 }],
             };
-            let bind_group = render_device.create_bind_group(&bind_group_descriptor);
-            commands
-                .entity(entity)
-                .insert(BindGroupBuffer::<WarblerHeight>::new(bind_group))
-                .insert(UniformHeightFlag);
-        } else {
-            warn!(
-                "Tried to prepare a entity buffer for a grass chunk which wasn't registered before"
-            );
+            chunk.height_bind_group = Some(render_device.create_bind_group(&bind_group_descriptor));
         }
+        // the masks are only read by the shader if the chunk has them
+        let trample_texture = trample_mask
+            .and_then(|trample_mask| images.get(&trample_mask.mask))
+            .map_or(&fallback_img.texture_view, |image| &image.texture_view);
+        let cut_texture = cut_mask
+            .and_then(|cut_mask| images.get(&cut_mask.mask))
+            .map_or(&fallback_img.texture_view, |image| &image.texture_view);
+        let bounds = ShaderMaskBounds {
+            min: Vec3::from(aabb.min()).xz(),
+            size: Vec3::from(aabb.half_extents).xz() * 2.,
+        };
+        let mask_key = (
+            [trample_texture.id(), cut_texture.id()],
+            Vec4::new(bounds.min.x, bounds.min.y, bounds.size.x, bounds.size.y),
+        );
+        if chunk.y_bind_group.is_none() || chunk.mask_key != Some(mask_key) {
+            if let Some(y_positions) = &chunk.y_positions {
+                let bounds_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+                    label: Some("mask bounds buffer"),
+                    contents: bytemuck::bytes_of(&bounds),
+                    usage: BufferUsages::UNIFORM,
+                });
+                let bind_group_descriptor = BindGroupDescriptor {
+                    label: Some("grass explicit y positions bind group"),
+                    layout: &pipeline.explicit_y_layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(y_positions),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::TextureView(cut_texture),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: BindingResource::Buffer(BufferBinding {
+                                buffer: &bounds_buffer,
+                                offset: 0,
+                                size: None,
+                            }),
+                        },
+                        BindGroupEntry {
+                            binding: 3,
+                            resource: BindingResource::TextureView(trample_texture),
+                        },
+                    ],
+                };
+                chunk.y_bind_group = Some(render_device.create_bind_group(&bind_group_descriptor));
+                chunk.mask_key = Some(mask_key);
+            }
+        }
+        let (Some(y_bind_group), Some(height_bind_group)) =
+            (&chunk.y_bind_group, &chunk.height_bind_group)
+        else {
+            continue;
+        };
+        commands.entity(entity).insert((
+            BindGroupBuffer::<HeightMap>::new(y_bind_group.clone()),
+            BindGroupBuffer::<WarblerHeight>::new(height_bind_group.clone()),
+            UniformHeightFlag,
+        ));
     }
 }
 #[derive(Component)]
 pub(crate) struct UniformHeightFlag;
+/// Marks chunks which are drawn as part of a batch and not on their own
+///
+/// Contains the entity the batch is stored by in the [`GrassBatchCache`]
+#[derive(Component)]
+pub(crate) struct InGrassBatch(pub Entity);
 
+/// Creates a bind group with a single uniform buffer
+fn create_uniform_bind_group(
+    render_device: &RenderDevice,
+    layout: &BindGroupLayout,
+    label: &str,
+    data: &[u8],
+) -> CachedBindGroup {
+    let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some(label),
+        contents: data,
+        usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+    });
+    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+    });
+    CachedBindGroup {
+        bind_group,
+        textures: Vec::new(),
+        uniform: Some((buffer, data.to_vec())),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn prepare_height_buffer(
     mut commands: Commands,
+    mut cache: ResMut<BindGroupCache<WarblerHeight>>,
     pipeline: Res<GrassPipeline>,
     fallback_img: Res<FallbackImage>,
     images: Res<RenderAssets<Image>>,
-
     render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
     inserted_grass: Query<(Entity, &WarblerHeight)>,
 ) {
+    cache
+        .data
+        .retain(|entity, _| inserted_grass.contains(*entity));
     for (entity, height) in inserted_grass.iter() {
-        match height.clone() {
+        match height {
             WarblerHeight::Uniform(height) => {
-                let layout = pipeline.uniform_height_layout.clone();
-
-                let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
-                    label: "grass blade height buffer".into(),
-                    contents: bytemuck::bytes_of(&ShaderHeightUniform::from(height)),
-                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+                let data = ShaderHeightUniform::from(*height);
+                let cached = cache.get_or_create(entity, &[], || {
+                    create_uniform_bind_group(
+                        &render_device,
+                        &pipeline.uniform_height_layout,
+                        "grass blade height bind group",
+                        bytemuck::bytes_of(&data),
+                    )
                 });
-                let bind_group_descriptor = BindGroupDescriptor {
-                    label: Some("grass blade height bind group"),
-                    layout: &layout,
-                    entries: &[BindGroupEntry {
-                        binding: 0,
-                        resource: BindingResource::Buffer(BufferBinding {
-                            buffer: &buffer,
-                            offset: 0,
-                            size: NonZeroU64::new(mem::size_of::<ShaderHeightUniform>() as u64),
-                        }),
-                    }],
-                };
-                let bind_group = render_device.create_bind_group(&bind_group_descriptor);
-                commands
-                    .entity(entity)
-                    .insert(BindGroupBuffer::<WarblerHeight>::new(bind_group))
-                    .insert(UniformHeightFlag);
+                cached.write_uniform(&render_queue, bytemuck::bytes_of(&data));
+                commands.entity(entity).insert((
+                    BindGroupBuffer::<WarblerHeight>::new(cached.bind_group.clone()),
+                    UniformHeightFlag,
+                ));
             }
             WarblerHeight::Texture(heights_texture) => {
-                let layout = pipeline.heights_texture_layout.clone();
-
-                let tex = if let Some(tex) = images.get(&heights_texture) {
+                let tex = if let Some(tex) = images.get(heights_texture) {
                     &tex.texture_view
                 } else {
                     &fallback_img.texture_view
                 };
-
-                let bind_group_descriptor = BindGroupDescriptor {
-                    label: Some("grass height map bind group"),
-                    layout: &layout,
-                    entries: &[BindGroupEntry {
-                        binding: 0,
-                        resource: BindingResource::TextureView(tex),
-                    }],
-                };
-
-                let bind_group = render_device.create_bind_group(&bind_group_descriptor);
+                let cached = cache.get_or_create(entity, &[tex.id()], || {
+                    let bind_group_descriptor = BindGroupDescriptor {
+                        label: Some("grass height map bind group"),
+                        layout: &pipeline.heights_texture_layout,
+                        entries: &[BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(tex),
+                        }],
+                    };
+                    CachedBindGroup {
+                        bind_group: render_device.create_bind_group(&bind_group_descriptor),
+                        textures: vec![tex.id()],
+                        uniform: None,
+                    }
+                });
                 commands
                     .entity(entity)
-                    .insert(BindGroupBuffer::<WarblerHeight>::new(bind_group));
+                    .insert(BindGroupBuffer::<WarblerHeight>::new(
+                        cached.bind_group.clone(),
+                    ));
             }
         };
     }
 }
 pub(crate) fn prepare_grass_color(
     mut commands: Commands,
+    mut cache: ResMut<BindGroupCache<GrassColor>>,
     pipeline: Res<GrassPipeline>,
     render_device: Res<RenderDevice>,
-    inserted_grass: Query<(Entity, &GrassColor)>,
+    render_queue: Res<RenderQueue>,
+    config: Res<GrassConfiguration>,
+    inserted_grass: Query<(Entity, &GrassColor, Option<&GrassLod>)>,
 ) {
-    for (entity, color) in inserted_grass.iter() {
-        let layout = pipeline.color_layout.clone();
-
-        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
-            label: "grass color buffer".into(),
-            contents: bytemuck::bytes_of(&ShaderColorUniform::from(color)),
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+    cache
+        .data
+        .retain(|entity, _| inserted_grass.contains(*entity));
+    for (entity, color, lod) in inserted_grass.iter() {
+        let data = ShaderColorUniform::new(color, lod.copied().or(config.lod));
+        let cached = cache.get_or_create(entity, &[], || {
+            create_uniform_bind_group(
+                &render_device,
+                &pipeline.color_layout,
+                "grass color bind group",
+                bytemuck::bytes_of(&data),
+            )
         });
-        let bind_group_descriptor = BindGroupDescriptor {
-            label: Some("grass color bind group"),
-            layout: &layout,
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: BindingResource::Buffer(BufferBinding {
-                    buffer: &buffer,
-                    offset: 0,
-                    size: NonZeroU64::new(mem::size_of::<ShaderColorUniform>() as u64),
-                }),
-            }],
-        };
-        let bind_group = render_device.create_bind_group(&bind_group_descriptor);
+        cached.write_uniform(&render_queue, bytemuck::bytes_of(&data));
         commands
             .entity(entity)
-            .insert(BindGroupBuffer::<GrassColor>::new(bind_group));
+            .insert(BindGroupBuffer::<GrassColor>::new(
+                cached.bind_group.clone(),
+            ));
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub(crate) fn prepare_height_map_buffer(
     mut commands: Commands,
+    mut cache: ResMut<BindGroupCache<HeightMap>>,
     render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
     pipeline: Res<GrassPipeline>,
     fallback_img: Res<FallbackImage>,
     images: Res<RenderAssets<Image>>,
-    inserted_grass: Query<(Entity, &HeightMap, &Aabb)>,
+    inserted_grass: Query<(
+        Entity,
+        &HeightMap,
+        &Aabb,
+        Option<&TrampleMask>,
+        Option<&GrassCutMask>,
+    )>,
 ) {
-    let layout = pipeline.height_map_layout.clone();
-
-    for (entity, height_map, aabb) in inserted_grass.iter() {
+    cache
+        .data
+        .retain(|entity, _| inserted_grass.contains(*entity));
+    for (entity, height_map, aabb, trample_mask, cut_mask) in inserted_grass.iter() {
         let height_map_texture = if let Some(tex) = images.get(&height_map.height_map) {
             &tex.texture_view
         } else {
             &fallback_img.texture_view
         };
+        // the mask is only read by the shader if the chunk has one
+        let trample_texture = trample_mask
+            .and_then(|trample_mask| images.get(&trample_mask.mask))
+            .map_or(&fallback_img.texture_view, |image| &image.texture_view);
+        let cut_texture = cut_mask
+            .and_then(|cut_mask| images.get(&cut_mask.mask))
+            .map_or(&fallback_img.texture_view, |image| &image.texture_view);
+        let textures = [
+            height_map_texture.id(),
+            trample_texture.id(),
+            cut_texture.id(),
+        ];
+        let data = ShaderAabb {
+            vect: Vec3::from(aabb.half_extents.mul(2.)),
+            height_map_filter: height_map.filter.shader_id(),
+            height_map_border: height_map.border,
+            _padding: [0; 3],
+        };
 
-        let aabb_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
-            label: Some("aabb buffer"),
-            contents: bytemuck::bytes_of(&ShaderAabb::from(Vec3::from(aabb.half_extents.mul(2.)))),
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        let cached = cache.get_or_create(entity, &textures, || {
+            let aabb_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+                label: Some("aabb buffer"),
+                contents: bytemuck::bytes_of(&data),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            });
+
+            let bind_group_descriptor = BindGroupDescriptor {
+                label: Some("grass height map bind group"),
+                layout: &pipeline.height_map_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(height_map_texture),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &aabb_buffer,
+                            offset: 0,
+                            size: None,
+                        }),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(trample_texture),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::TextureView(cut_texture),
+                    },
+                ],
+            };
+            CachedBindGroup {
+                bind_group: render_device.create_bind_group(&bind_group_descriptor),
+                textures: textures.to_vec(),
+                uniform: Some((aabb_buffer, bytemuck::bytes_of(&data).to_vec())),
+            }
         });
+        cached.write_uniform(&render_queue, bytemuck::bytes_of(&data));
+        commands
+            .entity(entity)
+            .insert(BindGroupBuffer::<HeightMap>::new(cached.bind_group.clone()));
+    }
+}
+/// Merges the chunks with the [`BatchedGrass`] component into batches which can be drawn at once
+///
+/// The batches contain all chunks which aren't hidden, whether they are in view or not,
+/// so the blade positions of a batch are only copied again if the chunks of the batch or their blades changed.
+/// Whether a batch is drawn is decided per view when queuing it.
+/// The data of the chunks is written every frame, since it is small and might change
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn prepare_grass_batches(
+    mut commands: Commands,
+    mut cache: ResMut<GrassBatchCache>,
+    pipeline: Res<GrassPipeline>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    fallback_img: Res<FallbackImage>,
+    images: Res<RenderAssets<Image>>,
+    dithered: Res<RenderAssets<DitheredBuffer>>,
+    config: Res<GrassConfiguration>,
+    chunks: Query<
+        (
+            Entity,
+            &Handle<DitheredBuffer>,
+            &BatchedGrassChunk,
+            &GrassColor,
+            &HeightMap,
+            &Aabb,
+            &WarblerHeight,
+            Option<&GrassLod>,
+            Option<&NoBladeRotation>,
+            Option<&UnlitGrass>,
+            Option<&GrassReceiveShadows>,
+            Option<&GrassCastShadows>,
+        ),
+        (
+            With<BatchedGrass>,
+            Without<TrampleMask>,
+            Without<GrassCutMask>,
+        ),
+    >,
+) {
+    let mut groups: HashMap<
+        GrassBatchKey,
+        Vec<(Entity, &GpuDitheredBuffer, ShaderBatchedChunk, &Aabb)>,
+    > = HashMap::default();
+    for (
+        entity,
+        dithered_handle,
+        batched_chunk,
+        color,
+        height_map,
+        aabb,
+        height,
+        lod,
+        no_rotation,
+        unlit,
+        receive_shadows,
+        cast_shadows,
+    ) in chunks.iter()
+    {
+        let Some(blades) = dithered.get(dithered_handle) else {
+            continue;
+        };
+        if blades.instances == 0 {
+            continue;
+        }
+        let (heights, uniform_height) = match height {
+            WarblerHeight::Uniform(height) => (None, *height),
+            WarblerHeight::Texture(texture) => (Some(texture.clone_weak()), 0.),
+        };
+        let key = GrassBatchKey {
+            mesh: batched_chunk.mesh.clone_weak(),
+            height_map: height_map.height_map.clone_weak(),
+            heights,
+            blade_rotation: no_rotation.is_none(),
+            lit: unlit.is_none(),
+            receive_shadows: receive_shadows.is_some(),
+            cast_shadows: cast_shadows.is_some(),
+            lod: lod.is_some() || config.lod.is_some(),
+        };
+        let color = ShaderColorUniform::new(color, lod.copied().or(config.lod));
+        let chunk = ShaderBatchedChunk {
+            model: batched_chunk.transform,
+            inverse_transpose_model: batched_chunk.transform.inverse().transpose(),
+            main_color: color.main_color,
+            bottom_color: color.bottom_color,
+            aabb: (Vec3::from(aabb.half_extents) * 2.).extend(uniform_height),
+            first_instance: 0,
+            height_map_filter: height_map.filter.shader_id(),
+            height_map_border: height_map.border,
+            _padding: 0,
+            lod: color.lod,
+        };
+        groups
+            .entry(key)
+            .or_default()
+            .push((entity, blades, chunk, aabb));
+    }
+
+    let mut batches = HashMap::default();
+    for (key, mut members) in groups {
+        members.sort_by_key(|(entity, ..)| *entity);
+        let first_entity = members[0].0;
+        let mut instance_count = 0;
+        for (_, blades, chunk, _) in members.iter_mut() {
+            chunk.first_instance = instance_count;
+            instance_count += blades.instances as u32;
+        }
+        // the bounds of the batch are used to check its distance to the views
+        let mut min = Vec3::splat(f32::INFINITY);
+        let mut max = Vec3::splat(f32::NEG_INFINITY);
+        for (entity, _, chunk, aabb) in &members {
+            commands.entity(*entity).insert(InGrassBatch(first_entity));
+            let center = chunk.model.transform_point3(aabb.center.into());
+            let radius = chunk
+                .model
+                .transform_vector3(aabb.half_extents.into())
+                .length();
+            min = min.min(center - radius);
+            max = max.max(center + radius);
+        }
+        let center = (min + max) / 2.;
+        let radius = (max - min).length() / 2.;
+        // the batch is only out of range if all of its chunks are
+        let lod_far = key.lod.then(|| {
+            members
+                .iter()
+                .map(|(_, _, chunk, _)| chunk.lod.y)
+                .fold(0., f32::max)
+        });
+        let chunk_data: Vec<ShaderBatchedChunk> =
+            members.iter().map(|(_, _, chunk, _)| *chunk).collect();
+        let batch_chunks: Vec<(Entity, BufferId)> = members
+            .iter()
+            .map(|(entity, blades, ..)| (*entity, blades.buffer.id()))
+            .collect();
+        let height_map = images
+            .get(&key.height_map)
+            .map_or(&fallback_img.texture_view, |image| &image.texture_view);
+        let heights = key
+            .heights
+            .as_ref()
+            .and_then(|heights| images.get(heights))
+            .map_or(&fallback_img.texture_view, |image| &image.texture_view);
+        let textures = (height_map.id(), heights.id());
+
+        let batch = match cache.remove(&first_entity) {
+            Some(mut batch)
+                if batch.key == key
+                    && batch.chunks == batch_chunks
+                    && batch.textures == textures =>
+            {
+                render_queue.write_buffer(
+                    &batch.chunk_buffer,
+                    0,
+                    bytemuck::cast_slice(chunk_data.as_slice()),
+                );
+                batch.transform = chunk_data[0].model;
+                batch.center = center;
+                batch.radius = radius;
+                batch.lod_far = lod_far;
+                batch
+            }
+            _ => {
+                // copies the blades of all chunks into one buffer
+                let positions = render_device.create_buffer(&BufferDescriptor {
+                    label: Some("grass batch positions buffer"),
+                    size: instance_count as u64 * mem::size_of::<Vec2>() as u64,
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("grass batch encoder"),
+                });
+                for (_, blades, chunk, _) in &members {
+                    let offset = chunk.first_instance as u64 * mem::size_of::<Vec2>() as u64;
+                    let size = blades.instances as u64 * mem::size_of::<Vec2>() as u64;
+                    encoder.copy_buffer_to_buffer(&blades.buffer, 0, &positions, offset, size);
+                }
+                render_queue.submit(std::iter::once(encoder.finish()));
 
-        let bind_group_descriptor = BindGroupDescriptor {
-            label: Some("grass height map bind group"),
-            layout: &layout,
+                let chunk_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+                    label: Some("grass batch chunk buffer"),
+                    contents: bytemuck::cast_slice(chunk_data.as_slice()),
+                    usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+                });
+                let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("grass batch bind group"),
+                    layout: &pipeline.batch_layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: chunk_buffer.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::TextureView(height_map),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: BindingResource::TextureView(heights),
+                        },
+                    ],
+                });
+                CachedGrassBatch {
+                    key,
+                    chunks: batch_chunks,
+                    textures,
+                    positions,
+                    instance_count,
+                    chunk_buffer,
+                    bind_group,
+                    transform: chunk_data[0].model,
+                    center,
+                    radius,
+                    lod_far,
+                }
+            }
+        };
+        batches.insert(first_entity, batch);
+    }
+    // batches which weren't used this frame are dropped
+    cache.data = batches;
+}
+
+/// Generates the blade positions of chunks with the [`GpuDithering`] component using a compute shader
+///
+/// The positions are only generated again if the density map, density or the [`Aabb`] changed.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn prepare_gpu_dithering(
+    mut cache: ResMut<GpuDitherCache>,
+    dither_pipeline: Res<DitherPipeline>,
+    pipeline_cache: Res<PipelineCache>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    images: Res<RenderAssets<Image>>,
+    meshes: Res<RenderAssets<Mesh>>,
+    chunks: Query<(Entity, &DensityMap, &Aabb, &Handle<Mesh>), With<GpuDithering>>,
+) {
+    cache.retain(|entity, _| chunks.contains(*entity));
+    let Some(pipeline) = pipeline_cache.get_compute_pipeline(dither_pipeline.pipeline) else {
+        return;
+    };
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("grass dither encoder"),
+    });
+    let mut dispatched = false;
+    for (entity, density_map, aabb, mesh_handle) in chunks.iter() {
+        let Some(image) = images.get(&density_map.density_map) else {
+            continue;
+        };
+        let vertex_count = match meshes.get(mesh_handle).map(|mesh| &mesh.buffer_info) {
+            Some(GpuBufferInfo::Indexed { count, .. }) => *count,
+            Some(GpuBufferInfo::NonIndexed { vertex_count }) => *vertex_count,
+            None => 0,
+        };
+        let key = GpuDitherKey {
+            density_map: image.texture_view.id(),
+            density: density_map.density,
+            field_size: aabb.half_extents.xz() * 2.,
+        };
+        if let Some(chunk) = cache.get_mut(&entity) {
+            if chunk.key == key {
+                // only the mesh might have changed
+                if chunk.vertex_count != vertex_count {
+                    render_queue.write_buffer(
+                        &chunk.indirect_args,
+                        0,
+                        bytemuck::bytes_of(&vertex_count),
+                    );
+                    chunk.vertex_count = vertex_count;
+                }
+                continue;
+            }
+        }
+        let blades_x = (key.density * key.field_size.x).abs() as u32;
+        let blades_z = (key.density * key.field_size.y).abs() as u32;
+        // the maximal amount of blades, if the density map is completely white
+        let capacity = (blades_x as u64 * blades_z as u64).max(1);
+        let positions = render_device.create_buffer(&BufferDescriptor {
+            label: Some("gpu dither buffer"),
+            size: capacity * mem::size_of::<Vec2>() as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let indirect_args = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("gpu dither indirect buffer"),
+            contents: bytemuck::cast_slice(&[vertex_count, 0, 0, 0, 0]),
+            usage: BufferUsages::INDIRECT
+                | BufferUsages::STORAGE
+                | BufferUsages::COPY_DST
+                | BufferUsages::COPY_SRC,
+        });
+        let config_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("gpu dither config buffer"),
+            contents: bytemuck::bytes_of(&ShaderDitherConfiguration::new(
+                &key,
+                image.texture_format,
+            )),
+            usage: BufferUsages::UNIFORM,
+        });
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("grass dither bind group"),
+            layout: &dither_pipeline.layout,
             entries: &[
                 BindGroupEntry {
                     binding: 0,
-                    resource: BindingResource::TextureView(height_map_texture),
+                    resource: BindingResource::TextureView(&image.texture_view),
                 },
                 BindGroupEntry {
                     binding: 1,
-                    resource: BindingResource::Buffer(BufferBinding {
-                        buffer: &aabb_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
+                    resource: config_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: positions.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: indirect_args.as_entire_binding(),
                 },
             ],
-        };
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("grass dither pass"),
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(
+                (blades_x + DITHER_WORKGROUP_SIZE - 1) / DITHER_WORKGROUP_SIZE,
+                (blades_z + DITHER_WORKGROUP_SIZE - 1) / DITHER_WORKGROUP_SIZE,
+                1,
+            );
+        }
+        dispatched = true;
+        cache.insert(
+            entity,
+            CachedGpuDitheredChunk {
+                key,
+                positions,
+                indirect_args,
+                vertex_count,
+            },
+        );
+    }
+    if dispatched {
+        render_queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+/// Has to match the workgroup size defined in the dither shader
+const DITHER_WORKGROUP_SIZE: u32 = 8;
 
-        let bind_group = render_device.create_bind_group(&bind_group_descriptor);
-        commands
-            .entity(entity)
-            .insert(BindGroupBuffer::<HeightMap>::new(bind_group));
+/// Culls the blades of chunks with the [`GpuCulling`] component against each camera using a compute shader
+///
+/// Chunks with a [`GrassLod`] are culled as well, so the blades thinned out by the lod aren't drawn.
+/// The culling runs every frame, but the buffers are only created again if the blades of the chunk changed.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn prepare_gpu_culling(
+    mut cache: ResMut<GpuCullingCache>,
+    cull_pipeline: Res<CullPipeline>,
+    pipeline_cache: Res<PipelineCache>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    config: Res<GrassConfiguration>,
+    wind_sources: Res<GrassWindSources>,
+    interactors: Res<GrassInteractors>,
+    meshes: Res<RenderAssets<Mesh>>,
+    dithered: Res<RenderAssets<DitheredBuffer>>,
+    gpu_dither_cache: Res<GpuDitherCache>,
+    stats: Option<Res<GpuCullingStats>>,
+    views: Query<(Entity, &ExtractedView, &VisibleEntities), With<RenderPhase<Opaque3d>>>,
+    chunks: Query<
+        (
+            Option<&GpuCulling>,
+            Option<&GrassLod>,
+            &Handle<Mesh>,
+            &MeshUniform,
+            &Aabb,
+            &WarblerHeight,
+            Option<&Handle<DitheredBuffer>>,
+        ),
+        Without<BatchedGrass>,
+    >,
+) {
+    if let Some(stats) = stats.as_ref() {
+        read_culling_stats(&mut cache.readbacks, stats);
+    }
+    let Some(pipeline) = pipeline_cache.get_compute_pipeline(cull_pipeline.pipeline) else {
+        cache.data.clear();
+        return;
+    };
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("grass cull encoder"),
+    });
+    let mut culled = HashMap::default();
+    for (view_entity, view, visible_entities) in views.iter() {
+        let view_projection = view
+            .view_projection
+            .unwrap_or_else(|| view.projection * view.transform.compute_matrix().inverse());
+        let frustum = Frustum::from_view_projection(&view_projection);
+        for entity in visible_entities.entities.iter().copied() {
+            let Ok((culling, lod, mesh_handle, mesh_uniform, aabb, height, dither_handle)) =
+                chunks.get(entity)
+            else {
+                continue;
+            };
+            // the lod of the chunk overrides the global one
+            let lod = lod.copied().or(config.lod);
+            let Some(max_distance) = cull_distance(culling, lod) else {
+                continue;
+            };
+            if let (None, Some(lod)) = (culling, lod) {
+                // no blade of the chunk is thinned out in this view
+                let (center, radius) = world_sphere(&mesh_uniform.transform, aabb);
+                if view.transform.translation().distance(center) + radius <= lod.near {
+                    continue;
+                }
+            }
+            let vertex_count = match meshes.get(mesh_handle).map(|mesh| &mesh.buffer_info) {
+                Some(GpuBufferInfo::Indexed { count, .. }) => *count,
+                Some(GpuBufferInfo::NonIndexed { vertex_count }) => *vertex_count,
+                None => continue,
+            };
+            // the blades of the chunk and the buffer containing their count
+            let (source, source_args, capacity) = if let Some(chunk) = gpu_dither_cache.get(&entity)
+            {
+                let capacity = chunk.positions.size() / mem::size_of::<Vec2>() as u64;
+                (&chunk.positions, None, capacity)
+            } else if let Some(blades) = dither_handle.and_then(|handle| dithered.get(handle)) {
+                (
+                    &blades.buffer,
+                    Some(blades.instances as u32),
+                    blades.instances as u64,
+                )
+            } else {
+                continue;
+            };
+            if capacity == 0 {
+                continue;
+            }
+            let chunk = match cache.data.remove(&(view_entity, entity)) {
+                Some(chunk) if chunk.source == source.id() => chunk,
+                _ => {
+                    let source_args = source_args.map(|instances| {
+                        render_device.create_buffer_with_data(&BufferInitDescriptor {
+                            label: Some("grass cull source buffer"),
+                            contents: bytemuck::cast_slice(&[0, instances, 0, 0, 0]),
+                            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+                        })
+                    });
+                    let positions = render_device.create_buffer(&BufferDescriptor {
+                        label: Some("grass cull buffer"),
+                        size: capacity * mem::size_of::<Vec2>() as u64,
+                        usage: BufferUsages::VERTEX | BufferUsages::STORAGE,
+                        mapped_at_creation: false,
+                    });
+                    let indirect_args = render_device.create_buffer(&BufferDescriptor {
+                        label: Some("grass cull indirect buffer"),
+                        size: 5 * mem::size_of::<u32>() as u64,
+                        usage: BufferUsages::INDIRECT
+                            | BufferUsages::STORAGE
+                            | BufferUsages::COPY_DST
+                            | BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    });
+                    let config = render_device.create_buffer(&BufferDescriptor {
+                        label: Some("grass cull config buffer"),
+                        size: mem::size_of::<ShaderCullConfiguration>() as u64,
+                        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    });
+                    let source_args_binding = source_args.as_ref().map_or_else(
+                        || gpu_dither_cache[&entity].indirect_args.as_entire_binding(),
+                        |buffer| buffer.as_entire_binding(),
+                    );
+                    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("grass cull bind group"),
+                        layout: &cull_pipeline.layout,
+                        entries: &[
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: config.as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: source.as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 2,
+                                resource: source_args_binding,
+                            },
+                            BindGroupEntry {
+                                binding: 3,
+                                resource: positions.as_entire_binding(),
+                            },
+                            BindGroupEntry {
+                                binding: 4,
+                                resource: indirect_args.as_entire_binding(),
+                            },
+                        ],
+                    });
+                    CachedCulledChunk {
+                        source: source.id(),
+                        source_args,
+                        positions,
+                        indirect_args,
+                        config,
+                        bind_group,
+                    }
+                }
+            };
+            let blade_height = match height {
+                WarblerHeight::Uniform(height) => *height,
+                // the shader maps the texture to heights between 4/3 and 5/3
+                WarblerHeight::Texture(_) => 5. / 3.,
+            };
+            let cull_config = ShaderCullConfiguration::new(
+                mesh_uniform.transform,
+                &frustum,
+                view.transform.translation(),
+                max_distance,
+                aabb.half_extents.y * 2. + blade_height,
+                blade_height
+                    + config.wind.length() * (1. + config.wind_model.gust_strength.max(0.))
+                    + wind_sources.max_strength
+                    + interactors.max_strength,
+                lod,
+                config.seed,
+            );
+            render_queue.write_buffer(&chunk.config, 0, bytemuck::bytes_of(&cull_config));
+            // the visible blades are counted again each frame
+            render_queue.write_buffer(
+                &chunk.indirect_args,
+                0,
+                bytemuck::cast_slice(&[vertex_count, 0, 0, 0, 0]),
+            );
+            let workgroups =
+                ((capacity + CULL_WORKGROUP_SIZE as u64 - 1) / CULL_WORKGROUP_SIZE as u64) as u32;
+            let max_workgroups = render_device.limits().max_compute_workgroups_per_dimension;
+            {
+                let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: Some("grass cull pass"),
+                });
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &chunk.bind_group, &[]);
+                pass.dispatch_workgroups(
+                    workgroups.min(max_workgroups),
+                    (workgroups + max_workgroups - 1) / max_workgroups,
+                    1,
+                );
+            }
+            culled.insert((view_entity, entity), chunk);
+        }
+    }
+    cache.data = culled;
+    let mut readback = None;
+    if let Some(stats) = stats {
+        if cache.data.is_empty() {
+            stats.set_culled_blades(0);
+        } else if cache.readbacks.len() < MAX_CULLING_READBACKS {
+            readback = Some(copy_culling_stats(
+                &cache,
+                &render_device,
+                &gpu_dither_cache,
+                &mut encoder,
+            ));
+        }
+    }
+    render_queue.submit(std::iter::once(encoder.finish()));
+    if let Some(readback) = readback {
+        let mapped = readback.mapped.clone();
+        // the buffer is mapped during one of the next submissions, so it's read in a later frame
+        render_device.map_buffer(&readback.buffer.slice(..), MapMode::Read, move |result| {
+            if result.is_ok() {
+                mapped.store(true, Ordering::Release);
+            }
+        });
+        cache.readbacks.push(readback);
+    }
+}
+/// Has to match the workgroup size defined in the cull shader
+const CULL_WORKGROUP_SIZE: u32 = 64;
+/// The distance up to which the blades of a chunk are kept by the culling, `None` if the chunk isn't culled
+fn cull_distance(culling: Option<&GpuCulling>, lod: Option<GrassLod>) -> Option<f32> {
+    let max_distance = culling.map(|culling| culling.max_distance.unwrap_or(f32::MAX));
+    match lod {
+        Some(lod) => Some(max_distance.map_or(lod.far, |distance| distance.min(lod.far))),
+        None => max_distance,
     }
 }
+/// The center and radius of a sphere containing the [`Aabb`] of a chunk in world space
+pub(crate) fn world_sphere(transform: &Mat4, aabb: &Aabb) -> (Vec3, f32) {
+    let center = transform.transform_point3(aabb.center.into());
+    let radius = transform
+        .transform_vector3(aabb.half_extents.into())
+        .length();
+    (center, radius)
+}
+/// The maximal amount of culling diagnostics waiting to be read back from the gpu
+const MAX_CULLING_READBACKS: usize = 3;
+
+/// Copies the total and visible blade count of each culled chunk into a buffer which can be read by the cpu
+fn copy_culling_stats(
+    cache: &GpuCullingCache,
+    render_device: &RenderDevice,
+    gpu_dither_cache: &GpuDitherCache,
+    encoder: &mut CommandEncoder,
+) -> CullingReadback {
+    let count_size = mem::size_of::<u32>() as u64;
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("grass cull readback buffer"),
+        size: cache.data.len() as u64 * 2 * count_size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    for (i, ((_, entity), chunk)) in cache.data.iter().enumerate() {
+        let offset = i as u64 * 2 * count_size;
+        // the instance count is the second field of the indirect arguments
+        let source_args = chunk
+            .source_args
+            .as_ref()
+            .unwrap_or_else(|| &gpu_dither_cache[entity].indirect_args);
+        encoder.copy_buffer_to_buffer(source_args, count_size, &buffer, offset, count_size);
+        encoder.copy_buffer_to_buffer(
+            &chunk.indirect_args,
+            count_size,
+            &buffer,
+            offset + count_size,
+            count_size,
+        );
+    }
+    CullingReadback {
+        buffer,
+        mapped: Arc::new(AtomicBool::new(false)),
+    }
+}
+/// Updates the culling diagnostics with the most recent readback buffer that is mapped
+fn read_culling_stats(readbacks: &mut Vec<CullingReadback>, stats: &GpuCullingStats) {
+    let Some(last_mapped) = readbacks
+        .iter()
+        .rposition(|readback| readback.mapped.load(Ordering::Acquire))
+    else {
+        return;
+    };
+    let readback = &readbacks[last_mapped];
+    {
+        let data = readback.buffer.slice(..).get_mapped_range();
+        let counts: &[u32] = bytemuck::cast_slice(&data);
+        let culled: u64 = counts
+            .chunks_exact(2)
+            .map(|counts| counts[0].saturating_sub(counts[1]) as u64)
+            .sum();
+        stats.set_culled_blades(culled);
+    }
+    // older readbacks are outdated
+    for readback in readbacks.drain(..=last_mapped) {
+        if readback.mapped.load(Ordering::Acquire) {
+            readback.buffer.unmap();
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn prepare_uniform_buffers(
     pipeline: Res<GrassPipeline>,
     region_config: Res<GrassConfiguration>,
+    wind_time: Res<GrassWindTime>,
+    wind_sources: Res<GrassWindSources>,
+    interactors: Res<GrassInteractors>,
     noise_config: Res<GrassNoiseTexture>,
     fallback_img: Res<FallbackImage>,
     render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
     mut uniform_buffer: ResMut<UniformBuffer>,
     images: Res<RenderAssets<Image>>,
     mut last_texture_id: Local<Option<TextureViewId>>,
+    mut last_buffers: Local<Option<[Buffer; 3]>>,
 ) {
-    let texture = &images
-        .get(&noise_config.0)
-        .unwrap_or(&fallback_img)
-        .texture_view;
-    if !region_config.is_changed() && Some(texture.id()) == *last_texture_id {
-        return;
+    let noise = images.get(&noise_config.0).unwrap_or(&fallback_img);
+    let texture = &noise.texture_view;
+    let shader_config =
+        ShaderRegionConfiguration::new(region_config.as_ref(), wind_time.elapsed, noise.size);
+    let sources: Vec<ShaderWindSource> = wind_sources
+        .sources
+        .iter()
+        .map(ShaderWindSource::from)
+        .collect();
+    let sources = uniform_array_data(&sources, MAX_WIND_SOURCES);
+    let interactors: Vec<ShaderInteractor> = interactors
+        .interactors
+        .iter()
+        .map(ShaderInteractor::from)
+        .collect();
+    let interactors = uniform_array_data(&interactors, MAX_GRASS_INTERACTORS);
+    if let Some([config_buffer, wind_buffer, interactor_buffer]) = last_buffers.as_ref() {
+        if Some(texture.id()) == *last_texture_id {
+            // the wind and interactors change every frame, so the buffers are updated instead of recreated
+            render_queue.write_buffer(config_buffer, 0, bytemuck::bytes_of(&shader_config));
+            render_queue.write_buffer(wind_buffer, 0, &sources);
+            render_queue.write_buffer(interactor_buffer, 0, &interactors);
+            return;
+        }
     }
     *last_texture_id = Some(texture.id());
 
-    let shader_config = ShaderRegionConfiguration::from(region_config.as_ref());
     let config_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
         label: Some("region config buffer"),
         contents: bytemuck::bytes_of(&shader_config),
         usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
     });
+    let wind_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("wind sources buffer"),
+        contents: &sources,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    });
+    let interactor_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("grass interactors buffer"),
+        contents: &interactors,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    });
 
     let layout = pipeline.region_layout.clone();
     let bind_group_descriptor = BindGroupDescriptor {
@@ -299,57 +1164,266 @@ pub(crate) fn prepare_uniform_buffers(
                 binding: 1,
                 resource: BindingResource::TextureView(texture),
             },
+            BindGroupEntry {
+                binding: 2,
+                resource: wind_buffer.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: interactor_buffer.as_entire_binding(),
+            },
         ],
     };
     let bind_group = render_device.create_bind_group(&bind_group_descriptor);
     uniform_buffer.set(bind_group);
+    *last_buffers = Some([config_buffer, wind_buffer, interactor_buffer]);
+}
+
+/// Lays out the items like a struct with a count followed by an array of `max` items in a shader.
+///
+/// Uniform arrays need a fixed size, so the remaining space is filled with zeros
+fn uniform_array_data<T: Pod>(items: &[T], max: usize) -> Vec<u8> {
+    let items = &items[..items.len().min(max)];
+    let count = [items.len() as u32, 0, 0, 0];
+    let mut data = bytemuck::cast_slice(&count).to_vec();
+    data.extend_from_slice(bytemuck::cast_slice(items));
+    data.resize(mem::size_of_val(&count) + max * mem::size_of::<T>(), 0);
+    data
 }
 
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
 struct ShaderRegionConfiguration {
     wind: Vec2,
-    /// Wasm requires shader uniforms to be aligned to 16 bytes
-    _wasm_padding: Vec2,
+    /// How far the wind moved the noise texture, wrapped to its size
+    wind_offset: Vec2,
+    max_blade_rotation: f32,
+    shadow_blade_fraction: f32,
+    seed: u32,
+    wind_noise_scale: f32,
+    gust_strength: f32,
+    gust_frequency: f32,
+    /// The phases of the two waves forming the gusts, wrapped to a single period
+    gust_phases: Vec2,
 }
 
-impl From<&GrassConfiguration> for ShaderRegionConfiguration {
-    fn from(config: &GrassConfiguration) -> Self {
+/// How much faster the second wave of the gusts is than the first one
+const SECOND_GUST_WAVE: f64 = 2.31;
+
+impl ShaderRegionConfiguration {
+    /// The time only reaches the shader wrapped to the periods of the animation,
+    /// since a large time would lose its precision as `f32`
+    fn new(config: &GrassConfiguration, wind_time: f64, noise_size: Vec2) -> Self {
+        let scroll = config.wind.as_dvec2() * wind_time * config.wind_model.speed as f64;
+        let wind_offset = DVec2::new(
+            scroll.x.rem_euclid(noise_size.x.max(1.) as f64),
+            scroll.y.rem_euclid(noise_size.y.max(1.) as f64),
+        );
+        let gust_time = wind_time * config.wind_model.gust_frequency as f64;
         Self {
             wind: config.wind,
-            _wasm_padding: Vec2::ZERO,
+            wind_offset: wind_offset.as_vec2(),
+            max_blade_rotation: config.max_blade_rotation,
+            shadow_blade_fraction: config.shadow_blade_fraction,
+            seed: config.seed,
+            wind_noise_scale: config.wind_model.noise_scale,
+            gust_strength: config.wind_model.gust_strength,
+            gust_frequency: config.wind_model.gust_frequency,
+            gust_phases: DVec2::new(gust_time.fract(), (gust_time * SECOND_GUST_WAVE).fract())
+                .as_vec2(),
         }
     }
 }
 
+/// A [`WindSource`] in the layout of the grass shader
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
-struct ShaderAabb {
-    vect: Vec3,
-    /// Wasm requires shader uniforms to be aligned to 16 bytes
-    _wasm_padding: f32,
+struct ShaderWindSource {
+    /// The center of the source and its kind
+    center: Vec4,
+    extents: Vec4,
+    wind: Vec4,
+}
+impl From<&WindSource> for ShaderWindSource {
+    fn from(source: &WindSource) -> Self {
+        Self {
+            center: source.center.extend(source.kind as u32 as f32),
+            extents: source.extents.extend(0.),
+            wind: source.wind.extend(0.).extend(0.),
+        }
+    }
+}
+
+/// An [`ActiveInteractor`] in the layout of the grass shader
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct ShaderInteractor {
+    /// The position of the interactor and its radius
+    position: Vec4,
+    strength: f32,
+    _padding: [f32; 3],
+}
+impl From<&ActiveInteractor> for ShaderInteractor {
+    fn from(interactor: &ActiveInteractor) -> Self {
+        Self {
+            position: interactor.position.extend(interactor.radius),
+            strength: interactor.strength,
+            _padding: [0.; 3],
+        }
+    }
+}
+
+/// The data of a single chunk in a batch
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct ShaderBatchedChunk {
+    model: Mat4,
+    inverse_transpose_model: Mat4,
+    main_color: Vec4,
+    bottom_color: Vec4,
+    /// The size of the aabb and the uniform height of the blades
+    aabb: Vec4,
+    first_instance: u32,
+    height_map_filter: u32,
+    height_map_border: u32,
+    _padding: u32,
+    /// The near and far distance of the [`GrassLod`] of the chunk
+    lod: Vec4,
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct ShaderBladeAttributes {
+    height: f32,
+    width: f32,
+    rotation: f32,
+    _padding: f32,
+    color: [f32; 4],
+}
+impl From<&BladeAttributes> for ShaderBladeAttributes {
+    fn from(blade: &BladeAttributes) -> Self {
+        Self {
+            height: blade.height,
+            width: blade.width,
+            rotation: blade.rotation,
+            _padding: 0.,
+            color: blade.color.as_linear_rgba_f32(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct ShaderCullConfiguration {
+    model: Mat4,
+    planes: [Vec4; 5],
+    view: Vec4,
+    bounds: Vec4,
+    /// The near and far distance of the [`GrassLod`], zero if the chunk has none
+    lod: Vec4,
+    seed: u32,
+    _padding: [u32; 3],
 }
+impl ShaderCullConfiguration {
+    /// Bounds every blade by a sphere reaching from the bottom of the [`Aabb`] to the tip of a blade on its top
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        model: Mat4,
+        frustum: &Frustum,
+        view_position: Vec3,
+        max_distance: f32,
+        height: f32,
+        blade_size: f32,
+        lod: Option<GrassLod>,
+        seed: u32,
+    ) -> Self {
+        let mut planes = [Vec4::ZERO; 5];
+        for (plane, frustum_plane) in planes.iter_mut().zip(frustum.planes.iter()) {
+            *plane = frustum_plane.normal_d();
+        }
+        let max_scale = model
+            .x_axis
+            .truncate()
+            .length()
+            .max(model.y_axis.truncate().length())
+            .max(model.z_axis.truncate().length());
+        let radius = Vec2::new(height / 2., blade_size).length() * max_scale;
+        ShaderCullConfiguration {
+            model,
+            planes,
+            view: view_position.extend(max_distance),
+            bounds: Vec4::new(height / 2., radius, 0., 0.),
+            lod: lod.map_or(Vec4::ZERO, |lod| Vec4::new(lod.near, lod.far, 0., 0.)),
+            seed,
+            _padding: [0; 3],
+        }
+    }
+}
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct ShaderDitherConfiguration {
+    field_size: Vec2,
+    density: f32,
+    flags: u32,
+}
+impl ShaderDitherConfiguration {
+    const FLAG_SRGB: u32 = 1;
+    const FLAG_SINGLE_CHANNEL: u32 = 2;
 
-impl From<Vec3> for ShaderAabb {
-    fn from(vect: Vec3) -> Self {
+    fn new(key: &GpuDitherKey, format: TextureFormat) -> Self {
+        let info = format.describe();
+        let mut flags = 0;
+        if info.srgb {
+            flags |= Self::FLAG_SRGB;
+        }
+        if info.components == 1 {
+            flags |= Self::FLAG_SINGLE_CHANNEL;
+        }
         Self {
-            vect,
-            _wasm_padding: 0.,
+            field_size: key.field_size,
+            density: key.density,
+            flags,
         }
     }
 }
 
+/// The area of an explicit chunk the [`TrampleMask`] and [`GrassCutMask`] are stretched over
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct ShaderMaskBounds {
+    min: Vec2,
+    size: Vec2,
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct ShaderAabb {
+    vect: Vec3,
+    /// The [`HeightMapFilter`](crate::height_map::HeightMapFilter) of the chunk
+    height_map_filter: u32,
+    /// The [`HeightMap::border`](crate::height_map::HeightMap::border) of the chunk
+    height_map_border: u32,
+    /// Pads the uniform to a multiple of 16 bytes as required by wasm
+    _padding: [u32; 3],
+}
+
+/// The color of a chunk.
+///
+/// Since it is bound for every chunk, it also contains the distances of the [`GrassLod`] used by the chunk
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
 struct ShaderColorUniform {
     main_color: Vec4,
     bottom_color: Vec4,
+    /// The near and far distance of the [`GrassLod`], zero if the chunk has none
+    lod: Vec4,
 }
-impl From<&GrassColor> for ShaderColorUniform {
-    fn from(config: &GrassColor) -> Self {
+impl ShaderColorUniform {
+    fn new(color: &GrassColor, lod: Option<GrassLod>) -> Self {
         Self {
-            main_color: config.main_color.into(),
-            bottom_color: config.bottom_color.into(),
+            main_color: color.main_color.into(),
+            bottom_color: color.bottom_color.into(),
+            lod: lod.map_or(Vec4::ZERO, |lod| Vec4::new(lod.near, lod.far, 0., 0.)),
         }
     }
 }
@@ -431,3 +1505,68 @@ fn prepare_texture_from_data<T: Default + Clone + bytemuck::Pod>(
         })
         .into()
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use super::{cull_distance, ShaderCullConfiguration, ShaderRegionConfiguration};
+    use crate::{bundle::GpuCulling, GrassConfiguration, GrassLod, WindModel};
+
+    #[test]
+    fn wind_time_is_wrapped() {
+        let config = GrassConfiguration {
+            wind: Vec2::new(2., -1.),
+            wind_model: WindModel {
+                speed: 3.,
+                gust_frequency: 0.5,
+                ..default()
+            },
+            ..default()
+        };
+        let noise_size = Vec2::new(64., 32.);
+
+        let shader = ShaderRegionConfiguration::new(&config, 10.5, noise_size);
+        // 2 * 10.5 * 3 = 63 and -1 * 10.5 * 3 = -31.5 wrapped to 0.5
+        assert_eq!(shader.wind_offset, Vec2::new(63., 0.5));
+        assert!((shader.gust_phases.x - 0.25).abs() < 1e-6);
+        assert!((shader.gust_phases.y - (5.25 * 2.31f64).fract() as f32).abs() < 1e-6);
+
+        // after a long time, the uploaded values stay as precise as at the start
+        let days = 60. * 60. * 24. * 64.;
+        let later = ShaderRegionConfiguration::new(&config, days + 10.5, noise_size);
+        assert!((later.wind_offset - shader.wind_offset).length() < 1e-3);
+        assert!((later.gust_phases.x - shader.gust_phases.x).abs() < 1e-6);
+        for value in [later.wind_offset.x, later.wind_offset.y] {
+            assert!((0. ..64.).contains(&value));
+        }
+        assert!((0. ..1.).contains(&later.gust_phases.y));
+    }
+    #[test]
+    fn lod_chunks_are_culled() {
+        let lod = GrassLod {
+            near: 10.,
+            far: 40.,
+        };
+        let culling = GpuCulling {
+            max_distance: Some(20.),
+        };
+        assert_eq!(cull_distance(None, None), None);
+        assert_eq!(
+            cull_distance(Some(&GpuCulling::default()), None),
+            Some(f32::MAX)
+        );
+        assert_eq!(cull_distance(Some(&culling), None), Some(20.));
+        assert_eq!(cull_distance(None, Some(lod)), Some(40.));
+        assert_eq!(
+            cull_distance(Some(&GpuCulling::default()), Some(lod)),
+            Some(40.)
+        );
+        assert_eq!(cull_distance(Some(&culling), Some(lod)), Some(20.));
+    }
+    #[test]
+    fn cull_configuration_matches_shader_layout() {
+        // the size of `CullConfiguration` in the cull shader, rounded up to its alignment of 16 bytes
+        assert_eq!(std::mem::size_of::<ShaderCullConfiguration>(), 208);
+    }
+}
@@ -1,20 +1,33 @@
 use bevy::core_pipeline::core_3d::Opaque3d;
-use bevy::pbr::{MeshPipelineKey, MeshUniform};
+use bevy::pbr::{
+    CascadesVisibleEntities, CubemapVisibleEntities, ExtractedDirectionalLight,
+    ExtractedPointLight, LightEntity, MeshPipelineKey, MeshUniform, Shadow, ViewLightEntities,
+};
 use bevy::prelude::*;
+use bevy::render::primitives::Aabb;
 use bevy::render::render_asset::RenderAssets;
 use bevy::render::render_phase::{DrawFunctions, RenderPhase};
-use bevy::render::render_resource::{PipelineCache, SpecializedMeshPipelines};
-use bevy::render::view::ExtractedView;
+use bevy::render::render_resource::{
+    BindGroupDescriptor, BindGroupEntry, PipelineCache, SpecializedMeshPipelines,
+};
+use bevy::render::renderer::RenderDevice;
+use bevy::render::view::{ExtractedView, ViewUniforms, VisibleEntities};
+use bevy::utils::HashSet;
 
+use crate::cutting::GrassCutMask;
+use crate::density_map::GpuDithering;
 use crate::dithering::DitheredBuffer;
-use crate::prelude::Grass;
+use crate::prelude::{GrassCastShadows, GrassReceiveShadows, NoBladeRotation, UnlitGrass};
+use crate::trample::TrampleMask;
+use crate::{GrassConfiguration, GrassLod};
 
-use super::cache::ExplicitGrassCache;
+use super::cache::{ExplicitGrassCache, GrassBatchCache, ShadowViewBindGroup};
+use super::extract::ExplicitGrassChunk;
 use super::grass_pipeline::{GrassPipeline, GrassRenderKey};
-use super::prepare::UniformHeightFlag;
-use super::GrassDrawCall;
+use super::prepare::{world_sphere, InGrassBatch, UniformHeightFlag};
+use super::{GrassBatchDrawCall, GrassBatchShadowDrawCall, GrassDrawCall, GrassShadowDrawCall};
 
-#[allow(clippy::too_many_arguments)]
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub(crate) fn queue_grass_buffers(
     opaque_3d_draw_functions: Res<DrawFunctions<Opaque3d>>,
     grass_pipeline: Res<GrassPipeline>,
@@ -29,28 +42,79 @@ pub(crate) fn queue_grass_buffers(
             &MeshUniform,
             &Handle<Mesh>,
             Option<&UniformHeightFlag>,
+            Option<&NoBladeRotation>,
+            Option<&UnlitGrass>,
+            Option<&GrassReceiveShadows>,
+            Option<&TrampleMask>,
+            Option<&GrassCutMask>,
+            Option<&GrassLod>,
+            &Aabb,
+        ),
+        (
+            Or<(
+                With<ExplicitGrassChunk>,
+                With<Handle<DitheredBuffer>>,
+                With<GpuDithering>,
+            )>,
+            Without<InGrassBatch>,
         ),
-        Or<(With<Grass>, With<Handle<DitheredBuffer>>)>,
     >,
-    mut views: Query<(&ExtractedView, &mut RenderPhase<Opaque3d>)>,
+    mut views: Query<(&ExtractedView, &VisibleEntities, &mut RenderPhase<Opaque3d>)>,
+    config: Res<GrassConfiguration>,
+    batches: Option<Res<GrassBatchCache>>,
+    batched_chunks: Query<&InGrassBatch>,
 ) {
     let draw_custom = opaque_3d_draw_functions
         .read()
         .get_id::<GrassDrawCall>()
         .unwrap();
+    let draw_batch = opaque_3d_draw_functions
+        .read()
+        .get_id::<GrassBatchDrawCall>()
+        .unwrap();
 
     let msaa_key = MeshPipelineKey::from_msaa_samples(msaa.samples());
 
-    for (view, mut opaque_phase) in &mut views {
+    for (view, visible_entities, mut opaque_phase) in &mut views {
         let view_key = msaa_key | MeshPipelineKey::from_hdr(view.hdr);
         let rangefinder = view.rangefinder3d();
-        for (entity, mesh_uniform, mesh_handle, has_uniform_height) in material_meshes.iter() {
+        for (
+            entity,
+            mesh_uniform,
+            mesh_handle,
+            has_uniform_height,
+            no_rotation,
+            unlit,
+            receive_shadows,
+            trample_mask,
+            cut_mask,
+            lod,
+            aabb,
+        ) in material_meshes.iter()
+        {
+            // the lod of the chunk overrides the global one
+            let lod = lod.copied().or(config.lod);
+            if let Some(lod) = lod {
+                let (center, radius) = world_sphere(&mesh_uniform.transform, aabb);
+                if is_out_of_lod_range(lod.far, view, center, radius) {
+                    continue;
+                }
+            }
             if let Some(mesh) = meshes.get(mesh_handle) {
                 let mesh_key =
                     view_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
                 let mut grass_key = GrassRenderKey::from(mesh_key);
                 grass_key.is_explicit = grass_cacher.contains_key(&entity);
+                grass_key.instance_data = grass_cacher
+                    .get(&entity)
+                    .is_some_and(|chunk| chunk.explicit_instance_buffer.is_some());
                 grass_key.uniform_height = has_uniform_height.is_some();
+                grass_key.blade_rotation = no_rotation.is_none();
+                grass_key.lit = unlit.is_none();
+                grass_key.receive_shadows = receive_shadows.is_some();
+                grass_key.trample_mask = trample_mask.is_some();
+                grass_key.cut_mask = cut_mask.is_some();
+                grass_key.lod = lod.is_some();
                 let pipeline = pipelines
                     .specialize(&pipeline_cache, &grass_pipeline, grass_key, &mesh.layout)
                     .unwrap();
@@ -62,5 +126,221 @@ pub(crate) fn queue_grass_buffers(
                 });
             }
         }
+        let Some(batches) = batches.as_ref() else {
+            continue;
+        };
+        for entity in visible_batches(visible_entities, &batched_chunks) {
+            let Some(batch) = batches.get(&entity) else {
+                continue;
+            };
+            if let Some(far) = batch.lod_far {
+                if is_out_of_lod_range(far, view, batch.center, batch.radius) {
+                    continue;
+                }
+            }
+            let Some(mesh) = meshes.get(&batch.key.mesh) else {
+                continue;
+            };
+            let mesh_key =
+                view_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
+            let mut grass_key = GrassRenderKey::from(mesh_key);
+            grass_key.batched = true;
+            grass_key.uniform_height = batch.key.heights.is_none();
+            grass_key.blade_rotation = batch.key.blade_rotation;
+            grass_key.lit = batch.key.lit;
+            grass_key.receive_shadows = batch.key.receive_shadows;
+            grass_key.lod = batch.key.lod;
+            let pipeline = pipelines
+                .specialize(&pipeline_cache, &grass_pipeline, grass_key, &mesh.layout)
+                .unwrap();
+            opaque_phase.add(Opaque3d {
+                entity,
+                pipeline,
+                draw_function: draw_batch,
+                distance: rangefinder.distance(&batch.transform),
+            });
+        }
+    }
+}
+
+/// Checks whether the whole sphere is further away from the view than the far distance of a [`GrassLod`]
+fn is_out_of_lod_range(far: f32, view: &ExtractedView, center: Vec3, radius: f32) -> bool {
+    view.transform.translation().distance(center) - radius > far
+}
+/// The batches with at least one chunk in the visible entities of a view or light
+fn visible_batches(
+    visible_entities: &VisibleEntities,
+    batched_chunks: &Query<&InGrassBatch>,
+) -> HashSet<Entity> {
+    visible_entities
+        .iter()
+        .filter_map(|entity| batched_chunks.get(*entity).ok())
+        .map(|batch| batch.0)
+        .collect()
+}
+
+/// Queues the grass chunks with the [`GrassCastShadows`] component into the shadow phases of all lights
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub(crate) fn queue_grass_shadows(
+    shadow_draw_functions: Res<DrawFunctions<Shadow>>,
+    grass_pipeline: Res<GrassPipeline>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<GrassPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    grass_cacher: Res<ExplicitGrassCache>,
+    meshes: Res<RenderAssets<Mesh>>,
+    casting_grass: Query<
+        (
+            &Handle<Mesh>,
+            Option<&UniformHeightFlag>,
+            Option<&NoBladeRotation>,
+            Option<&TrampleMask>,
+            Option<&GrassCutMask>,
+        ),
+        (
+            With<GrassCastShadows>,
+            Or<(
+                With<ExplicitGrassChunk>,
+                With<Handle<DitheredBuffer>>,
+                With<GpuDithering>,
+            )>,
+            Without<InGrassBatch>,
+        ),
+    >,
+    view_lights: Query<(Entity, &ViewLightEntities)>,
+    mut view_light_shadow_phases: Query<(&LightEntity, &mut RenderPhase<Shadow>)>,
+    point_light_entities: Query<&CubemapVisibleEntities, With<ExtractedPointLight>>,
+    directional_light_entities: Query<&CascadesVisibleEntities, With<ExtractedDirectionalLight>>,
+    spot_light_entities: Query<&VisibleEntities, With<ExtractedPointLight>>,
+    batches: Option<Res<GrassBatchCache>>,
+    batched_chunks: Query<&InGrassBatch>,
+) {
+    let draw_shadow = shadow_draw_functions
+        .read()
+        .get_id::<GrassShadowDrawCall>()
+        .unwrap();
+    let draw_batch_shadow = shadow_draw_functions
+        .read()
+        .get_id::<GrassBatchShadowDrawCall>()
+        .unwrap();
+
+    for (view_entity, view_lights) in &view_lights {
+        for view_light_entity in view_lights.lights.iter().copied() {
+            let Ok((light_entity, mut shadow_phase)) =
+                view_light_shadow_phases.get_mut(view_light_entity)
+            else {
+                continue;
+            };
+            let is_directional_light = matches!(light_entity, LightEntity::Directional { .. });
+            // Lights with shadows disabled have no visible entities
+            let visible_entities = match light_entity {
+                LightEntity::Directional {
+                    light_entity,
+                    cascade_index,
+                } => directional_light_entities
+                    .get(*light_entity)
+                    .ok()
+                    .and_then(|cascades| cascades.entities.get(&view_entity))
+                    .and_then(|cascades| cascades.get(*cascade_index)),
+                LightEntity::Point {
+                    light_entity,
+                    face_index,
+                } => point_light_entities
+                    .get(*light_entity)
+                    .ok()
+                    .map(|cubemap| cubemap.get(*face_index)),
+                LightEntity::Spot { light_entity } => spot_light_entities.get(*light_entity).ok(),
+            };
+            let Some(visible_entities) = visible_entities else {
+                continue;
+            };
+            // a batch is drawn into the shadow map if one of its chunks casts a shadow into it
+            for entity in visible_batches(visible_entities, &batched_chunks) {
+                let Some(batch) = batches.as_ref().and_then(|batches| batches.get(&entity)) else {
+                    continue;
+                };
+                if !batch.key.cast_shadows {
+                    continue;
+                }
+                let Some(mesh) = meshes.get(&batch.key.mesh) else {
+                    continue;
+                };
+                let mut mesh_key =
+                    MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
+                if is_directional_light {
+                    mesh_key |= MeshPipelineKey::DEPTH_CLAMP_ORTHO;
+                }
+                let mut grass_key = GrassRenderKey::from(mesh_key);
+                grass_key.batched = true;
+                grass_key.uniform_height = batch.key.heights.is_none();
+                grass_key.blade_rotation = batch.key.blade_rotation;
+                grass_key.lit = false;
+                grass_key.shadow_pass = true;
+                let pipeline = pipelines
+                    .specialize(&pipeline_cache, &grass_pipeline, grass_key, &mesh.layout)
+                    .unwrap();
+                shadow_phase.add(Shadow {
+                    draw_function: draw_batch_shadow,
+                    pipeline,
+                    entity,
+                    distance: 0.,
+                });
+            }
+            for entity in visible_entities.iter().copied() {
+                let Ok((mesh_handle, has_uniform_height, no_rotation, trample_mask, cut_mask)) =
+                    casting_grass.get(entity)
+                else {
+                    continue;
+                };
+                let Some(mesh) = meshes.get(mesh_handle) else {
+                    continue;
+                };
+                let mut mesh_key =
+                    MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
+                if is_directional_light {
+                    mesh_key |= MeshPipelineKey::DEPTH_CLAMP_ORTHO;
+                }
+                let mut grass_key = GrassRenderKey::from(mesh_key);
+                grass_key.is_explicit = grass_cacher.contains_key(&entity);
+                grass_key.instance_data = grass_cacher
+                    .get(&entity)
+                    .is_some_and(|chunk| chunk.explicit_instance_buffer.is_some());
+                grass_key.uniform_height = has_uniform_height.is_some();
+                grass_key.blade_rotation = no_rotation.is_none();
+                grass_key.trample_mask = trample_mask.is_some();
+                grass_key.cut_mask = cut_mask.is_some();
+                grass_key.lit = false;
+                grass_key.shadow_pass = true;
+                let pipeline = pipelines
+                    .specialize(&pipeline_cache, &grass_pipeline, grass_key, &mesh.layout)
+                    .unwrap();
+                shadow_phase.add(Shadow {
+                    draw_function: draw_shadow,
+                    pipeline,
+                    entity,
+                    distance: 0.,
+                });
+            }
+        }
     }
 }
+
+/// Creates the bind group containing the view used in the shadow pass
+pub(crate) fn queue_shadow_view_bind_group(
+    render_device: Res<RenderDevice>,
+    grass_pipeline: Res<GrassPipeline>,
+    view_uniforms: Res<ViewUniforms>,
+    mut shadow_view_bind_group: ResMut<ShadowViewBindGroup>,
+) {
+    let Some(view_binding) = view_uniforms.uniforms.binding() else {
+        return;
+    };
+    let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+        label: Some("grass shadow view bind group"),
+        layout: &grass_pipeline.shadow_view_layout,
+        entries: &[BindGroupEntry {
+            binding: 0,
+            resource: view_binding,
+        }],
+    });
+    shadow_view_bind_group.0 = Some(bind_group);
+}
@@ -0,0 +1,403 @@
+//! Lets characters and objects push the grass aside
+//!
+//! Blades around entities with the [`GrassInteractor`] component bend away from them.
+//! Chunks with a [`TrampleMask`] also remember where the grass was trampled,
+//! so trails stay visible behind players and vehicles and slowly recover.
+//!
+//! # Example
+//! ```rust
+//! use bevy::prelude::*;
+//! use warbler_grass::prelude::*;
+//! use warbler_grass::trample::{GrassInteractor, TrampleMask};
+//!
+//! fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+//!     // the player bends the grass around it
+//!     commands.spawn((
+//!         GrassInteractor {
+//!             radius: 1.5,
+//!             strength: 1.,
+//!         },
+//!         SpatialBundle::default(),
+//!     ));
+//!     // the chunk remembers where it was trampled
+//!     commands.spawn((
+//!         WarblersBundle::default(),
+//!         TrampleMask::new(&mut images, UVec2::splat(128)),
+//!     ));
+//! }
+//! ```
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    render::{
+        extract_component::ExtractComponent,
+        extract_resource::ExtractResource,
+        primitives::Aabb,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+    utils::HashMap,
+};
+
+use crate::bundle::Grass;
+
+/// The maximal amount of [`GrassInteractor`]s bending the grass at once.
+///
+/// Further interactors still trample the [`TrampleMask`]s but don't bend the grass directly
+pub const MAX_GRASS_INTERACTORS: usize = 32;
+
+/// Bends the grass around the [`GlobalTransform`] of its entity, like a player walking through it.
+///
+/// Hidden interactors don't affect the grass.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Reflect, FromReflect)]
+pub struct GrassInteractor {
+    /// The radius of the sphere in which the blades are bent
+    pub radius: f32,
+    /// How far the tips of the blades closest to the interactor are pushed away
+    pub strength: f32,
+}
+impl Default for GrassInteractor {
+    fn default() -> Self {
+        GrassInteractor {
+            radius: 1.,
+            strength: 1.,
+        }
+    }
+}
+
+/// Remembers where the grass of a chunk was trampled by [`GrassInteractor`]s.
+///
+/// The trampled blades lean over and recover within the `recovery_time`.
+/// The mask is stored in an `R32Float` texture stretched over the chunk,
+/// where `1.` means fully trampled grass.
+///
+/// Chunks with a [`TrampleMask`] are never merged into batches, see [`BatchedGrass`](crate::bundle::BatchedGrass).
+#[derive(Component, Clone, Debug, Reflect)]
+pub struct TrampleMask {
+    pub mask: Handle<Image>,
+    /// The time in seconds fully trampled grass needs to stand up again
+    pub recovery_time: f32,
+}
+impl TrampleMask {
+    /// Creates an empty trample mask with the given resolution
+    pub fn new(images: &mut Assets<Image>, resolution: UVec2) -> Self {
+        let image = Image::new_fill(
+            Extent3d {
+                width: resolution.x.max(1),
+                height: resolution.y.max(1),
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &0f32.to_le_bytes(),
+            TextureFormat::R32Float,
+        );
+        TrampleMask {
+            mask: images.add(image),
+            recovery_time: 10.,
+        }
+    }
+}
+impl ExtractComponent for TrampleMask {
+    type Query = &'static Self;
+    type Filter = ();
+    type Out = Self;
+
+    fn extract_component(item: bevy::ecs::query::QueryItem<'_, Self::Query>) -> Option<Self::Out> {
+        Some(TrampleMask {
+            mask: item.mask.clone_weak(),
+            recovery_time: item.recovery_time,
+        })
+    }
+}
+
+/// A [`GrassInteractor`] in world space, as used by the grass shader
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ActiveInteractor {
+    pub position: Vec3,
+    pub radius: f32,
+    pub strength: f32,
+}
+
+/// All visible [`GrassInteractor`]s of the current frame, which are extracted to the render world
+#[derive(Resource, Clone, Debug, Default, ExtractResource)]
+pub(crate) struct GrassInteractors {
+    pub interactors: Vec<ActiveInteractor>,
+    /// The maximal distance any of the interactors can push a blade, used to cull blades
+    pub max_strength: f32,
+}
+
+/// Collects the visible [`GrassInteractor`]s into the [`GrassInteractors`]
+pub(crate) fn gather_grass_interactors(
+    interactors: Query<(
+        &GrassInteractor,
+        &GlobalTransform,
+        Option<&ComputedVisibility>,
+    )>,
+    mut gathered: ResMut<GrassInteractors>,
+) {
+    gathered.interactors.clear();
+    gathered.interactors.extend(
+        visible_interactors(&interactors)
+            .into_iter()
+            .take(MAX_GRASS_INTERACTORS)
+            .map(|(interactor, position)| ActiveInteractor {
+                position,
+                radius: interactor.radius,
+                strength: interactor.strength,
+            }),
+    );
+    gathered.max_strength = gathered
+        .interactors
+        .iter()
+        .map(|interactor| interactor.strength.abs())
+        .fold(0., f32::max);
+}
+
+/// How much trampled grass recovers before its [`TrampleMask`] is updated, as fraction of fully trampled grass.
+///
+/// Updating the mask uploads the whole texture again, so it isn't done every frame
+pub const RECOVERY_STEP: f32 = 0.01;
+
+/// Stamps the [`GrassInteractor`]s into the [`TrampleMask`]s and lets the trampled grass recover
+///
+/// Only the texels below the interactors are stamped and the mask is only updated if they change
+#[allow(clippy::type_complexity)]
+pub(crate) fn update_trample_masks(
+    time: Res<Time>,
+    interactors: Query<(
+        &GrassInteractor,
+        &GlobalTransform,
+        Option<&ComputedVisibility>,
+    )>,
+    chunks: Query<(
+        Entity,
+        &TrampleMask,
+        &Aabb,
+        &GlobalTransform,
+        Option<&Grass>,
+    )>,
+    changed: Query<Entity, Changed<TrampleMask>>,
+    mut images: ResMut<Assets<Image>>,
+    // the recovery of each trampled chunk since its mask was updated the last time
+    mut recovering: Local<HashMap<Entity, f32>>,
+) {
+    let interactors = visible_interactors(&interactors);
+    recovering.retain(|entity, _| chunks.contains(*entity));
+    // new masks might already be trampled
+    for entity in changed.iter() {
+        recovering.entry(entity).or_default();
+    }
+    for (entity, mask, aabb, transform, explicit) in chunks.iter() {
+        let Some(image) = images.get(&mask.mask) else {
+            continue;
+        };
+        if image.texture_descriptor.format != TextureFormat::R32Float {
+            warn!("trample masks need to be `R32Float` textures");
+            continue;
+        }
+        if let Some(recovery) = recovering.get_mut(&entity) {
+            *recovery += time.delta_seconds() / mask.recovery_time.max(f32::EPSILON);
+            if *recovery >= RECOVERY_STEP {
+                let recovery = std::mem::take(recovery);
+                let Some(image) = images.get_mut(&mask.mask) else {
+                    continue;
+                };
+                // recovered masks don't need to be updated anymore
+                if !recover_mask(image, recovery) {
+                    recovering.remove(&entity);
+                }
+            }
+        }
+
+        // the positions of explicit grass aren't relative to the corner of the aabb
+        let origin = if explicit.is_some() {
+            Vec3::from(aabb.min()).xz()
+        } else {
+            Vec2::ZERO
+        };
+        let (min, max) = (Vec3::from(aabb.min()), Vec3::from(aabb.max()));
+        let inverse = transform.affine().inverse();
+        // the interactors in the local space of the chunk
+        let stamps: Vec<(Vec2, f32)> = interactors
+            .iter()
+            .filter_map(|(interactor, position)| {
+                let local = inverse.transform_point3(*position);
+                let radius = inverse
+                    .transform_vector3(Vec3::X * interactor.radius)
+                    .length();
+                let inside = local.cmpge(min - radius).all() && local.cmple(max + radius).all();
+                inside.then_some((local.xz(), radius))
+            })
+            .collect();
+        let Some(image) = images.get(&mask.mask) else {
+            continue;
+        };
+        let size = Vec3::from(aabb.half_extents).xz() * 2.;
+        let trampled = stamp_mask(image, &stamps, origin, size);
+        if trampled.is_empty() {
+            continue;
+        }
+        let Some(image) = images.get_mut(&mask.mask) else {
+            continue;
+        };
+        for (index, value) in trampled {
+            // the stamps of several interactors might overlap
+            if value > read_trample(image, index) {
+                image.data[index * 4..index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        recovering.entry(entity).or_default();
+    }
+}
+
+/// The texels of the mask which the stamps trample further, together with their new values.
+///
+/// Only the texels around the stamps are visited.
+/// `origin` is the local x,z position the mask starts at and `size` the area it is stretched over
+fn stamp_mask(
+    image: &Image,
+    stamps: &[(Vec2, f32)],
+    origin: Vec2,
+    size: Vec2,
+) -> Vec<(usize, f32)> {
+    let width = image.texture_descriptor.size.width as usize;
+    let dim = image.size();
+    let mut trampled = Vec::new();
+    for (center, radius) in stamps {
+        // the texels whose centers might lie inside the stamp
+        let texel =
+            |position: Vec2| (position - origin) / size.max(Vec2::splat(f32::EPSILON)) * dim - 0.5;
+        let start = texel(*center - *radius).floor().max(Vec2::ZERO).as_uvec2();
+        let end = (texel(*center + *radius).ceil() + 1.)
+            .min(dim)
+            .max(Vec2::ZERO)
+            .as_uvec2();
+        for y in start.y..end.y {
+            for x in start.x..end.x {
+                let pixel = Vec2::new(x as f32, y as f32);
+                // the center of the texel in the local space of the chunk, like the shader maps it
+                let xz = origin + (pixel + 0.5) / dim * size;
+                let distance = center.distance(xz);
+                if distance >= *radius {
+                    continue;
+                }
+                // the grass below the interactor is trampled completely
+                let value = ((1. - distance / radius) * 2.).min(1f32);
+                let index = y as usize * width + x as usize;
+                if value > read_trample(image, index) {
+                    trampled.push((index, value));
+                }
+            }
+        }
+    }
+    trampled
+}
+/// Lets all texels of the mask recover by the given amount.
+///
+/// Returns whether any of the grass is still trampled
+fn recover_mask(image: &mut Image, recovery: f32) -> bool {
+    let mut trampled = false;
+    for texel in image.data.chunks_exact_mut(4) {
+        let value = f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]);
+        let value = (value - recovery).max(0.);
+        trampled |= value > 0.;
+        texel.copy_from_slice(&value.to_le_bytes());
+    }
+    trampled
+}
+/// Reads how much the grass of a texel of a [`TrampleMask`] is trampled
+fn read_trample(image: &Image, index: usize) -> f32 {
+    let texel = &image.data[index * 4..index * 4 + 4];
+    f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]])
+}
+
+/// The interactors which aren't hidden together with their positions
+fn visible_interactors(
+    interactors: &Query<(
+        &GrassInteractor,
+        &GlobalTransform,
+        Option<&ComputedVisibility>,
+    )>,
+) -> Vec<(GrassInteractor, Vec3)> {
+    interactors
+        .iter()
+        .filter(|(_, _, visibility)| visibility.map_or(true, |v| v.is_visible_in_hierarchy()))
+        .map(|(interactor, transform, _)| (*interactor, transform.translation()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        prelude::*,
+        render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    };
+
+    use super::{recover_mask, stamp_mask};
+
+    /// An untrampled mask
+    fn mask(width: u32, height: u32) -> Image {
+        Image::new_fill(
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &0f32.to_le_bytes(),
+            TextureFormat::R32Float,
+        )
+    }
+    #[test]
+    fn stamps_only_cover_texels_inside() {
+        let image = mask(8, 4);
+        let size = Vec2::new(16., 8.);
+        // explicit chunks start at the minimum of their aabb
+        for origin in [Vec2::ZERO, Vec2::new(-8., -4.)] {
+            let stamps = [
+                (origin + Vec2::new(5., 3.), 2.5),
+                (origin + Vec2::new(15., 1.), 1.),
+            ];
+            let mut trampled = stamp_mask(&image, &stamps, origin, size);
+            trampled.sort_by_key(|(index, _)| *index);
+            // compares against all texels
+            let mut expected = Vec::new();
+            for index in 0..32 {
+                let center =
+                    origin + Vec2::new((index % 8) as f32 * 2. + 1., (index / 8) as f32 * 2. + 1.);
+                for (position, radius) in stamps {
+                    let distance = position.distance(center);
+                    if distance < radius {
+                        expected.push((index, ((1. - distance / radius) * 2.).min(1.)));
+                    }
+                }
+            }
+            assert!(!expected.is_empty());
+            assert_eq!(trampled, expected);
+        }
+        // stamps outside of the mask don't touch it
+        let stamps = [(Vec2::new(-3., 4.), 2.)];
+        assert!(stamp_mask(&image, &stamps, Vec2::ZERO, size).is_empty());
+    }
+    #[test]
+    fn stamps_dont_lower_texels() {
+        let mut image = mask(2, 2);
+        image.data[..4].copy_from_slice(&1f32.to_le_bytes());
+        let stamps = [(Vec2::new(1., 1.), 1.)];
+        // the first texel is already trampled completely
+        assert!(stamp_mask(&image, &stamps, Vec2::ZERO, Vec2::splat(4.)).is_empty());
+    }
+    #[test]
+    fn masks_recover() {
+        let mut image = mask(2, 2);
+        image.data[..4].copy_from_slice(&0.5f32.to_le_bytes());
+        image.data[4..8].copy_from_slice(&0.1f32.to_le_bytes());
+        assert!(recover_mask(&mut image, 0.2));
+        let values: Vec<f32> = image
+            .data
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        assert_eq!(values, [0.3, 0., 0., 0.]);
+        assert!(!recover_mask(&mut image, 0.3));
+    }
+}
@@ -1,6 +1,17 @@
-use bevy::{ecs::prelude::*, prelude::Vec3, render::primitives::Aabb};
+use bevy::{
+    asset::Assets,
+    ecs::prelude::*,
+    prelude::{Image, Vec3},
+    render::primitives::Aabb,
+    time::Time,
+};
 
-use crate::prelude::Grass;
+use crate::{
+    height_map::{to_float_height_texture, HeightMap},
+    prelude::{Grass, WarblerHeight},
+    GrassConfiguration, GrassWindTime,
+};
+#[allow(clippy::type_complexity)]
 pub fn add_aabb_to_explicit(
     mut commands: Commands,
     grasses: Query<(Entity, &Grass), Or<(Changed<Grass>, Changed<Aabb>)>>,
@@ -23,3 +34,38 @@ pub fn add_aabb_to_explicit(
     }
     *added_last_frame = added;
 }
+
+/// Converts height maps and height textures to a format the grass shader can load as float.
+///
+/// See [`to_float_height_texture`] for the converted formats
+pub(crate) fn convert_height_textures(
+    mut images: ResMut<Assets<Image>>,
+    height_maps: Query<&HeightMap>,
+    heights: Query<&WarblerHeight>,
+) {
+    let textures = height_maps
+        .iter()
+        .map(|height_map| &height_map.height_map)
+        .chain(heights.iter().filter_map(|height| match height {
+            WarblerHeight::Texture(texture) => Some(texture),
+            WarblerHeight::Uniform(_) => None,
+        }));
+    for handle in textures {
+        // only access the image mutably if it is converted, since this marks it as modified
+        let Some(converted) = images.get(handle).and_then(to_float_height_texture) else {
+            continue;
+        };
+        if let Some(image) = images.get_mut(handle) {
+            *image = converted;
+        }
+    }
+}
+
+/// Advances the [`GrassWindTime`] by the scaled frame time
+pub(crate) fn update_wind_time(
+    time: Res<Time>,
+    config: Res<GrassConfiguration>,
+    mut wind_time: ResMut<GrassWindTime>,
+) {
+    wind_time.elapsed += time.delta_seconds_f64() * config.wind_model.time_scale as f64;
+}
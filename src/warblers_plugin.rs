@@ -2,38 +2,67 @@ use bevy::{
     app::Plugin,
     asset::{load_internal_asset, Assets, HandleUntyped},
     core_pipeline::core_3d::Opaque3d,
+    pbr::Shadow,
     prelude::*,
     reflect::TypeUuid,
     render::{
         extract_component::ExtractComponentPlugin,
         extract_resource::ExtractResourcePlugin,
         mesh::{Indices, Mesh},
-        render_asset::RenderAssetPlugin,
+        render_asset::{PrepareAssetSet, RenderAssetPlugin},
         render_phase::AddRenderCommand,
         render_resource::{PrimitiveTopology, Shader, SpecializedMeshPipelines},
+        renderer::RenderDevice,
         texture::FallbackImage,
         RenderApp, RenderSet,
     },
+    transform::TransformSystem,
 };
 
 use crate::{
-    dithering::{add_dither_to_density, DitheredBuffer},
+    cutting::{self, CutGrass, GrassCutMask, GrassRegrowth},
+    density_map::GpuDithering,
+    dithering::{
+        add_dither_to_density, gpu_dithering_supported, poll_dithering_tasks,
+        remove_dither_of_removed_density, DitheredBuffer, DitheringGeneration, GrassChunkReady,
+    },
+    grid,
     height_map::HeightMap,
-    prelude::{GrassColor, WarblerHeight},
+    prelude::{
+        BatchedGrass, GpuCulling, GrassCastShadows, GrassColor, GrassReceiveShadows,
+        NoBladeRotation, UnlitGrass, WarblerHeight,
+    },
+    query,
     render::{
         self,
-        cache::{ExplicitGrassCache, UniformBuffer},
+        cache::{
+            BindGroupCache, ExplicitGrassCache, GpuCullingCache, GpuDitherCache, GrassBatchCache,
+            ShadowViewBindGroup, UniformBuffer,
+        },
+        cull_pipeline::{gpu_culling_supported, CullPipeline},
+        dither_pipeline::DitherPipeline,
         extract,
         grass_pipeline::GrassPipeline,
         prepare, queue,
     },
-    update, GrassConfiguration, GrassNoiseTexture,
+    trample::{self, GrassInteractor, GrassInteractors, TrampleMask},
+    update,
+    wind::{self, ActiveWindImpulses, GrassWindSources, WindImpulse, WindZone},
+    GrassConfiguration, GrassLod, GrassNoiseTexture, GrassWindTime,
 };
 
 /// A raw handle which points to the shader used to render the grass.
 pub(crate) const GRASS_SHADER_HANDLE: HandleUntyped =
     HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 2263343952151597127);
 
+/// A raw handle which points to the compute shader used to dither density maps on the gpu.
+pub(crate) const DITHER_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 7318530481639471263);
+
+/// A raw handle which points to the compute shader used to cull blades on the gpu.
+pub(crate) const CULL_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 5412694381960274113);
+
 /// A raw handle to the default mesh used for grass.
 ///
 /// The [`WarblersPlugin`] adds the corresponding mesh to the world.
@@ -54,32 +83,112 @@ impl Plugin for WarblersPlugin {
             "render/assets/grass_shader.wgsl",
             Shader::from_wgsl
         );
+        load_internal_asset!(
+            app,
+            DITHER_SHADER_HANDLE,
+            "render/assets/dither_shader.wgsl",
+            Shader::from_wgsl
+        );
+        load_internal_asset!(
+            app,
+            CULL_SHADER_HANDLE,
+            "render/assets/cull_shader.wgsl",
+            Shader::from_wgsl
+        );
 
         // Load default grass blade mesh
         let mut meshes = app.world.resource_mut::<Assets<Mesh>>();
         meshes.set_untracked(GRASS_MESH_HANDLE, default_grass_mesh());
 
-        app.add_system(add_dither_to_density)
+        app.add_event::<GrassChunkReady>()
+            .add_event::<WindImpulse>()
+            .add_event::<CutGrass>()
+            .add_systems(
+                (
+                    add_dither_to_density,
+                    poll_dithering_tasks,
+                    remove_dither_of_removed_density,
+                )
+                    .chain(),
+            )
             .add_system(update::add_aabb_to_explicit)
+            .add_system(update::convert_height_textures)
+            .add_system(update::update_wind_time)
+            .add_system(wind::update_wind_impulses)
+            .add_system(
+                wind::gather_wind_sources
+                    .after(wind::update_wind_impulses)
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(TransformSystem::TransformPropagate),
+            )
+            .add_systems(
+                (
+                    trample::gather_grass_interactors,
+                    trample::update_trample_masks,
+                )
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(TransformSystem::TransformPropagate),
+            )
+            .add_systems(
+                (cutting::regrow_grass, cutting::cut_grass)
+                    .chain()
+                    .in_base_set(CoreSet::PostUpdate)
+                    .after(TransformSystem::TransformPropagate),
+            )
+            .add_system(grid::split_grass_grids)
+            .add_system(query::update_blade_indices.after(poll_dithering_tasks))
             .add_asset::<DitheredBuffer>()
             .add_plugin(RenderAssetPlugin::<DitheredBuffer>::default());
         // Init resources
         app.init_resource::<GrassConfiguration>()
             .register_type::<GrassConfiguration>()
+            .init_resource::<GrassWindTime>()
+            .register_type::<GrassWindTime>()
+            .register_type::<WindZone>()
+            .init_resource::<ActiveWindImpulses>()
+            .init_resource::<GrassWindSources>()
+            .register_type::<GrassInteractor>()
+            .register_type::<TrampleMask>()
+            .init_resource::<GrassInteractors>()
+            .register_type::<GrassCutMask>()
+            .register_type::<GrassRegrowth>()
+            .register_type::<GpuDithering>()
+            .init_resource::<DitheringGeneration>()
             .init_resource::<GrassNoiseTexture>();
         // Add extraction of the configuration
         app.add_plugin(ExtractResourcePlugin::<GrassConfiguration>::default());
         app.add_plugin(ExtractResourcePlugin::<GrassNoiseTexture>::default());
+        app.add_plugin(ExtractResourcePlugin::<GrassWindTime>::default());
+        app.add_plugin(ExtractResourcePlugin::<GrassWindSources>::default());
+        app.add_plugin(ExtractResourcePlugin::<GrassInteractors>::default());
         app.add_plugin(ExtractComponentPlugin::<HeightMap>::default());
         app.add_plugin(ExtractComponentPlugin::<WarblerHeight>::default());
         app.add_plugin(ExtractComponentPlugin::<GrassColor>::default());
+        app.add_plugin(ExtractComponentPlugin::<NoBladeRotation>::default());
+        app.add_plugin(ExtractComponentPlugin::<UnlitGrass>::default());
+        app.add_plugin(ExtractComponentPlugin::<GrassReceiveShadows>::default());
+        app.add_plugin(ExtractComponentPlugin::<GrassCastShadows>::default());
+        app.add_plugin(ExtractComponentPlugin::<BatchedGrass>::default());
+        app.add_plugin(ExtractComponentPlugin::<GpuCulling>::default());
+        app.add_plugin(ExtractComponentPlugin::<TrampleMask>::default());
+        app.add_plugin(ExtractComponentPlugin::<GrassCutMask>::default());
+        app.add_plugin(ExtractComponentPlugin::<GrassLod>::default());
         // Init render app
         app.sub_app_mut(RenderApp)
             .add_render_command::<Opaque3d, render::GrassDrawCall>()
+            .add_render_command::<Shadow, render::GrassShadowDrawCall>()
+            .add_render_command::<Opaque3d, render::GrassBatchDrawCall>()
+            .add_render_command::<Shadow, render::GrassBatchShadowDrawCall>()
             .init_resource::<FallbackImage>()
             .init_resource::<GrassPipeline>()
             .init_resource::<UniformBuffer>()
+            .init_resource::<ShadowViewBindGroup>()
             .init_resource::<ExplicitGrassCache>()
+            .init_resource::<BindGroupCache<GrassColor>>()
+            .init_resource::<BindGroupCache<WarblerHeight>>()
+            .init_resource::<BindGroupCache<HeightMap>>()
+            .init_resource::<GpuDitherCache>()
+            .init_resource::<GpuCullingCache>()
             .init_resource::<SpecializedMeshPipelines<GrassPipeline>>()
             .add_systems(
                 (
@@ -99,7 +208,55 @@ impl Plugin for WarblersPlugin {
                 )
                     .in_set(RenderSet::Prepare),
             )
-            .add_system(queue::queue_grass_buffers.in_set(RenderSet::Queue));
+            .add_systems(
+                (
+                    queue::queue_grass_buffers,
+                    queue::queue_grass_shadows,
+                    queue::queue_shadow_view_bind_group,
+                )
+                    .in_set(RenderSet::Queue),
+            );
+        // Chunks with the GpuDithering component fall back to the cpu if compute shaders aren't available
+        let gpu_dithering = app
+            .world
+            .get_resource::<RenderDevice>()
+            .is_some_and(gpu_dithering_supported);
+        // batches store the data of their chunks in storage buffers, which aren't available on WebGL2
+        let batching = app
+            .world
+            .get_resource::<RenderDevice>()
+            .is_some_and(|device| device.limits().max_storage_buffers_per_shader_stage > 0);
+        if batching {
+            app.sub_app_mut(RenderApp)
+                .init_resource::<GrassBatchCache>()
+                .add_system(extract::extract_batched_grass.in_schedule(ExtractSchedule))
+                .add_system(
+                    prepare::prepare_grass_batches
+                        .in_set(RenderSet::Prepare)
+                        .after(PrepareAssetSet::AssetPrepare),
+                );
+        }
+        if gpu_dithering {
+            app.sub_app_mut(RenderApp)
+                .init_resource::<DitherPipeline>()
+                .add_system(extract::extract_gpu_dithered_grass.in_schedule(ExtractSchedule))
+                .add_system(prepare::prepare_gpu_dithering.in_set(RenderSet::Prepare));
+        }
+        // Chunks with the GpuCulling component are drawn completely if compute shaders aren't available
+        if app
+            .world
+            .get_resource::<RenderDevice>()
+            .is_some_and(gpu_culling_supported)
+        {
+            app.sub_app_mut(RenderApp)
+                .init_resource::<CullPipeline>()
+                .add_system(
+                    prepare::prepare_gpu_culling
+                        .in_set(RenderSet::Prepare)
+                        .after(PrepareAssetSet::AssetPrepare)
+                        .after(prepare::prepare_gpu_dithering),
+                );
+        }
     }
 }
 
@@ -0,0 +1,194 @@
+//! Local wind sources pushing the grass around them
+//!
+//! Besides the global wind of the [`GrassConfiguration`], grass reacts to
+//! entities with a [`WindZone`] and to [`WindImpulse`] events.
+//! Both are gathered each frame by the [`WarblersPlugin`](crate::warblers_plugin::WarblersPlugin)
+//! and added to the wind in the grass shader.
+//!
+//! # Example
+//! ```rust
+//! use bevy::prelude::*;
+//! use warbler_grass::wind::{WindImpulse, WindZone};
+//!
+//! fn spawn_fan(mut commands: Commands) {
+//!     commands.spawn((
+//!         WindZone::Directional {
+//!             half_extents: Vec3::new(5., 2., 5.),
+//!             wind: Vec2::new(3., 0.),
+//!         },
+//!         SpatialBundle::from_transform(Transform::from_xyz(10., 0., 10.)),
+//!     ));
+//! }
+//! fn explode(mut impulses: EventWriter<WindImpulse>) {
+//!     impulses.send(WindImpulse {
+//!         position: Vec3::new(20., 0., 20.),
+//!         radius: 15.,
+//!         strength: 4.,
+//!         duration: 1.5,
+//!     });
+//! }
+//! ```
+use bevy::{prelude::*, render::extract_resource::ExtractResource};
+
+use crate::GrassConfiguration;
+
+/// The maximal amount of wind sources affecting the grass at once.
+///
+/// Further [`WindZone`]s and [`WindImpulse`]s are ignored until others are removed or expire
+pub const MAX_WIND_SOURCES: usize = 64;
+
+/// A local source of wind, placed at the [`GlobalTransform`] of its entity.
+///
+/// Hidden zones don't affect the grass, so a zone can be toggled using its [`Visibility`].
+///
+/// The strength of all zones is given in the same units as the `wind` of the [`GrassConfiguration`].
+#[derive(Component, Clone, Copy, Debug, PartialEq, Reflect, FromReflect)]
+pub enum WindZone {
+    /// Pushes the grass inside an axis aligned box in one direction, like a fan.
+    ///
+    /// The wind fades out towards the borders of the box
+    Directional {
+        /// Half the size of the box around the translation of the entity
+        half_extents: Vec3,
+        /// The direction and strength of the wind on the x,z plane
+        wind: Vec2,
+    },
+    /// Pushes the grass inside a sphere away from its center, like the downwash of a helicopter.
+    ///
+    /// A negative strength pulls the grass towards the center.
+    /// The wind fades out towards the border of the sphere
+    Radial { radius: f32, strength: f32 },
+    /// Swirls the grass inside a sphere around its center.
+    ///
+    /// A positive strength swirls counterclockwise when seen from above.
+    /// The wind fades out towards the border of the sphere
+    Vortex { radius: f32, strength: f32 },
+}
+
+/// An event sending a short burst of wind through the grass, like the shockwave of an explosion.
+///
+/// The grass inside the sphere is pushed away from its center.
+/// The push weakens over the `duration` until the impulse is removed.
+/// Impulses age with the [`GrassWindTime`](crate::GrassWindTime), so they slow down with the wind
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindImpulse {
+    /// The center of the impulse in world space
+    pub position: Vec3,
+    /// The radius of the sphere affected by the impulse
+    pub radius: f32,
+    /// The initial strength of the push
+    pub strength: f32,
+    /// The time in seconds until the impulse vanished
+    pub duration: f32,
+}
+
+/// The [`WindImpulse`]s which haven't expired yet, together with their age in seconds
+#[derive(Resource, Clone, Debug, Default)]
+pub(crate) struct ActiveWindImpulses(Vec<(WindImpulse, f32)>);
+
+/// The shape of a wind source in the grass shader
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum WindSourceKind {
+    Directional = 0,
+    Radial = 1,
+    Vortex = 2,
+}
+
+/// A [`WindZone`] or [`WindImpulse`] in world space, as used by the grass shader
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WindSource {
+    pub kind: WindSourceKind,
+    pub center: Vec3,
+    /// The half extents of directional sources, the x value is the radius of the other sources
+    pub extents: Vec3,
+    /// The direction and strength of directional sources, the x value is the strength of the other sources
+    pub wind: Vec2,
+}
+impl WindSource {
+    /// The maximal distance the source can push a blade
+    fn max_strength(&self) -> f32 {
+        match self.kind {
+            WindSourceKind::Directional => self.wind.length(),
+            WindSourceKind::Radial | WindSourceKind::Vortex => self.wind.x.abs(),
+        }
+    }
+}
+
+/// All wind sources of the current frame, which are extracted to the render world
+#[derive(Resource, Clone, Debug, Default, ExtractResource)]
+pub(crate) struct GrassWindSources {
+    pub sources: Vec<WindSource>,
+    /// The maximal distance any of the sources can push a blade, used to cull blades
+    pub max_strength: f32,
+}
+
+/// Adds new [`WindImpulse`]s and removes the expired ones
+pub(crate) fn update_wind_impulses(
+    time: Res<Time>,
+    config: Res<GrassConfiguration>,
+    mut events: EventReader<WindImpulse>,
+    mut impulses: ResMut<ActiveWindImpulses>,
+) {
+    let delta = time.delta_seconds() * config.wind_model.time_scale;
+    for (_, age) in impulses.0.iter_mut() {
+        *age += delta;
+    }
+    impulses.0.retain(|(impulse, age)| *age < impulse.duration);
+    impulses
+        .0
+        .extend(events.iter().map(|impulse| (*impulse, 0.)));
+}
+
+/// Collects the [`WindZone`]s and active [`WindImpulse`]s into the [`GrassWindSources`]
+pub(crate) fn gather_wind_sources(
+    zones: Query<(&WindZone, &GlobalTransform, Option<&ComputedVisibility>)>,
+    impulses: Res<ActiveWindImpulses>,
+    mut wind_sources: ResMut<GrassWindSources>,
+) {
+    let zones = zones
+        .iter()
+        // hidden zones don't push the grass
+        .filter(|(_, _, visibility)| visibility.map_or(true, |v| v.is_visible_in_hierarchy()))
+        .map(|(zone, transform, _)| {
+            let center = transform.translation();
+            match *zone {
+                WindZone::Directional { half_extents, wind } => WindSource {
+                    kind: WindSourceKind::Directional,
+                    center,
+                    extents: half_extents,
+                    wind,
+                },
+                WindZone::Radial { radius, strength } => WindSource {
+                    kind: WindSourceKind::Radial,
+                    center,
+                    extents: Vec3::new(radius, 0., 0.),
+                    wind: Vec2::new(strength, 0.),
+                },
+                WindZone::Vortex { radius, strength } => WindSource {
+                    kind: WindSourceKind::Vortex,
+                    center,
+                    extents: Vec3::new(radius, 0., 0.),
+                    wind: Vec2::new(strength, 0.),
+                },
+            }
+        });
+    let impulses = impulses.0.iter().map(|(impulse, age)| {
+        // the impulse weakens quickly at first and slowly fades out
+        let remaining = (1. - age / impulse.duration).clamp(0., 1.);
+        WindSource {
+            kind: WindSourceKind::Radial,
+            center: impulse.position,
+            extents: Vec3::new(impulse.radius, 0., 0.),
+            wind: Vec2::new(impulse.strength * remaining * remaining, 0.),
+        }
+    });
+    wind_sources.sources.clear();
+    wind_sources
+        .sources
+        .extend(impulses.chain(zones).take(MAX_WIND_SOURCES));
+    wind_sources.max_strength = wind_sources
+        .sources
+        .iter()
+        .map(WindSource::max_strength)
+        .sum();
+}